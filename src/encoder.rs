@@ -0,0 +1,732 @@
+use crate::parser::FunctionBody;
+use crate::primitives::{
+    ElemItems, ExternalKind, GlobalArg, ImportKind, MemArg, Mutability, Operator, ResizableLimit,
+    Section, SectionCodeEntity, SectionDataEntity, SectionElemEntity, SectionExportEntity,
+    SectionFuncEntity, SectionGlobalEntity, SectionImportEntity, SectionMemoryEntity,
+    SectionTableEntity, SectionTypeEntity, SegmentMode, Type,
+};
+
+const WASM_MAGIC_NUMBER: u32 = 0x6D73_6100;
+const WASM_SUPPORTED_VERSION: u32 = 0x01;
+
+// Encodes a module made of `sections`, in the given order, to the binary format.
+pub fn encode(sections: &[Section]) -> Vec<u8> {
+    let mut buf = vec![];
+    encode_head(&mut buf);
+
+    for section in sections {
+        encode_section(&mut buf, section);
+    }
+
+    buf
+}
+
+pub(crate) fn encode_head(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&WASM_MAGIC_NUMBER.to_le_bytes());
+    buf.extend_from_slice(&WASM_SUPPORTED_VERSION.to_le_bytes());
+}
+
+pub fn encode_section(buf: &mut Vec<u8>, section: &Section) {
+    let width = section.size_width();
+    match section {
+        Section::Custom { name, payload, .. } => encode_custom_section(buf, name, payload, width),
+        Section::Type { entities, .. } => {
+            encode_entities(buf, 0x01, entities, encode_section_type_entity, width)
+        }
+        Section::Import { entities, .. } => {
+            encode_entities(buf, 0x02, entities, encode_section_import_entity, width)
+        }
+        Section::Function { entities, .. } => {
+            encode_entities(buf, 0x03, entities, encode_section_func_entity, width)
+        }
+        Section::Table { entities, .. } => {
+            encode_entities(buf, 0x04, entities, encode_section_table_entity, width)
+        }
+        Section::Memory { entities, .. } => {
+            encode_entities(buf, 0x05, entities, encode_section_memory_entity, width)
+        }
+        Section::Global { entities, .. } => {
+            encode_entities(buf, 0x06, entities, encode_section_global_entity, width)
+        }
+        Section::Export { entities, .. } => {
+            encode_entities(buf, 0x07, entities, encode_section_export_entity, width)
+        }
+        Section::Start {
+            signature_index, ..
+        } => encode_start_section(buf, *signature_index, width),
+        Section::Element { entities, .. } => {
+            encode_entities(buf, 0x09, entities, encode_section_elem_entity, width)
+        }
+        Section::Code { entities, .. } => {
+            encode_entities(buf, 0x0A, entities, encode_section_code_entity, width)
+        }
+        Section::Data { entities, .. } => {
+            encode_entities(buf, 0x0B, entities, encode_section_data_entity, width)
+        }
+        Section::DataCount { count, .. } => encode_data_count_section(buf, *count, width),
+    }
+}
+
+pub(crate) fn encode_custom_section(buf: &mut Vec<u8>, name: &str, data: &[u8], width: u8) {
+    let mut payload = vec![];
+    encode_name(&mut payload, name);
+    payload.extend_from_slice(data);
+    encode_payload(buf, 0x00, &payload, width);
+}
+
+pub(crate) fn encode_start_section(buf: &mut Vec<u8>, signature_index: u32, width: u8) {
+    let mut payload = vec![];
+    write_var_u32(&mut payload, signature_index);
+    encode_payload(buf, 0x08, &payload, width);
+}
+
+pub(crate) fn encode_data_count_section(buf: &mut Vec<u8>, count: u32, width: u8) {
+    let mut payload = vec![];
+    write_var_u32(&mut payload, count);
+    encode_payload(buf, 0x0C, &payload, width);
+}
+
+// Writes a section of `entities` whose size takes at least `width` bytes.
+pub(crate) fn encode_entities<T>(
+    buf: &mut Vec<u8>,
+    section_code: u8,
+    entities: &[T],
+    f: fn(&mut Vec<u8>, &T),
+    width: u8,
+) {
+    let mut payload = vec![];
+    encode_vec(&mut payload, entities, f);
+    encode_payload(buf, section_code, &payload, width);
+}
+
+fn encode_payload(buf: &mut Vec<u8>, section_code: u8, payload: &[u8], width: u8) {
+    buf.push(section_code);
+    write_padded_var_u32(buf, payload.len() as u32, width);
+    buf.extend_from_slice(payload);
+}
+
+pub(crate) fn encode_section_type_entity(buf: &mut Vec<u8>, entity: &SectionTypeEntity) {
+    encode_type(buf, entity.form);
+    encode_vec(buf, &entity.params, |buf, t| encode_type(buf, *t));
+    encode_vec(buf, &entity.returns, |buf, t| encode_type(buf, *t));
+}
+
+pub(crate) fn encode_section_import_entity(buf: &mut Vec<u8>, entity: &SectionImportEntity) {
+    encode_name(buf, &entity.mod_name);
+    encode_name(buf, &entity.field_name);
+    encode_import_kind(buf, &entity.kind);
+}
+
+pub(crate) fn encode_section_func_entity(buf: &mut Vec<u8>, entity: &SectionFuncEntity) {
+    write_var_u32(buf, entity.signature_index as u32);
+}
+
+pub(crate) fn encode_section_table_entity(buf: &mut Vec<u8>, entity: &SectionTableEntity) {
+    encode_type(buf, entity.elem_type);
+    encode_resizable_limit(buf, &entity.limit);
+}
+
+pub(crate) fn encode_section_memory_entity(buf: &mut Vec<u8>, entity: &SectionMemoryEntity) {
+    encode_resizable_limit(buf, &entity.limit);
+}
+
+pub(crate) fn encode_section_global_entity(buf: &mut Vec<u8>, entity: &SectionGlobalEntity) {
+    encode_global_arg(buf, &entity.global_arg);
+    encode_expr(buf, &entity.expr);
+}
+
+pub(crate) fn encode_section_export_entity(buf: &mut Vec<u8>, entity: &SectionExportEntity) {
+    encode_name(buf, &entity.name);
+    encode_external_kind(buf, entity.kind);
+}
+
+// Segments use the shortest of the flag encodings that can represent them.
+pub(crate) fn encode_section_elem_entity(buf: &mut Vec<u8>, entity: &SectionElemEntity) {
+    let expressions = match entity.elems {
+        ElemItems::Functions(_) => 0,
+        ElemItems::Expressions(_) => 4,
+    };
+    // flags 0 and 4 imply functions in table 0
+    let implicit = entity.table_index == 0 && entity.elem_type == Type::AnyFunc;
+    let flags = expressions
+        | match entity.mode {
+            SegmentMode::Active if implicit => 0,
+            SegmentMode::Passive => 1,
+            SegmentMode::Active => 2,
+            SegmentMode::Declared => 3,
+        };
+    write_var_u32(buf, flags);
+    if flags & 3 == 2 {
+        write_var_u32(buf, entity.table_index);
+    }
+    if entity.mode == SegmentMode::Active {
+        encode_expr(buf, &entity.offset);
+    }
+    if flags & 3 != 0 {
+        match entity.elems {
+            ElemItems::Functions(_) => buf.push(0x00),
+            ElemItems::Expressions(_) => encode_type(buf, entity.elem_type),
+        }
+    }
+    match &entity.elems {
+        ElemItems::Functions(functions) => {
+            encode_vec(buf, functions, |buf, index| write_var_u32(buf, *index))
+        }
+        ElemItems::Expressions(exprs) => encode_vec(buf, exprs, |buf, expr| encode_expr(buf, expr)),
+    }
+}
+
+// A body read from the binary format is written as it was while it holds the
+// same code, keeping the padded immediates of object files, or if it was never
+// decoded.
+pub(crate) fn encode_section_code_entity(buf: &mut Vec<u8>, entity: &SectionCodeEntity) {
+    let body = encode_function_body(&entity.locals, &entity.code);
+    if let Some(original) = &entity.body {
+        // a body that was never decoded is kept, and a decoded one while unchanged
+        if entity.lazy || decode_function_body(original).as_ref() == Some(&body) {
+            write_padded_var_u32(buf, original.bytes().len() as u32, original.size_width());
+            buf.extend_from_slice(original.bytes());
+            return;
+        }
+    }
+
+    encode_bytes(buf, &body);
+}
+
+fn encode_function_body(locals: &[Type], code: &[Operator]) -> Vec<u8> {
+    let mut body = vec![];
+
+    // consecutive locals of the same type share one declaration
+    let mut decls: Vec<(u32, Type)> = vec![];
+    for t in locals {
+        match decls.last_mut() {
+            Some((count, last)) if last == t => *count += 1,
+            _ => decls.push((1, *t)),
+        }
+    }
+    encode_vec(&mut body, &decls, |buf, (count, t)| {
+        write_var_u32(buf, *count);
+        encode_type(buf, *t);
+    });
+    encode_expr(&mut body, code);
+
+    body
+}
+
+// The canonical encoding of `body`, `None` if it does not decode.
+fn decode_function_body(body: &FunctionBody) -> Option<Vec<u8>> {
+    let mut locals = vec![];
+    let mut locals_reader = body.locals_reader().ok()?;
+    for entry in &mut locals_reader {
+        let (count, t) = entry.ok()?;
+        locals.extend((0..count).map(|_| t));
+    }
+    let code = locals_reader
+        .into_operators_reader()
+        .ok()?
+        .map(|operator| operator.map(|(operator, _)| operator))
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    Some(encode_function_body(&locals, &code))
+}
+
+pub(crate) fn encode_section_data_entity(buf: &mut Vec<u8>, entity: &SectionDataEntity) {
+    match (entity.mode, entity.memid) {
+        (SegmentMode::Active, 0) => {
+            write_var_u32(buf, 0);
+            encode_expr(buf, &entity.expr);
+        }
+        (SegmentMode::Active, memid) => {
+            write_var_u32(buf, 2);
+            write_var_u32(buf, memid);
+            encode_expr(buf, &entity.expr);
+        }
+        // data segments are never declared
+        _ => write_var_u32(buf, 1),
+    }
+    encode_bytes(buf, &entity.data);
+}
+
+pub fn encode_type(buf: &mut Vec<u8>, t: Type) {
+    let type_code = match t {
+        Type::I32 => -0x01,
+        Type::I64 => -0x02,
+        Type::F32 => -0x03,
+        Type::F64 => -0x04,
+        Type::V128 => -0x05,
+
+        Type::AnyFunc => -0x10,
+        Type::AnyRef => -0x11,
+        Type::Func => -0x20,
+        Type::EmptyBlockType => -0x40,
+    };
+    write_var_i32(buf, type_code);
+}
+
+pub fn encode_resizable_limit(buf: &mut Vec<u8>, limit: &ResizableLimit) {
+    match limit.max {
+        None => {
+            buf.push(0x00);
+            write_var_u32(buf, limit.initial);
+        }
+        Some(max) => {
+            buf.push(0x01);
+            write_var_u32(buf, limit.initial);
+            write_var_u32(buf, max);
+        }
+    }
+}
+
+fn encode_external_kind(buf: &mut Vec<u8>, kind: ExternalKind) {
+    let (kind_code, index) = match kind {
+        ExternalKind::Func { signature_index } => (0x00, signature_index),
+        ExternalKind::Table { table_index } => (0x01, table_index),
+        ExternalKind::Memory { mem_index } => (0x02, mem_index),
+        ExternalKind::Global { global_index } => (0x03, global_index),
+    };
+    buf.push(kind_code);
+    write_var_u32(buf, index);
+}
+
+fn encode_import_kind(buf: &mut Vec<u8>, kind: &ImportKind) {
+    match kind {
+        ImportKind::Func { signature_index } => {
+            buf.push(0x00);
+            write_var_u32(buf, *signature_index);
+        }
+        ImportKind::Table { elem_type, limit } => {
+            buf.push(0x01);
+            encode_type(buf, *elem_type);
+            encode_resizable_limit(buf, limit);
+        }
+        ImportKind::Memory { limit } => {
+            buf.push(0x02);
+            encode_resizable_limit(buf, limit);
+        }
+        ImportKind::Global { global_arg } => {
+            buf.push(0x03);
+            encode_global_arg(buf, global_arg);
+        }
+    }
+}
+
+pub fn encode_global_arg(buf: &mut Vec<u8>, global_arg: &GlobalArg) {
+    encode_type(buf, global_arg.content_type);
+    buf.push(match global_arg.mutability {
+        Mutability::Const => 0x00,
+        Mutability::Var => 0x01,
+    });
+}
+
+pub fn encode_memarg(buf: &mut Vec<u8>, memarg: &MemArg) {
+    write_var_u32(buf, memarg.align);
+    write_var_u32(buf, memarg.offset);
+}
+
+fn encode_expr(buf: &mut Vec<u8>, expr: &[Operator]) {
+    for operator in expr {
+        encode_operator(buf, operator);
+    }
+}
+
+fn encode_name(buf: &mut Vec<u8>, name: &str) {
+    encode_bytes(buf, name.as_bytes());
+}
+
+fn encode_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    write_var_u32(buf, bytes.len() as u32);
+    buf.extend_from_slice(bytes);
+}
+
+fn encode_vec<T, F: Fn(&mut Vec<u8>, &T)>(buf: &mut Vec<u8>, items: &[T], f: F) {
+    write_var_u32(buf, items.len() as u32);
+    for item in items {
+        f(buf, item);
+    }
+}
+
+pub fn encode_operator(buf: &mut Vec<u8>, operator: &Operator) {
+    match operator {
+        Operator::Unreachable => buf.push(0x00),
+        Operator::Nop => buf.push(0x01),
+        Operator::Block { t } => {
+            buf.push(0x02);
+            encode_type(buf, *t);
+        }
+        Operator::Loop { t } => {
+            buf.push(0x03);
+            encode_type(buf, *t);
+        }
+        Operator::If { t } => {
+            buf.push(0x04);
+            encode_type(buf, *t);
+        }
+        Operator::Else => buf.push(0x05),
+        Operator::End => buf.push(0x0B),
+        Operator::Br { relative_depth } => {
+            buf.push(0x0C);
+            write_var_u32(buf, *relative_depth);
+        }
+        Operator::BrIf { relative_depth } => {
+            buf.push(0x0D);
+            write_var_u32(buf, *relative_depth);
+        }
+        Operator::BrTable {
+            target_table,
+            default_target,
+        } => {
+            buf.push(0x0E);
+            encode_vec(buf, target_table, |buf, depth| write_var_u32(buf, *depth));
+            write_var_u32(buf, *default_target);
+        }
+        Operator::Return => buf.push(0x0F),
+        Operator::Call { function_index } => {
+            buf.push(0x10);
+            write_var_u32(buf, *function_index);
+        }
+        Operator::CallIndirect {
+            type_index,
+            table_index,
+        } => {
+            buf.push(0x11);
+            write_var_u32(buf, *type_index);
+            write_var_u32(buf, *table_index);
+        }
+
+        Operator::Drop => buf.push(0x1A),
+        Operator::Select => buf.push(0x1B),
+        Operator::TypedSelect { types } => {
+            buf.push(0x1C);
+            encode_vec(buf, types, |buf, t| encode_type(buf, *t));
+        }
+
+        Operator::LocalGet { local_index } => {
+            buf.push(0x20);
+            write_var_u32(buf, *local_index);
+        }
+        Operator::LocalSet { local_index } => {
+            buf.push(0x21);
+            write_var_u32(buf, *local_index);
+        }
+        Operator::LocalTee { local_index } => {
+            buf.push(0x22);
+            write_var_u32(buf, *local_index);
+        }
+        Operator::GlobalGet { global_index } => {
+            buf.push(0x23);
+            write_var_u32(buf, *global_index);
+        }
+        Operator::GlobalSet { global_index } => {
+            buf.push(0x24);
+            write_var_u32(buf, *global_index);
+        }
+        Operator::TableGet { table_index } => {
+            buf.push(0x25);
+            write_var_u32(buf, *table_index);
+        }
+        Operator::TableSet { table_index } => {
+            buf.push(0x26);
+            write_var_u32(buf, *table_index);
+        }
+
+        Operator::I32Load { memarg } => encode_memory_operator(buf, 0x28, memarg),
+        Operator::I64Load { memarg } => encode_memory_operator(buf, 0x29, memarg),
+        Operator::F32Load { memarg } => encode_memory_operator(buf, 0x2A, memarg),
+        Operator::F64Load { memarg } => encode_memory_operator(buf, 0x2B, memarg),
+        Operator::I32Load8s { memarg } => encode_memory_operator(buf, 0x2C, memarg),
+        Operator::I32Load8u { memarg } => encode_memory_operator(buf, 0x2D, memarg),
+        Operator::I32Load16s { memarg } => encode_memory_operator(buf, 0x2E, memarg),
+        Operator::I32Load16u { memarg } => encode_memory_operator(buf, 0x2F, memarg),
+        Operator::I64Load8s { memarg } => encode_memory_operator(buf, 0x30, memarg),
+        Operator::I64Load8u { memarg } => encode_memory_operator(buf, 0x31, memarg),
+        Operator::I64Load16s { memarg } => encode_memory_operator(buf, 0x32, memarg),
+        Operator::I64Load16u { memarg } => encode_memory_operator(buf, 0x33, memarg),
+        Operator::I64Load32s { memarg } => encode_memory_operator(buf, 0x34, memarg),
+        Operator::I64Load32u { memarg } => encode_memory_operator(buf, 0x35, memarg),
+        Operator::I32Store { memarg } => encode_memory_operator(buf, 0x36, memarg),
+        Operator::I64Store { memarg } => encode_memory_operator(buf, 0x37, memarg),
+        Operator::F32Store { memarg } => encode_memory_operator(buf, 0x38, memarg),
+        Operator::F64Store { memarg } => encode_memory_operator(buf, 0x39, memarg),
+        Operator::I32Store8 { memarg } => encode_memory_operator(buf, 0x3A, memarg),
+        Operator::I32Store16 { memarg } => encode_memory_operator(buf, 0x3B, memarg),
+        Operator::I64Store8 { memarg } => encode_memory_operator(buf, 0x3C, memarg),
+        Operator::I64Store16 { memarg } => encode_memory_operator(buf, 0x3D, memarg),
+        Operator::I64Store32 { memarg } => encode_memory_operator(buf, 0x3E, memarg),
+        Operator::MemorySize { size } => {
+            buf.push(0x3F);
+            write_var_u32(buf, *size);
+        }
+        Operator::MemoryGrow { grow } => {
+            buf.push(0x40);
+            write_var_u32(buf, *grow);
+        }
+
+        Operator::I32Const { val } => {
+            buf.push(0x41);
+            write_var_i32(buf, *val);
+        }
+        Operator::I64Const { val } => {
+            buf.push(0x42);
+            write_var_i64(buf, *val);
+        }
+        Operator::F32Const { val } => {
+            buf.push(0x43);
+            buf.extend_from_slice(&val.to_le_bytes());
+        }
+        Operator::F64Const { val } => {
+            buf.push(0x44);
+            buf.extend_from_slice(&val.to_le_bytes());
+        }
+
+        Operator::I32Eqz => buf.push(0x45),
+        Operator::I32Eq => buf.push(0x46),
+        Operator::I32Ne => buf.push(0x47),
+        Operator::I32LtS => buf.push(0x48),
+        Operator::I32LtU => buf.push(0x49),
+        Operator::I32GtS => buf.push(0x4A),
+        Operator::I32GtU => buf.push(0x4B),
+        Operator::I32LeS => buf.push(0x4C),
+        Operator::I32LeU => buf.push(0x4D),
+        Operator::I32GeS => buf.push(0x4E),
+        Operator::I32GeU => buf.push(0x4F),
+
+        Operator::I64Eqz => buf.push(0x50),
+        Operator::I64Eq => buf.push(0x51),
+        Operator::I64Ne => buf.push(0x52),
+        Operator::I64LtS => buf.push(0x53),
+        Operator::I64LtU => buf.push(0x54),
+        Operator::I64GtS => buf.push(0x55),
+        Operator::I64GtU => buf.push(0x56),
+        Operator::I64LeS => buf.push(0x57),
+        Operator::I64LeU => buf.push(0x58),
+        Operator::I64GeS => buf.push(0x59),
+        Operator::I64GeU => buf.push(0x5A),
+
+        Operator::F32Eq => buf.push(0x5B),
+        Operator::F32Ne => buf.push(0x5C),
+        Operator::F32Lt => buf.push(0x5D),
+        Operator::F32Gt => buf.push(0x5E),
+        Operator::F32Le => buf.push(0x5F),
+        Operator::F32Ge => buf.push(0x60),
+
+        Operator::F64Eq => buf.push(0x61),
+        Operator::F64Ne => buf.push(0x62),
+        Operator::F64Lt => buf.push(0x63),
+        Operator::F64Gt => buf.push(0x64),
+        Operator::F64Le => buf.push(0x65),
+        Operator::F64Ge => buf.push(0x66),
+
+        Operator::I32Clz => buf.push(0x67),
+        Operator::I32Ctz => buf.push(0x68),
+        Operator::I32Popcnt => buf.push(0x69),
+        Operator::I32Add => buf.push(0x6A),
+        Operator::I32Sub => buf.push(0x6B),
+        Operator::I32Mul => buf.push(0x6C),
+        Operator::I32DivS => buf.push(0x6D),
+        Operator::I32DivU => buf.push(0x6E),
+        Operator::I32RemS => buf.push(0x6F),
+        Operator::I32RemU => buf.push(0x70),
+        Operator::I32And => buf.push(0x71),
+        Operator::I32Or => buf.push(0x72),
+        Operator::I32Xor => buf.push(0x73),
+        Operator::I32Shl => buf.push(0x74),
+        Operator::I32ShrS => buf.push(0x75),
+        Operator::I32ShrU => buf.push(0x76),
+        Operator::I32Rotl => buf.push(0x77),
+        Operator::I32Rotr => buf.push(0x78),
+
+        Operator::I64Clz => buf.push(0x79),
+        Operator::I64Ctz => buf.push(0x7A),
+        Operator::I64Popcnt => buf.push(0x7B),
+        Operator::I64Add => buf.push(0x7C),
+        Operator::I64Sub => buf.push(0x7D),
+        Operator::I64Mul => buf.push(0x7E),
+        Operator::I64DivS => buf.push(0x7F),
+        Operator::I64DivU => buf.push(0x80),
+        Operator::I64RemS => buf.push(0x81),
+        Operator::I64RemU => buf.push(0x82),
+        Operator::I64And => buf.push(0x83),
+        Operator::I64Or => buf.push(0x84),
+        Operator::I64Xor => buf.push(0x85),
+        Operator::I64Shl => buf.push(0x86),
+        Operator::I64ShrS => buf.push(0x87),
+        Operator::I64ShrU => buf.push(0x88),
+        Operator::I64Rotl => buf.push(0x89),
+        Operator::I64Rotr => buf.push(0x8A),
+
+        Operator::F32Abs => buf.push(0x8B),
+        Operator::F32Neg => buf.push(0x8C),
+        Operator::F32Ceil => buf.push(0x8D),
+        Operator::F32Floor => buf.push(0x8E),
+        Operator::F32Trunc => buf.push(0x8F),
+        Operator::F32Nearest => buf.push(0x90),
+        Operator::F32Sqrt => buf.push(0x91),
+        Operator::F32Add => buf.push(0x92),
+        Operator::F32Sub => buf.push(0x93),
+        Operator::F32Mul => buf.push(0x94),
+        Operator::F32Div => buf.push(0x95),
+        Operator::F32Min => buf.push(0x96),
+        Operator::F32Max => buf.push(0x97),
+        Operator::F32Copysign => buf.push(0x98),
+
+        Operator::F64Abs => buf.push(0x99),
+        Operator::F64Neg => buf.push(0x9A),
+        Operator::F64Ceil => buf.push(0x9B),
+        Operator::F64Floor => buf.push(0x9C),
+        Operator::F64Trunc => buf.push(0x9D),
+        Operator::F64Nearest => buf.push(0x9E),
+        Operator::F64Sqrt => buf.push(0x9F),
+        Operator::F64Add => buf.push(0xA0),
+        Operator::F64Sub => buf.push(0xA1),
+        Operator::F64Mul => buf.push(0xA2),
+        Operator::F64Div => buf.push(0xA3),
+        Operator::F64Min => buf.push(0xA4),
+        Operator::F64Max => buf.push(0xA5),
+        Operator::F64Copysign => buf.push(0xA6),
+
+        Operator::I32WrapI64 => buf.push(0xA7),
+        Operator::I32TruncSF32 => buf.push(0xA8),
+        Operator::I32TruncUF32 => buf.push(0xA9),
+        Operator::I32TruncSF64 => buf.push(0xAA),
+        Operator::I32TruncUF64 => buf.push(0xAB),
+        Operator::I64ExtendSI32 => buf.push(0xAC),
+        Operator::I64ExtendUI32 => buf.push(0xAD),
+        Operator::I64TruncSF32 => buf.push(0xAE),
+        Operator::I64TruncUF32 => buf.push(0xAF),
+        Operator::I64TruncSF64 => buf.push(0xB0),
+        Operator::I64TruncUF64 => buf.push(0xB1),
+        Operator::F32ConvertSI32 => buf.push(0xB2),
+        Operator::F32ConvertUI32 => buf.push(0xB3),
+        Operator::F32ConvertSI64 => buf.push(0xB4),
+        Operator::F32ConvertUI64 => buf.push(0xB5),
+        Operator::F32DemoteF64 => buf.push(0xB6),
+        Operator::F64ConvertSI32 => buf.push(0xB7),
+        Operator::F64ConvertUI32 => buf.push(0xB8),
+        Operator::F64ConvertSI64 => buf.push(0xB9),
+        Operator::F64ConvertUI64 => buf.push(0xBA),
+        Operator::F64PromoteF32 => buf.push(0xBB),
+        Operator::I32ReinterpretF32 => buf.push(0xBC),
+        Operator::I64ReinterpretF64 => buf.push(0xBD),
+        Operator::F32ReinterpretI32 => buf.push(0xBE),
+        Operator::F64ReinterpretI64 => buf.push(0xBF),
+        Operator::I32Extend8S => buf.push(0xC0),
+        Operator::I32Extend16S => buf.push(0xC1),
+        Operator::I64Extend8S => buf.push(0xC2),
+        Operator::I64Extend16S => buf.push(0xC3),
+        Operator::I64Extend32S => buf.push(0xC4),
+        Operator::RefNull { t } => {
+            buf.push(0xD0);
+            encode_type(buf, *t);
+        }
+        Operator::RefIsNull => buf.push(0xD1),
+        Operator::RefFunc { function_index } => {
+            buf.push(0xD2);
+            write_var_u32(buf, *function_index);
+        }
+        Operator::I32TruncSatSF32 => encode_prefixed_operator(buf, 0xFC, 0),
+        Operator::I32TruncSatUF32 => encode_prefixed_operator(buf, 0xFC, 1),
+        Operator::I32TruncSatSF64 => encode_prefixed_operator(buf, 0xFC, 2),
+        Operator::I32TruncSatUF64 => encode_prefixed_operator(buf, 0xFC, 3),
+        Operator::I64TruncSatSF32 => encode_prefixed_operator(buf, 0xFC, 4),
+        Operator::I64TruncSatUF32 => encode_prefixed_operator(buf, 0xFC, 5),
+        Operator::I64TruncSatSF64 => encode_prefixed_operator(buf, 0xFC, 6),
+        Operator::I64TruncSatUF64 => encode_prefixed_operator(buf, 0xFC, 7),
+        Operator::MemoryInit { data_index, mem } => {
+            encode_prefixed_operator(buf, 0xFC, 8);
+            write_var_u32(buf, *data_index);
+            write_var_u32(buf, *mem);
+        }
+        Operator::DataDrop { data_index } => {
+            encode_prefixed_operator(buf, 0xFC, 9);
+            write_var_u32(buf, *data_index);
+        }
+        Operator::MemoryCopy { dst_mem, src_mem } => {
+            encode_prefixed_operator(buf, 0xFC, 10);
+            write_var_u32(buf, *dst_mem);
+            write_var_u32(buf, *src_mem);
+        }
+        Operator::MemoryFill { mem } => {
+            encode_prefixed_operator(buf, 0xFC, 11);
+            write_var_u32(buf, *mem);
+        }
+        Operator::TableInit {
+            elem_index,
+            table_index,
+        } => {
+            encode_prefixed_operator(buf, 0xFC, 12);
+            write_var_u32(buf, *elem_index);
+            write_var_u32(buf, *table_index);
+        }
+        Operator::ElemDrop { elem_index } => {
+            encode_prefixed_operator(buf, 0xFC, 13);
+            write_var_u32(buf, *elem_index);
+        }
+        Operator::TableCopy {
+            dst_table,
+            src_table,
+        } => {
+            encode_prefixed_operator(buf, 0xFC, 14);
+            write_var_u32(buf, *dst_table);
+            write_var_u32(buf, *src_table);
+        }
+        Operator::TableGrow { table_index } => {
+            encode_prefixed_operator(buf, 0xFC, 15);
+            write_var_u32(buf, *table_index);
+        }
+        Operator::TableSize { table_index } => {
+            encode_prefixed_operator(buf, 0xFC, 16);
+            write_var_u32(buf, *table_index);
+        }
+        Operator::TableFill { table_index } => {
+            encode_prefixed_operator(buf, 0xFC, 17);
+            write_var_u32(buf, *table_index);
+        }
+    }
+}
+
+fn encode_prefixed_operator(buf: &mut Vec<u8>, prefix: u8, op_code: u32) {
+    buf.push(prefix);
+    write_var_u32(buf, op_code);
+}
+
+fn encode_memory_operator(buf: &mut Vec<u8>, op_code: u8, memarg: &MemArg) {
+    buf.push(op_code);
+    encode_memarg(buf, memarg);
+}
+
+fn write_var_u32(buf: &mut Vec<u8>, value: u32) {
+    write_padded_var_u32(buf, value, 0);
+}
+
+// Writes `value` in at least `width` bytes, continuing it with zeros as object
+// files do.
+fn write_padded_var_u32(buf: &mut Vec<u8>, mut value: u32, width: u8) {
+    let mut len = 1;
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 && len >= width {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+        len += 1;
+    }
+}
+
+fn write_var_i32(buf: &mut Vec<u8>, value: i32) {
+    write_var_i64(buf, i64::from(value));
+}
+
+fn write_var_i64(buf: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        let done = value == if byte & 0x40 == 0 { 0 } else { -1 };
+        if done {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
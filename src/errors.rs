@@ -1,7 +1,9 @@
-use std::string::FromUtf8Error;
-
 use std::error::Error;
 use std::fmt;
+use std::io;
+
+use crate::primitives::Type;
+use crate::runtime::Trap;
 
 #[derive(Debug)]
 pub enum WasmError {
@@ -11,11 +13,179 @@ pub enum WasmError {
     InvalidType(i32),
     InvalidKindType(u32),
     InvalidOperator(u8),
+    InvalidPrefixedOperator(u8, u32),
+    InvalidSegmentFlags(u32),
 
     InvalidLEB128,
 
-    FromUtf8(FromUtf8Error),
+    // a name is not valid UTF-8
+    MalformedUtf8,
     EOF,
+    // reading the input failed
+    Io(io::Error),
+
+    // strict parsing
+    SectionOutOfOrder(u32),
+    DuplicateSection(u32),
+    // the section payload is longer than its content
+    SectionSizeMismatch,
+    // a reserved byte, e.g. the memory index of `memory.size`, is not zero
+    ZeroByteExpected,
+    IntegerRepresentationTooLong,
+    IntegerTooLarge,
+    // the flags of limits other than 0 or 1
+    MalformedLimitsFlags(u32),
+    // a global mutability other than 0 or 1
+    MalformedMutability(u32),
+
+    // validation
+    UnknownType(u32),
+    UnknownFunction(u32),
+    UnknownTable(u32),
+    UnknownMemory(u32),
+    UnknownGlobal(u32),
+    UnknownLocal(u32),
+    UnknownLabel(u32),
+    InvalidValueType(Type),
+    InvalidResultArity,
+    // `None` stands for any value on the expected side and for an empty stack on the actual side
+    TypeMismatch {
+        expected: Option<Type>,
+        actual: Option<Type>,
+    },
+    StackHeightMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    UnmatchedElse,
+    OperatorsAfterEnd,
+    MissingEnd,
+    InvalidAlignment(u32),
+    ImmutableGlobal(u32),
+    ConstantExpressionRequired,
+    InvalidLimits {
+        initial: u32,
+        max: u32,
+    },
+    MemorySizeTooLarge(u32),
+    MultipleMemories,
+    DuplicateExportName(String),
+    InvalidStartFunction(u32),
+    FunctionCodeCountMismatch {
+        functions: usize,
+        codes: usize,
+    },
+    UnknownDataSegment(u32),
+    UnknownElemSegment(u32),
+    // `memory.init` and `data.drop` need the data count section
+    DataCountRequired,
+    DataCountMismatch {
+        count: u32,
+        segments: usize,
+    },
+    // `ref.func` of a function not referenced outside of function bodies
+    UndeclaredFunctionReference(u32),
+    ReferenceTypeExpected(Type),
+    // `select` without types of reference operands
+    UntypedSelect(Type),
+
+    // text format
+    UnexpectedToken(String),
+    UnknownOperator(String),
+    InvalidNumber(String),
+    UnknownIdentifier(String),
+    DuplicateIdentifier(String),
+    InlineFunctionType,
+    ImportAfterDefinition,
+    MultipleStart,
+
+    // instantiation
+    // the module and field names of the imports that were not found, and of
+    // those found with another type than the module expects
+    UnresolvedImports {
+        unknown: Vec<(String, String)>,
+        incompatible: Vec<(String, String)>,
+    },
+    SetConstGlobal,
+    // a segment was out of bounds or the start function trapped
+    Trap(Trap),
+
+    // an error raised while parsing, together with where it happened
+    Positioned {
+        position: Position,
+        err: Box<WasmError>,
+    },
+}
+
+// The location in the input at which an error was raised.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Position {
+    // absolute byte offset in the input
+    pub offset: usize,
+    // id of the section being parsed
+    pub section: Option<u32>,
+    // index of the function, imported functions included
+    pub function_index: Option<u32>,
+    // index of the operator in the function body
+    pub operator_index: Option<u32>,
+}
+
+impl WasmError {
+    pub fn position(&self) -> Option<&Position> {
+        match self {
+            WasmError::Positioned { position, .. } => Some(position),
+            _ => None,
+        }
+    }
+
+    // The error without its position.
+    pub fn inner(&self) -> &WasmError {
+        match self {
+            WasmError::Positioned { err, .. } => err.inner(),
+            err => err,
+        }
+    }
+
+    pub(crate) fn at(self, offset: usize) -> Self {
+        match self {
+            WasmError::Positioned { .. } => self,
+            err => WasmError::Positioned {
+                position: Position {
+                    offset,
+                    ..Position::default()
+                },
+                err: Box::new(err),
+            },
+        }
+    }
+
+    pub(crate) fn in_section(self, section: u32) -> Self {
+        self.with_position(|position| {
+            position.section.get_or_insert(section);
+        })
+    }
+
+    pub(crate) fn in_function(self, function_index: u32) -> Self {
+        self.with_position(|position| {
+            position.function_index.get_or_insert(function_index);
+        })
+    }
+
+    pub(crate) fn at_operator(self, operator_index: u32) -> Self {
+        self.with_position(|position| {
+            position.operator_index.get_or_insert(operator_index);
+        })
+    }
+
+    fn with_position<F: FnOnce(&mut Position)>(mut self, f: F) -> Self {
+        if let WasmError::Positioned {
+            ref mut position, ..
+        } = self
+        {
+            f(position);
+        }
+        self
+    }
 }
 
 impl Error for WasmError {}
@@ -33,16 +203,177 @@ impl fmt::Display for WasmError {
             WasmError::InvalidType(t) => write!(f, "[wasm]: invalid type {:?}", t),
             WasmError::InvalidKindType(t) => write!(f, "[wasm]: invalid kind type {:?}", t),
             WasmError::InvalidOperator(o) => write!(f, "[wasm]: invalid operator code {:?}", o),
+            WasmError::InvalidPrefixedOperator(prefix, o) => {
+                write!(f, "[wasm]: invalid operator code {:?} {:?}", prefix, o)
+            }
+            WasmError::InvalidSegmentFlags(flags) => {
+                write!(f, "[wasm]: invalid segment flags {}", flags)
+            }
             WasmError::InvalidLEB128 => write!(f, "[wasm]: invalid leb-128"),
 
-            WasmError::FromUtf8(ref err) => write!(f, "[wasm]: {:?}", err),
+            WasmError::MalformedUtf8 => write!(f, "[wasm]: malformed UTF-8 encoding"),
             WasmError::EOF => write!(f, "parser EOF"),
+            WasmError::Io(err) => write!(f, "[wasm]: {}", err),
+
+            WasmError::SectionOutOfOrder(id) => write!(f, "[wasm]: section {} out of order", id),
+            WasmError::DuplicateSection(id) => write!(f, "[wasm]: duplicate section {}", id),
+            WasmError::SectionSizeMismatch => write!(f, "[wasm]: section size mismatch"),
+            WasmError::ZeroByteExpected => write!(f, "[wasm]: zero byte expected"),
+            WasmError::IntegerRepresentationTooLong => {
+                write!(f, "[wasm]: integer representation too long")
+            }
+            WasmError::IntegerTooLarge => write!(f, "[wasm]: integer too large"),
+            WasmError::MalformedLimitsFlags(flags) => {
+                write!(f, "[wasm]: malformed limits flags {}", flags)
+            }
+            WasmError::MalformedMutability(mutability) => {
+                write!(f, "[wasm]: malformed mutability {}", mutability)
+            }
+
+            WasmError::UnknownType(index) => write!(f, "[wasm]: unknown type {}", index),
+            WasmError::UnknownFunction(index) => write!(f, "[wasm]: unknown function {}", index),
+            WasmError::UnknownTable(index) => write!(f, "[wasm]: unknown table {}", index),
+            WasmError::UnknownMemory(index) => write!(f, "[wasm]: unknown memory {}", index),
+            WasmError::UnknownGlobal(index) => write!(f, "[wasm]: unknown global {}", index),
+            WasmError::UnknownLocal(index) => write!(f, "[wasm]: unknown local {}", index),
+            WasmError::UnknownLabel(depth) => write!(f, "[wasm]: unknown label {}", depth),
+            WasmError::InvalidValueType(t) => write!(f, "[wasm]: invalid value type {}", t),
+            WasmError::InvalidResultArity => write!(f, "[wasm]: invalid result arity"),
+            WasmError::TypeMismatch { expected, actual } => {
+                write!(f, "[wasm]: type mismatch: expected ")?;
+                match expected {
+                    Some(t) => write!(f, "{}", t)?,
+                    None => write!(f, "a value")?,
+                }
+                match actual {
+                    Some(t) => write!(f, ", found {}", t),
+                    None => write!(f, ", found nothing"),
+                }
+            }
+            WasmError::StackHeightMismatch { expected, actual } => write!(
+                f,
+                "[wasm]: type mismatch: expected {} values at end of block, found {}",
+                expected, actual
+            ),
+            WasmError::UnmatchedElse => write!(f, "[wasm]: else without matching if"),
+            WasmError::OperatorsAfterEnd => write!(f, "[wasm]: operators after function end"),
+            WasmError::MissingEnd => write!(f, "[wasm]: END opcode expected"),
+            WasmError::InvalidAlignment(align) => write!(
+                f,
+                "[wasm]: alignment must not be larger than natural, got {}",
+                align
+            ),
+            WasmError::ImmutableGlobal(index) => write!(f, "[wasm]: global is immutable {}", index),
+            WasmError::ConstantExpressionRequired => {
+                write!(f, "[wasm]: constant expression required")
+            }
+            WasmError::InvalidLimits { initial, max } => write!(
+                f,
+                "[wasm]: size minimum must not be greater than maximum, {} > {}",
+                initial, max
+            ),
+            WasmError::MemorySizeTooLarge(pages) => write!(
+                f,
+                "[wasm]: memory size must be at most 65536 pages (4GiB), got {}",
+                pages
+            ),
+            WasmError::MultipleMemories => write!(f, "[wasm]: multiple memories"),
+            WasmError::DuplicateExportName(name) => {
+                write!(f, "[wasm]: duplicate export name {:?}", name)
+            }
+            WasmError::InvalidStartFunction(index) => write!(
+                f,
+                "[wasm]: start function {} must have type [] -> []",
+                index
+            ),
+            WasmError::FunctionCodeCountMismatch { functions, codes } => write!(
+                f,
+                "[wasm]: function and code section have inconsistent lengths, {} != {}",
+                functions, codes
+            ),
+            WasmError::UnknownDataSegment(index) => {
+                write!(f, "[wasm]: unknown data segment {}", index)
+            }
+            WasmError::UnknownElemSegment(index) => {
+                write!(f, "[wasm]: unknown elem segment {}", index)
+            }
+            WasmError::DataCountRequired => write!(f, "[wasm]: data count section required"),
+            WasmError::DataCountMismatch { count, segments } => write!(
+                f,
+                "[wasm]: data count and data section have inconsistent lengths, {} != {}",
+                count, segments
+            ),
+            WasmError::UndeclaredFunctionReference(index) => {
+                write!(f, "[wasm]: undeclared function reference {}", index)
+            }
+            WasmError::ReferenceTypeExpected(t) => write!(
+                f,
+                "[wasm]: type mismatch: expected a reference, found {}",
+                t
+            ),
+            WasmError::UntypedSelect(t) => {
+                write!(f, "[wasm]: type mismatch: select of {} needs a type", t)
+            }
+
+            WasmError::UnexpectedToken(token) => write!(f, "[wasm]: unexpected token {:?}", token),
+            WasmError::UnknownOperator(name) => write!(f, "[wasm]: unknown operator {}", name),
+            WasmError::InvalidNumber(number) => {
+                write!(f, "[wasm]: constant out of range or malformed {}", number)
+            }
+            WasmError::UnknownIdentifier(id) => write!(f, "[wasm]: unknown identifier {}", id),
+            WasmError::DuplicateIdentifier(id) => write!(f, "[wasm]: duplicate identifier {}", id),
+            WasmError::InlineFunctionType => write!(f, "[wasm]: inline function type"),
+            WasmError::ImportAfterDefinition => {
+                write!(f, "[wasm]: import after function, table, memory or global")
+            }
+            WasmError::MultipleStart => write!(f, "[wasm]: multiple start sections"),
+
+            WasmError::UnresolvedImports {
+                unknown,
+                incompatible,
+            } => {
+                write!(f, "[wasm]:")?;
+                let lists = [
+                    ("unknown import", unknown),
+                    ("incompatible import type", incompatible),
+                ];
+                let lists = lists.iter().filter(|(_, names)| !names.is_empty());
+                for (index, (message, names)) in lists.enumerate() {
+                    let separator = if index == 0 { " " } else { "; " };
+                    write!(f, "{}{}", separator, message)?;
+                    for (index, (mod_name, field_name)) in names.iter().enumerate() {
+                        let separator = if index == 0 { " " } else { ", " };
+                        write!(f, "{}{}.{}", separator, mod_name, field_name)?;
+                    }
+                }
+                Ok(())
+            }
+            WasmError::SetConstGlobal => write!(f, "[wasm]: global is immutable"),
+            WasmError::Trap(trap) => write!(f, "{}", trap),
+
+            WasmError::Positioned { position, err } => write!(f, "{} {}", err, position),
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at offset {:#x}", self.offset)?;
+        if let Some(section) = self.section {
+            write!(f, ", section {}", section)?;
+        }
+        if let Some(function_index) = self.function_index {
+            write!(f, ", function {}", function_index)?;
+        }
+        if let Some(operator_index) = self.operator_index {
+            write!(f, ", operator {}", operator_index)?;
         }
+        Ok(())
     }
 }
 
-impl From<FromUtf8Error> for WasmError {
-    fn from(err: FromUtf8Error) -> Self {
-        WasmError::FromUtf8(err)
+impl From<io::Error> for WasmError {
+    fn from(err: io::Error) -> Self {
+        WasmError::Io(err)
     }
 }
@@ -1,8 +1,15 @@
+pub mod encoder;
 mod errors;
+mod module;
 mod parser;
 pub mod primitives;
+pub mod runtime;
+pub mod text;
+mod validate;
 
-pub use errors::WasmError;
-pub use parser::Parser;
+pub use errors::{Position, WasmError};
+pub use module::{CustomSection, Function, Module};
+pub use parser::{FunctionBody, LocalsReader, OperatorsReader, Parser};
+pub use validate::validate;
 
 pub type WasmResult<T> = Result<T, WasmError>;
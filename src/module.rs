@@ -0,0 +1,452 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::encoder::{
+    encode_custom_section, encode_data_count_section, encode_entities, encode_head,
+    encode_section_code_entity, encode_section_data_entity, encode_section_elem_entity,
+    encode_section_export_entity, encode_section_func_entity, encode_section_global_entity,
+    encode_section_import_entity, encode_section_memory_entity, encode_section_table_entity,
+    encode_section_type_entity, encode_start_section,
+};
+use crate::parser::parser_names;
+use crate::primitives::{
+    GlobalArg, ImportKind, Names, Payload, ResizableLimit, Section, SectionCodeEntity,
+    SectionDataEntity, SectionElemEntity, SectionExportEntity, SectionFuncEntity,
+    SectionGlobalEntity, SectionImportEntity, SectionMemoryEntity, SectionTableEntity,
+    SectionTypeEntity, Type,
+};
+use crate::validate::{validate_parts, Parts};
+use crate::{Parser, WasmResult};
+
+// The ids of the known sections in the order required by the spec.
+const SECTION_IDS: [u32; 12] = [
+    0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0C, 0x0A, 0x0B,
+];
+
+// A parsed module with the entities of each section collected together. Names
+// and bytes borrow from the input of `Module::parse`.
+#[derive(Debug, Default)]
+pub struct Module<'a> {
+    pub types: Vec<SectionTypeEntity>,
+    pub imports: Vec<SectionImportEntity<'a>>,
+    pub functions: Vec<SectionFuncEntity>,
+    pub tables: Vec<SectionTableEntity>,
+    pub memories: Vec<SectionMemoryEntity>,
+    pub globals: Vec<SectionGlobalEntity>,
+    pub exports: Vec<SectionExportEntity<'a>>,
+    pub start: Option<u32>,
+    pub elements: Vec<SectionElemEntity>,
+    pub codes: Vec<SectionCodeEntity<'a>>,
+    pub data: Vec<SectionDataEntity<'a>>,
+    pub data_count: Option<u32>,
+    pub customs: Vec<CustomSection<'a>>,
+
+    // the sections in the order they were read
+    layout: Vec<SectionLayout>,
+}
+
+#[derive(Debug)]
+struct SectionLayout {
+    id: u32,
+    size_width: u8,
+}
+
+#[derive(Debug)]
+pub struct CustomSection<'a> {
+    pub name: Cow<'a, str>,
+    pub payload: Cow<'a, [u8]>,
+}
+
+// An entry of the function index space.
+#[derive(Debug)]
+pub enum Function<'a> {
+    Imported {
+        signature: &'a SectionTypeEntity,
+        import: &'a SectionImportEntity<'a>,
+    },
+    Defined {
+        signature: &'a SectionTypeEntity,
+        body: &'a SectionCodeEntity<'a>,
+    },
+}
+
+impl<'a> Function<'a> {
+    pub fn signature(&self) -> &'a SectionTypeEntity {
+        match self {
+            Function::Imported { signature, .. } | Function::Defined { signature, .. } => signature,
+        }
+    }
+}
+
+impl<'a> Module<'a> {
+    pub fn parse(code: &'a [u8]) -> WasmResult<Self> {
+        Self::read(Parser::new(code))
+    }
+
+    // Parses `code`, rejecting everything the spec considers malformed.
+    pub fn parse_strict(code: &'a [u8]) -> WasmResult<Self> {
+        Self::read(Parser::new(code).strict())
+    }
+
+    fn read(parser: Parser<'a>) -> WasmResult<Self> {
+        let mut sections = vec![];
+        for payload in parser {
+            if let Payload::Section { section, .. } = payload? {
+                sections.push(section);
+            }
+        }
+
+        Ok(Self::from_sections(sections))
+    }
+
+    pub fn from_sections(sections: Vec<Section<'a>>) -> Self {
+        let mut module = Self::default();
+        for section in sections {
+            module.layout.push(SectionLayout {
+                id: section.id(),
+                size_width: section.size_width(),
+            });
+            match section {
+                Section::Custom { name, payload, .. } => {
+                    module.customs.push(CustomSection { name, payload })
+                }
+                Section::Type { entities, .. } => module.types.extend(entities),
+                Section::Import { entities, .. } => module.imports.extend(entities),
+                Section::Function { entities, .. } => module.functions.extend(entities),
+                Section::Table { entities, .. } => module.tables.extend(entities),
+                Section::Memory { entities, .. } => module.memories.extend(entities),
+                Section::Global { entities, .. } => module.globals.extend(entities),
+                Section::Export { entities, .. } => module.exports.extend(entities),
+                Section::Start {
+                    signature_index, ..
+                } => module.start = Some(signature_index),
+                Section::Element { entities, .. } => module.elements.extend(entities),
+                Section::Code { entities, .. } => module.codes.extend(entities),
+                Section::Data { entities, .. } => module.data.extend(entities),
+                Section::DataCount { count, .. } => module.data_count = Some(count),
+            }
+        }
+
+        module
+    }
+
+    // Encodes the module to the binary format. Sections are written in the order
+    // required by the spec with custom sections kept where they were read, and a
+    // parsed module encodes back to its original bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        // custom sections are anchored after the last known section read before them
+        let mut anchors = vec![];
+        let mut widths = HashMap::new();
+        let mut last_id = 0;
+        for layout in &self.layout {
+            if layout.id == 0 {
+                anchors.push((last_id, layout.size_width));
+            } else {
+                last_id = layout.id;
+                widths.entry(layout.id).or_insert(layout.size_width);
+            }
+        }
+        let present = |id: u32| self.layout.iter().any(|layout| layout.id == id);
+
+        let mut buf = vec![];
+        encode_head(&mut buf);
+        self.encode_customs(&mut buf, &anchors, Some(0));
+        for &id in SECTION_IDS.iter() {
+            let width = widths.get(&id).cloned().unwrap_or(0);
+            match id {
+                0x01 if present(id) || !self.types.is_empty() => encode_entities(
+                    &mut buf,
+                    0x01,
+                    &self.types,
+                    encode_section_type_entity,
+                    width,
+                ),
+                0x02 if present(id) || !self.imports.is_empty() => encode_entities(
+                    &mut buf,
+                    0x02,
+                    &self.imports,
+                    encode_section_import_entity,
+                    width,
+                ),
+                0x03 if present(id) || !self.functions.is_empty() => encode_entities(
+                    &mut buf,
+                    0x03,
+                    &self.functions,
+                    encode_section_func_entity,
+                    width,
+                ),
+                0x04 if present(id) || !self.tables.is_empty() => encode_entities(
+                    &mut buf,
+                    0x04,
+                    &self.tables,
+                    encode_section_table_entity,
+                    width,
+                ),
+                0x05 if present(id) || !self.memories.is_empty() => encode_entities(
+                    &mut buf,
+                    0x05,
+                    &self.memories,
+                    encode_section_memory_entity,
+                    width,
+                ),
+                0x06 if present(id) || !self.globals.is_empty() => encode_entities(
+                    &mut buf,
+                    0x06,
+                    &self.globals,
+                    encode_section_global_entity,
+                    width,
+                ),
+                0x07 if present(id) || !self.exports.is_empty() => encode_entities(
+                    &mut buf,
+                    0x07,
+                    &self.exports,
+                    encode_section_export_entity,
+                    width,
+                ),
+                0x08 => {
+                    if let Some(signature_index) = self.start {
+                        encode_start_section(&mut buf, signature_index, width)
+                    }
+                }
+                0x09 if present(id) || !self.elements.is_empty() => encode_entities(
+                    &mut buf,
+                    0x09,
+                    &self.elements,
+                    encode_section_elem_entity,
+                    width,
+                ),
+                0x0C => {
+                    if let Some(count) = self.data_count {
+                        encode_data_count_section(&mut buf, count, width)
+                    }
+                }
+                0x0A if present(id) || !self.codes.is_empty() => encode_entities(
+                    &mut buf,
+                    0x0A,
+                    &self.codes,
+                    encode_section_code_entity,
+                    width,
+                ),
+                0x0B if present(id) || !self.data.is_empty() => encode_entities(
+                    &mut buf,
+                    0x0B,
+                    &self.data,
+                    encode_section_data_entity,
+                    width,
+                ),
+                _ => {}
+            }
+            self.encode_customs(&mut buf, &anchors, Some(id));
+        }
+        self.encode_customs(&mut buf, &anchors, None);
+
+        buf
+    }
+
+    // Writes the custom sections anchored after section `id`, or the ones added
+    // after parsing when `id` is `None`.
+    fn encode_customs(&self, buf: &mut Vec<u8>, anchors: &[(u32, u8)], id: Option<u32>) {
+        for (index, custom) in self.customs.iter().enumerate() {
+            let width = match (anchors.get(index), id) {
+                (Some((anchor, width)), Some(id)) if *anchor == id => *width,
+                (None, None) => 0,
+                _ => continue,
+            };
+            encode_custom_section(buf, &custom.name, &custom.payload, width);
+        }
+    }
+
+    // Copies what is borrowed from the input, as instances do.
+    pub fn into_owned(self) -> Module<'static> {
+        Module {
+            types: self.types,
+            imports: self.imports.into_iter().map(|e| e.into_owned()).collect(),
+            functions: self.functions,
+            tables: self.tables,
+            memories: self.memories,
+            globals: self.globals,
+            exports: self.exports.into_iter().map(|e| e.into_owned()).collect(),
+            start: self.start,
+            elements: self.elements,
+            codes: self.codes.into_iter().map(|e| e.into_owned()).collect(),
+            data: self.data.into_iter().map(|e| e.into_owned()).collect(),
+            data_count: self.data_count,
+            customs: self
+                .customs
+                .into_iter()
+                .map(|custom| CustomSection {
+                    name: Cow::Owned(custom.name.into_owned()),
+                    payload: Cow::Owned(custom.payload.into_owned()),
+                })
+                .collect(),
+            layout: self.layout,
+        }
+    }
+
+    pub fn validate(&self) -> WasmResult<()> {
+        validate_parts(&Parts {
+            types: self.types.iter().collect(),
+            imports: self.imports.iter().collect(),
+            functions: self.functions.iter().collect(),
+            tables: self.tables.iter().collect(),
+            memories: self.memories.iter().collect(),
+            globals: self.globals.iter().collect(),
+            exports: self.exports.iter().collect(),
+            start: self.start,
+            elements: self.elements.iter().collect(),
+            codes: self.codes.iter().collect(),
+            data: self.data.iter().collect(),
+            data_count: self.data_count,
+        })
+    }
+
+    pub fn func_type(&self, type_index: u32) -> Option<&SectionTypeEntity> {
+        self.types.get(type_index as usize)
+    }
+
+    pub fn imported_functions(&self) -> impl Iterator<Item = &SectionImportEntity> {
+        self.imports.iter().filter(|entity| is_func(&entity.kind))
+    }
+
+    pub fn imported_tables(&self) -> impl Iterator<Item = &SectionImportEntity> {
+        self.imports.iter().filter(|entity| is_table(&entity.kind))
+    }
+
+    pub fn imported_memories(&self) -> impl Iterator<Item = &SectionImportEntity> {
+        self.imports.iter().filter(|entity| is_memory(&entity.kind))
+    }
+
+    pub fn imported_globals(&self) -> impl Iterator<Item = &SectionImportEntity> {
+        self.imports.iter().filter(|entity| is_global(&entity.kind))
+    }
+
+    // The import at `index` in the index space of the imports of one kind, or the
+    // index among the defined items when it is past them. Reads `imports` in a
+    // single pass, so that the index spaces follow changes to it.
+    fn lookup_import(
+        &self,
+        index: u32,
+        is_kind: fn(&ImportKind) -> bool,
+    ) -> Result<&SectionImportEntity<'a>, usize> {
+        let index = index as usize;
+        let mut imported = 0;
+        for entity in self.imports.iter().filter(|entity| is_kind(&entity.kind)) {
+            if imported == index {
+                return Ok(entity);
+            }
+            imported += 1;
+        }
+        Err(index - imported)
+    }
+
+    pub fn function_count(&self) -> usize {
+        self.imported_functions().count() + self.functions.len()
+    }
+
+    // Looks up a function by its index, imported functions first.
+    pub fn function(&self, index: u32) -> Option<Function> {
+        match self.lookup_import(index, is_func) {
+            Ok(import) => {
+                let signature = match import.kind {
+                    ImportKind::Func { signature_index } => self.func_type(signature_index)?,
+                    _ => return None,
+                };
+                Some(Function::Imported { signature, import })
+            }
+            Err(index) => {
+                let entity = self.functions.get(index)?;
+                let signature = self.func_type(entity.signature_index as u32)?;
+                let body = self.codes.get(index)?;
+                Some(Function::Defined { signature, body })
+            }
+        }
+    }
+
+    pub fn table_count(&self) -> usize {
+        self.imported_tables().count() + self.tables.len()
+    }
+
+    // The element type and limits of a table, imported tables first.
+    pub fn table_type(&self, index: u32) -> Option<(Type, &ResizableLimit)> {
+        match self.lookup_import(index, is_table) {
+            Ok(import) => match &import.kind {
+                ImportKind::Table { elem_type, limit } => Some((*elem_type, limit)),
+                _ => None,
+            },
+            Err(index) => {
+                let entity = self.tables.get(index)?;
+                Some((entity.elem_type, &entity.limit))
+            }
+        }
+    }
+
+    pub fn memory_count(&self) -> usize {
+        self.imported_memories().count() + self.memories.len()
+    }
+
+    // The limits of a memory, imported memories first.
+    pub fn memory_type(&self, index: u32) -> Option<&ResizableLimit> {
+        match self.lookup_import(index, is_memory) {
+            Ok(import) => match &import.kind {
+                ImportKind::Memory { limit } => Some(limit),
+                _ => None,
+            },
+            Err(index) => Some(&self.memories.get(index)?.limit),
+        }
+    }
+
+    pub fn global_count(&self) -> usize {
+        self.imported_globals().count() + self.globals.len()
+    }
+
+    // The type of a global, imported globals first.
+    pub fn global_type(&self, index: u32) -> Option<&GlobalArg> {
+        match self.lookup_import(index, is_global) {
+            Ok(import) => match &import.kind {
+                ImportKind::Global { global_arg } => Some(global_arg),
+                _ => None,
+            },
+            Err(index) => Some(&self.globals.get(index)?.global_arg),
+        }
+    }
+
+    // The debug names of the "name" custom section, empty if there is none.
+    pub fn names(&self) -> WasmResult<Names> {
+        match self.customs.iter().find(|custom| custom.name == "name") {
+            Some(custom) => parser_names(&custom.payload),
+            None => Ok(Names::default()),
+        }
+    }
+
+    pub fn export(&self, name: &str) -> Option<&SectionExportEntity> {
+        self.exports.iter().find(|entity| entity.name == name)
+    }
+}
+
+fn is_func(kind: &ImportKind) -> bool {
+    match kind {
+        ImportKind::Func { .. } => true,
+        _ => false,
+    }
+}
+
+fn is_table(kind: &ImportKind) -> bool {
+    match kind {
+        ImportKind::Table { .. } => true,
+        _ => false,
+    }
+}
+
+fn is_memory(kind: &ImportKind) -> bool {
+    match kind {
+        ImportKind::Memory { .. } => true,
+        _ => false,
+    }
+}
+
+fn is_global(kind: &ImportKind) -> bool {
+    match kind {
+        ImportKind::Global { .. } => true,
+        _ => false,
+    }
+}
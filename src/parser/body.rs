@@ -0,0 +1,149 @@
+use std::ops::Range;
+
+use crate::parser::bytes_reader::BytesReader;
+use crate::parser::{parser_operator, parser_type};
+use crate::primitives::{Operator, Type};
+use crate::WasmResult;
+
+// The undecoded body of a function, read on demand.
+#[derive(Debug, Clone)]
+pub struct FunctionBody<'a> {
+    reader: BytesReader<'a>,
+    // index of the function, imported functions included
+    index: u32,
+    // width of the LEB-128 size before the body
+    size_width: u8,
+}
+
+impl<'a> FunctionBody<'a> {
+    pub(crate) fn new(reader: BytesReader<'a>, index: u32, size_width: u8) -> Self {
+        Self {
+            reader,
+            index,
+            size_width,
+        }
+    }
+
+    // The absolute byte range of the body, local declarations included.
+    pub fn range(&self) -> Range<usize> {
+        self.reader.offset()..self.reader.offset() + self.reader.remaining()
+    }
+
+    // The bytes of the body, local declarations included.
+    pub fn bytes(&self) -> &[u8] {
+        self.reader.rest()
+    }
+
+    pub(crate) fn size_width(&self) -> u8 {
+        self.size_width
+    }
+
+    pub fn locals_reader(&self) -> WasmResult<LocalsReader<'a>> {
+        let mut reader = self.reader.clone();
+        let count = reader
+            .read_var_u32()
+            .map_err(|err| err.in_function(self.index))?;
+        Ok(LocalsReader {
+            reader,
+            index: self.index,
+            count,
+            read: 0,
+        })
+    }
+
+    // Reads the operators after the local declarations.
+    pub fn operators_reader(&self) -> WasmResult<OperatorsReader<'a>> {
+        self.locals_reader()?.into_operators_reader()
+    }
+
+    pub fn into_owned(self) -> FunctionBody<'static> {
+        FunctionBody {
+            reader: self.reader.into_owned(),
+            index: self.index,
+            size_width: self.size_width,
+        }
+    }
+}
+
+// Yields the local declarations of a function body as `(count, type)`.
+pub struct LocalsReader<'a> {
+    reader: BytesReader<'a>,
+    index: u32,
+    count: u32,
+    read: u32,
+}
+
+impl<'a> LocalsReader<'a> {
+    // The number of declarations, each of which may declare many locals.
+    pub fn declaration_count(&self) -> u32 {
+        self.count
+    }
+
+    // Reads the operators after the declarations, skipping the ones not read yet.
+    pub fn into_operators_reader(mut self) -> WasmResult<OperatorsReader<'a>> {
+        while let Some(entry) = self.next() {
+            entry?;
+        }
+        Ok(OperatorsReader {
+            reader: self.reader,
+            function_index: self.index,
+            index: 0,
+            finished: false,
+        })
+    }
+}
+
+impl<'a> Iterator for LocalsReader<'a> {
+    type Item = WasmResult<(u32, Type)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.read == self.count {
+            return None;
+        }
+
+        self.read += 1;
+        let entry = self
+            .reader
+            .read_var_u32()
+            .and_then(|count| Ok((count, parser_type(&mut self.reader)?)));
+        if entry.is_err() {
+            self.read = self.count;
+        }
+        Some(entry.map_err(|err| err.in_function(self.index)))
+    }
+}
+
+// Yields the operators of a function body with their absolute offsets, up to
+// the end of the body or the first error.
+pub struct OperatorsReader<'a> {
+    reader: BytesReader<'a>,
+    function_index: u32,
+    // index of the next operator
+    index: u32,
+    finished: bool,
+}
+
+impl<'a> OperatorsReader<'a> {
+    // The absolute offset of the next operator, or of the end of the body.
+    pub fn offset(&self) -> usize {
+        self.reader.offset()
+    }
+}
+
+impl<'a> Iterator for OperatorsReader<'a> {
+    type Item = WasmResult<(Operator, usize)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished || self.reader.remaining() == 0 {
+            return None;
+        }
+
+        let offset = self.reader.offset();
+        let operator = parser_operator(&mut self.reader).map_err(|err| {
+            self.finished = true;
+            err.at_operator(self.index).in_function(self.function_index)
+        });
+        self.index += 1;
+        Some(operator.map(|operator| (operator, offset)))
+    }
+}
@@ -1,3 +1,6 @@
+use std::borrow::Cow;
+use std::str;
+
 use byteorder::{ByteOrder, LittleEndian};
 use bytes::Bytes;
 use codicon::Decoder;
@@ -5,14 +8,90 @@ use lebicon::Leb128;
 
 use crate::{WasmError, WasmResult};
 
-pub struct BytesReader {
-    code: Bytes,
+// The bytes of a reader, borrowed from the input or shared with a buffer of the parser.
+#[derive(Debug, Clone)]
+pub enum Code<'a> {
+    Borrowed(&'a [u8]),
+    Shared(Bytes),
+}
+
+impl<'a> Code<'a> {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Code::Borrowed(code) => code,
+            Code::Shared(code) => code,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    fn slice(&self, start: usize, end: usize) -> Code<'a> {
+        match self {
+            Code::Borrowed(code) => Code::Borrowed(&code[start..end]),
+            Code::Shared(code) => Code::Shared(code.slice(start, end)),
+        }
+    }
+
+    // Borrowed bytes are not copied, shared ones are.
+    fn cow(&self, start: usize, end: usize) -> Cow<'a, [u8]> {
+        match self {
+            Code::Borrowed(code) => Cow::Borrowed(&code[start..end]),
+            Code::Shared(code) => Cow::Owned(code[start..end].to_vec()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BytesReader<'a> {
+    code: Code<'a>,
     position: usize,
+    // absolute offset of `code` in the original input
+    base: usize,
+    // rejects integers with overlong or oversized LEB128 encodings
+    strict: bool,
 }
 
-impl BytesReader {
-    pub fn new(code: Bytes, position: usize) -> Self {
-        Self { code, position }
+impl<'a> BytesReader<'a> {
+    pub fn new(code: Code<'a>, position: usize) -> Self {
+        Self::with_base(code, position, 0)
+    }
+
+    pub fn with_base(code: Code<'a>, position: usize, base: usize) -> Self {
+        Self {
+            code,
+            position,
+            base,
+            strict: false,
+        }
+    }
+
+    // Copies the bytes if they are borrowed.
+    pub fn into_owned(self) -> BytesReader<'static> {
+        let code = match self.code {
+            Code::Borrowed(code) => Code::Shared(Bytes::from(code)),
+            Code::Shared(code) => Code::Shared(code),
+        };
+        BytesReader {
+            code,
+            position: self.position,
+            base: self.base,
+            strict: self.strict,
+        }
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    // The absolute offset of the next byte to read.
+    pub fn offset(&self) -> usize {
+        self.base + self.position
     }
 
     pub fn is_empty(&self) -> bool {
@@ -23,37 +102,63 @@ impl BytesReader {
         self.position
     }
 
+    // The number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        self.code.len() - self.position
+    }
+
+    // The bytes left to read.
+    pub fn rest(&self) -> &[u8] {
+        &self.code.as_slice()[self.position..]
+    }
+
     pub fn read_range(&mut self, len: usize) -> WasmResult<&[u8]> {
         let buf = self
             .code
+            .as_slice()
             .get(self.position..self.position + len)
-            .ok_or(WasmError::EOF)?;
+            .ok_or_else(|| WasmError::EOF.at(self.base + self.position))?;
         self.position += len;
         Ok(buf)
     }
 
+    // Splits off the next `len` bytes as a reader that keeps reporting
+    // absolute offsets, without copying them.
+    pub fn read_reader(&mut self, len: usize) -> WasmResult<BytesReader<'a>> {
+        if self.position + len > self.code.len() {
+            return Err(WasmError::EOF.at(self.offset()));
+        }
+
+        let base = self.offset();
+        let code = self.code.slice(self.position, self.position + len);
+        self.position += len;
+        let mut reader = BytesReader::with_base(code, 0, base);
+        reader.strict = self.strict;
+        Ok(reader)
+    }
+
     pub fn read_var_u8(&mut self) -> WasmResult<u32> {
-        self.read_var::<u32>(1)
+        self.read_var::<u32>(8, false)
     }
 
     pub fn read_var_i8(&mut self) -> WasmResult<i32> {
-        self.read_var::<i32>(1)
+        self.read_var::<i32>(8, true)
     }
 
     pub fn read_var_u32(&mut self) -> WasmResult<u32> {
-        self.read_var::<u32>(4)
+        self.read_var::<u32>(32, false)
     }
 
     pub fn read_var_i32(&mut self) -> WasmResult<i32> {
-        self.read_var::<i32>(4)
+        self.read_var::<i32>(32, true)
     }
 
     // pub fn read_var_u64(&mut self) -> WasmResult<u64> {
-    //     self.read_var::<u64>(8)
+    //     self.read_var::<u64>(64, false)
     // }
 
     pub fn read_var_i64(&mut self) -> WasmResult<i64> {
-        let a = self.read_var::<i64>(9)?;
+        let a = self.read_var::<i64>(64, true)?;
         Ok(a)
     }
 
@@ -75,20 +180,38 @@ impl BytesReader {
         Ok(LittleEndian::read_u32(buf))
     }
 
-    pub fn read_str(&mut self, len: usize) -> WasmResult<String> {
-        let bytes = self.read_range(len)?;
-        let s = String::from_utf8(bytes.to_owned())?;
-        Ok(s)
+    // Reads `len` bytes, borrowed from the input if the reader is.
+    pub fn read_bytes(&mut self, len: usize) -> WasmResult<Cow<'a, [u8]>> {
+        if self.position + len > self.code.len() {
+            return Err(WasmError::EOF.at(self.offset()));
+        }
+
+        let bytes = self.code.cow(self.position, self.position + len);
+        self.position += len;
+        Ok(bytes)
+    }
+
+    pub fn read_str(&mut self, len: usize) -> WasmResult<Cow<'a, str>> {
+        let offset = self.offset();
+        let s = match self.read_bytes(len)? {
+            Cow::Borrowed(bytes) => str::from_utf8(bytes).ok().map(Cow::Borrowed),
+            Cow::Owned(bytes) => String::from_utf8(bytes).ok().map(Cow::Owned),
+        };
+        s.ok_or_else(|| WasmError::MalformedUtf8.at(offset))
     }
 
     pub fn read_u8(&mut self) -> WasmResult<u8> {
-        let byte = self.code.get(self.position).ok_or(WasmError::EOF)?;
+        let byte = self.peek_byte(self.position)?;
         self.position += 1;
-        Ok(*byte)
+        Ok(byte)
     }
 
     fn peek_byte(&self, position: usize) -> WasmResult<u8> {
-        let byte = self.code.get(position).ok_or(WasmError::EOF)?;
+        let byte = self
+            .code
+            .as_slice()
+            .get(position)
+            .ok_or_else(|| WasmError::EOF.at(self.base + position))?;
         Ok(*byte)
     }
 
@@ -101,19 +224,25 @@ impl BytesReader {
         Ok(b1 | (b2 << 8) | (b3 << 16) | (b4 << 24))
     }
 
-    fn read_var<T: Decoder<Leb128>>(&mut self, max_len: usize) -> WasmResult<T> {
+    // Reads a LEB128 integer of `bits` bits, which takes at most `ceil(bits / 7)` bytes.
+    fn read_var<T: Decoder<Leb128>>(&mut self, bits: u32, signed: bool) -> WasmResult<T> {
+        let max_len = ((bits + 6) / 7) as usize;
         let byte = self.peek_byte(self.position)?;
         let mut bytes = vec![byte];
         if (bytes[0] & 0x80) == 0 {
-            let value = T::decode(&mut &bytes[..], Leb128).map_err(|_| WasmError::InvalidLEB128)?;
+            let value = T::decode(&mut &bytes[..], Leb128)
+                .map_err(|_| WasmError::InvalidLEB128.at(self.offset()))?;
             self.position += 1;
             return Ok(value);
         }
 
         let mut len = bytes.len();
         loop {
-            if len > max_len {
-                return Err(WasmError::InvalidLEB128);
+            if len >= max_len {
+                if self.strict {
+                    return Err(WasmError::IntegerRepresentationTooLong.at(self.offset()));
+                }
+                return Err(WasmError::InvalidLEB128.at(self.offset()));
             }
             len += 1;
 
@@ -124,8 +253,29 @@ impl BytesReader {
             }
         }
 
-        let value = T::decode(&mut &bytes[..], Leb128).map_err(|_| WasmError::InvalidLEB128)?;
+        if self.strict && len == max_len && !fits(byte_bits(bits, max_len), signed, bytes[len - 1])
+        {
+            return Err(WasmError::IntegerTooLarge.at(self.offset()));
+        }
+
+        let value = T::decode(&mut &bytes[..], Leb128)
+            .map_err(|_| WasmError::InvalidLEB128.at(self.offset()))?;
         self.position += bytes.len();
         Ok(value)
     }
 }
+
+// The number of bits of the integer held by the last of `len` bytes.
+fn byte_bits(bits: u32, len: usize) -> u32 {
+    bits - 7 * (len as u32 - 1)
+}
+
+// Whether the unused bits of the last byte are zeros, or copies of the sign bit.
+fn fits(used: u32, signed: bool, byte: u8) -> bool {
+    let unused = 0x7F & !((1u8 << used) - 1);
+    if signed && byte & (1 << (used - 1)) != 0 {
+        byte & unused == unused
+    } else {
+        byte & unused == 0
+    }
+}
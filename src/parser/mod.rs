@@ -1,185 +1,521 @@
+mod body;
 mod bytes_reader;
 
-use bytes::Bytes;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read};
 
-use crate::parser::bytes_reader::BytesReader;
+use bytes::BytesMut;
+
+pub use crate::parser::body::{FunctionBody, LocalsReader, OperatorsReader};
+use crate::parser::bytes_reader::{BytesReader, Code};
 use crate::primitives::{
-    ExternalKind, Frame, GlobalArg, ImportKind, MemArg, Mutability, Operator, ResizableLimit,
-    Section, SectionCodeEntity, SectionDataEntity, SectionElemEntity, SectionExportEntity,
-    SectionFuncEntity, SectionGlobalEntity, SectionImportEntity, SectionMemoryEntity,
-    SectionTableEntity, SectionTypeEntity, Type,
+    ElemItems, ExternalKind, Frame, GlobalArg, ImportKind, MemArg, Mutability, Names, Operator,
+    Payload, ResizableLimit, Section, SectionCodeEntity, SectionDataEntity, SectionElemEntity,
+    SectionExportEntity, SectionFuncEntity, SectionGlobalEntity, SectionImportEntity,
+    SectionMemoryEntity, SectionTableEntity, SectionTypeEntity, SegmentMode, Type,
 };
 use crate::{WasmError, WasmResult};
 
 const WASM_MAGIC_NUMBER: u32 = 0x6D73_6100;
 const WASM_SUPPORTED_VERSION: u32 = 0x01;
-
-pub struct Parser {
-    reader: BytesReader,
+// the least number of bytes `from_reader` reads at a time
+const READ_CHUNK_SIZE: usize = 4096;
+
+// Sections parsed from a slice borrow their names and bytes from it.
+pub struct Parser<'a> {
+    // the head or section being parsed
+    reader: BytesReader<'a>,
+    // where `buffer` is filled from, until its end
+    source: Option<Box<dyn Read + 'a>>,
+    // the input after `reader`
+    buffer: Input<'a>,
+    // the absolute offset of `buffer`
+    consumed: usize,
+    // whether `buffer` holds the rest of the input
+    eof: bool,
     initialize: bool,
+    // rejects the modules the spec considers malformed, which are otherwise read leniently
+    strict: bool,
+    // leaves function bodies undecoded
+    lazy_bodies: bool,
+    // the order of the last non-custom section read
+    last_order: u32,
+    // the number of imported functions, which the code section's are numbered after
+    imported_functions: u32,
+    // the number of entries in the function section
+    functions: usize,
+    code_read: bool,
+    // the count of the data count section
+    data_count: Option<u32>,
+    data_read: bool,
+    // set once the iterator has yielded an error or reached the end
+    finished: bool,
 }
 
-impl Parser {
-    pub fn new(code: &[u8]) -> Self {
-        let bytes = Bytes::from(code);
+impl<'a> Parser<'a> {
+    pub fn new(code: &'a [u8]) -> Self {
+        Self::with_input(Input::Borrowed(code), true)
+    }
+
+    // Reads `source` as sections are parsed, so that at most a section and a
+    // small chunk after it are buffered at a time.
+    pub fn from_reader<R: Read + 'a>(source: R) -> Self {
+        let mut parser = Self::incremental();
+        parser.source = Some(Box::new(source));
+        parser
+    }
 
+    // A parser without input, which is pushed with `feed` as it arrives. Reads
+    // return `Frame::NeedMoreData` until a whole head or section is buffered.
+    pub fn incremental() -> Self {
+        Self::with_input(Input::Buffered(BytesMut::new()), false)
+    }
+
+    fn with_input(buffer: Input<'a>, eof: bool) -> Self {
         Self {
-            reader: BytesReader::new(bytes, 0),
+            reader: BytesReader::new(Code::Borrowed(&[]), 0),
+            source: None,
+            buffer,
+            consumed: 0,
+            eof,
             initialize: false,
+            strict: false,
+            lazy_bodies: false,
+            last_order: 0,
+            imported_functions: 0,
+            functions: 0,
+            code_read: false,
+            data_count: None,
+            data_read: false,
+            finished: false,
         }
     }
 
-    pub fn read(&mut self) -> Frame {
+    // Rejects out of order or duplicate sections, trailing bytes in sections,
+    // function and code sections of different lengths, data sections of another
+    // length than the data count, non-zero reserved bytes, and overlong or
+    // oversized integers.
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self.reader.set_strict(true);
+        self
+    }
+
+    // Leaves the `locals` and `code` of function bodies empty, to be read on demand
+    // through their `body`.
+    pub fn lazy_bodies(mut self) -> Self {
+        self.lazy_bodies = true;
+        self
+    }
+
+    // Appends the next chunk of input.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.buffer.extend(chunk);
+    }
+
+    // Marks the end of the input, after which an incomplete section is an error.
+    pub fn finish(&mut self) {
+        self.eof = true;
+    }
+
+    pub fn read(&mut self) -> Frame<'a> {
         match self.inner_read() {
             Ok(frame) => frame,
             Err(err) => Frame::ParserError { err },
         }
     }
 
-    pub fn inner_read(&mut self) -> WasmResult<Frame> {
+    pub fn inner_read(&mut self) -> WasmResult<Frame<'a>> {
+        loop {
+            match (self.read_buffered()?, self.source.is_some()) {
+                (Frame::NeedMoreData { hint }, true) => self.fill(hint)?,
+                (frame, _) => return Ok(frame),
+            }
+        }
+    }
+
+    // Reads at least `hint` bytes from the source into the buffer, or marks the end of the input.
+    // The hint comes from the input itself, so the buffer only grows as data arrives.
+    fn fill(&mut self, hint: usize) -> WasmResult<()> {
+        let source = match self.source.as_mut() {
+            Some(source) => source,
+            None => return Ok(()),
+        };
+        let mut chunk = [0; READ_CHUNK_SIZE];
+        let mut len = 0;
+        while len < hint {
+            match source.read(&mut chunk) {
+                Ok(0) => {
+                    self.source = None;
+                    self.eof = true;
+                    break;
+                }
+                Ok(read) => {
+                    self.buffer.extend(&chunk[..read]);
+                    len += read;
+                }
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    let offset = self.consumed + self.buffer.len();
+                    return Err(WasmError::from(err).at(offset));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_buffered(&mut self) -> WasmResult<Frame<'a>> {
+        if self.buffer.is_empty() && self.initialize {
+            if !self.eof {
+                return Ok(Frame::NeedMoreData { hint: 1 });
+            }
+            if self.strict && !self.code_read && self.functions > 0 {
+                return Err(WasmError::FunctionCodeCountMismatch {
+                    functions: self.functions,
+                    codes: 0,
+                }
+                .at(self.consumed));
+            }
+            match self.data_count {
+                Some(count) if self.strict && !self.data_read && count > 0 => {
+                    return Err(
+                        WasmError::DataCountMismatch { count, segments: 0 }.at(self.consumed)
+                    );
+                }
+                _ => {}
+            }
+            return Ok(Frame::End);
+        }
+
+        let len = if self.initialize {
+            unit_len(self.buffer.as_slice())
+        } else {
+            Some(8)
+        };
+        let len = match len {
+            Some(len) if len <= self.buffer.len() => len,
+            Some(len) if !self.eof => {
+                let hint = len - self.buffer.len();
+                return Ok(Frame::NeedMoreData { hint });
+            }
+            None if !self.eof => return Ok(Frame::NeedMoreData { hint: 1 }),
+            _ => self.buffer.len(),
+        };
+
+        let unit = self.buffer.split_to(len);
+        self.reader = BytesReader::with_base(unit, 0, self.consumed);
+        self.reader.set_strict(self.strict);
+        self.consumed += len;
+
         if !self.initialize {
             let head = self.head()?;
             self.initialize = true;
             Ok(head)
-        } else if self.reader.is_empty() {
-            Ok(Frame::End)
         } else {
             self.section()
         }
     }
 
-    fn head(&mut self) -> WasmResult<Frame> {
+    fn head(&mut self) -> WasmResult<Frame<'a>> {
+        let offset = self.reader.offset();
         let magic_number = self.reader.read_u32()?;
         if magic_number != WASM_MAGIC_NUMBER {
-            return Err(WasmError::InvalidMagicNumber(magic_number));
+            return Err(WasmError::InvalidMagicNumber(magic_number).at(offset));
         }
 
+        let offset = self.reader.offset();
         let version = self.reader.read_u32()?;
         if version != WASM_SUPPORTED_VERSION {
-            return Err(WasmError::InvalidVersion(version));
+            return Err(WasmError::InvalidVersion(version).at(offset));
         }
         Ok(Frame::Head { version })
     }
 
-    fn section(&mut self) -> WasmResult<Frame> {
+    fn section(&mut self) -> WasmResult<Frame<'a>> {
+        let offset = self.reader.offset();
         let section_code = self.reader.read_var_u8()?;
-        let section = match section_code {
-            0x00 => self.section_custom()?,
-            0x01 => self.section_type()?,
-            0x02 => self.section_import()?,
-            0x03 => self.section_function()?,
-            0x04 => self.section_table()?,
-            0x05 => self.section_memory()?,
-            0x06 => self.section_global()?,
-            0x07 => self.section_export()?,
-            0x08 => self.section_start()?,
-            0x09 => self.section_elem()?,
-            0x0A => self.section_code()?,
-            0x0B => self.section_data()?,
-            _ => return Err(WasmError::InvalidSection(section_code)),
-        };
+        let section = self
+            .section_payload(section_code, offset)
+            .map_err(|err| err.in_section(section_code))?;
 
-        Ok(Frame::Section { section })
+        Ok(Frame::Section {
+            section,
+            range: offset..self.reader.offset(),
+        })
     }
 
-    fn section_custom(&mut self) -> WasmResult<Section> {
-        let payload_len = self.reader.read_var_u32()? as usize;
-
-        let size_of_before = self.reader.len();
-        let name_len = self.reader.read_var_u32()? as usize;
-        let size_of_after = self.reader.len();
-        let name = self.reader.read_str(name_len)?;
+    fn section_payload(&mut self, section_code: u32, offset: usize) -> WasmResult<Section<'a>> {
+        if section_code == 0x00 {
+            return self.section_custom();
+        }
+        let order = section_order(section_code)
+            .ok_or_else(|| WasmError::InvalidSection(section_code).at(offset))?;
+        if self.strict {
+            if order == self.last_order {
+                return Err(WasmError::DuplicateSection(section_code).at(offset));
+            } else if order < self.last_order {
+                return Err(WasmError::SectionOutOfOrder(section_code).at(offset));
+            }
+            self.last_order = order;
+        }
 
-        let payload = self
-            .reader
-            .read_range(payload_len - name_len - (size_of_after - size_of_before))?
-            .to_vec();
+        let (mut reader, size_width) = parser_section_payload(&mut self.reader)?;
+        let section = match section_code {
+            0x01 => Section::Type {
+                entities: parser_section_type_entities(&mut reader)?,
+                size_width,
+            },
+            0x02 => {
+                let entities = parser_section_import_entities(&mut reader)?;
+                for entity in &entities {
+                    if let ImportKind::Func { .. } = entity.kind {
+                        self.imported_functions += 1;
+                    }
+                }
+                Section::Import {
+                    entities,
+                    size_width,
+                }
+            }
+            0x03 => Section::Function {
+                entities: parser_section_function_entities(&mut reader)?,
+                size_width,
+            },
+            0x04 => Section::Table {
+                entities: parser_section_table_entities(&mut reader)?,
+                size_width,
+            },
+            0x05 => Section::Memory {
+                entities: parser_section_memory_entities(&mut reader)?,
+                size_width,
+            },
+            0x06 => Section::Global {
+                entities: parser_section_global_entities(&mut reader)?,
+                size_width,
+            },
+            0x07 => Section::Export {
+                entities: parser_section_export_entities(&mut reader)?,
+                size_width,
+            },
+            0x08 => Section::Start {
+                signature_index: reader.read_var_u32()?,
+                size_width,
+            },
+            0x09 => Section::Element {
+                entities: parser_section_elem_entities(&mut reader)?,
+                size_width,
+            },
+            0x0A => Section::Code {
+                entities: parser_section_code_entities(
+                    &mut reader,
+                    self.imported_functions,
+                    self.lazy_bodies,
+                )?,
+                size_width,
+            },
+            0x0B => Section::Data {
+                entities: parser_section_data_entities(&mut reader)?,
+                size_width,
+            },
+            _ => Section::DataCount {
+                count: reader.read_var_u32()?,
+                size_width,
+            },
+        };
+        if !self.strict {
+            return Ok(section);
+        }
 
-        Ok(Section::Custom { name, payload })
+        if !reader.is_empty() {
+            return Err(WasmError::SectionSizeMismatch.at(reader.offset()));
+        }
+        match &section {
+            Section::Function { entities, .. } => self.functions = entities.len(),
+            Section::Code { entities, .. } if entities.len() != self.functions => {
+                return Err(WasmError::FunctionCodeCountMismatch {
+                    functions: self.functions,
+                    codes: entities.len(),
+                }
+                .at(offset));
+            }
+            Section::Code { .. } => self.code_read = true,
+            Section::DataCount { count, .. } => self.data_count = Some(*count),
+            Section::Data { entities, .. } => {
+                self.data_read = true;
+                match self.data_count {
+                    Some(count) if count as usize != entities.len() => {
+                        return Err(WasmError::DataCountMismatch {
+                            count,
+                            segments: entities.len(),
+                        }
+                        .at(offset));
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        Ok(section)
     }
-    fn section_type(&mut self) -> WasmResult<Section> {
-        let mut reader = parser_payload(&mut self.reader)?;
 
-        let entities = parser_section_type_entities(&mut reader)?;
-        Ok(Section::Type { entities })
+    fn section_custom(&mut self) -> WasmResult<Section<'a>> {
+        let (mut reader, size_width) = parser_section_payload(&mut self.reader)?;
+        let name_len = reader.read_var_u32()? as usize;
+        let name = reader.read_str(name_len)?;
+        let payload = reader.read_bytes(reader.remaining())?;
+
+        Ok(Section::Custom {
+            name,
+            payload,
+            size_width,
+        })
     }
+}
 
-    fn section_function(&mut self) -> WasmResult<Section> {
-        let mut reader = parser_payload(&mut self.reader)?;
+// Yields the frames of `read` up to the first error or the end, after which it
+// yields nothing. An incremental parser that needs more data yields an EOF
+// error, so one fed as data arrives is read with `read` instead.
+impl<'a> Iterator for Parser<'a> {
+    type Item = WasmResult<Payload<'a>>;
 
-        let count = reader.read_var_u32()? as usize;
-        let mut entities = Vec::with_capacity(count);
-        for _ in 0..count {
-            entities.push(SectionFuncEntity {
-                signature_index: reader.read_var_u32()? as usize,
-            });
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
         }
 
-        Ok(Section::Function { entities })
+        match self.read() {
+            Frame::Head { version } => Some(Ok(Payload::Head { version })),
+            Frame::Section { section, range } => Some(Ok(Payload::Section { section, range })),
+            Frame::ParserError { err } => {
+                self.finished = true;
+                Some(Err(err))
+            }
+            Frame::NeedMoreData { .. } => {
+                self.finished = true;
+                let offset = self.consumed + self.buffer.len();
+                Some(Err(WasmError::EOF.at(offset)))
+            }
+            Frame::End => {
+                self.finished = true;
+                None
+            }
+        }
     }
+}
 
-    fn section_export(&mut self) -> WasmResult<Section> {
-        let mut reader = parser_payload(&mut self.reader)?;
+// The input after the head or section being parsed.
+enum Input<'a> {
+    // the rest of the slice given to `Parser::new`
+    Borrowed(&'a [u8]),
+    // what was fed or read so far
+    Buffered(BytesMut),
+}
 
-        let entities = parser_section_export_entities(&mut reader)?;
-        Ok(Section::Export { entities })
+impl<'a> Input<'a> {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Input::Borrowed(input) => input,
+            Input::Buffered(buffer) => buffer,
+        }
     }
 
-    fn section_code(&mut self) -> WasmResult<Section> {
-        let mut reader = parser_payload(&mut self.reader)?;
-
-        let entities = parser_section_code_entities(&mut reader)?;
-        Ok(Section::Code { entities })
+    fn len(&self) -> usize {
+        self.as_slice().len()
     }
 
-    fn section_memory(&mut self) -> WasmResult<Section> {
-        let mut reader = parser_payload(&mut self.reader)?;
-
-        let entities = parser_section_memory_entities(&mut reader)?;
-        Ok(Section::Memory { entities })
+    fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
-    fn section_data(&mut self) -> WasmResult<Section> {
-        let mut reader = parser_payload(&mut self.reader)?;
-
-        let entities = parser_section_data_entities(&mut reader)?;
-        Ok(Section::Data { entities })
+    fn split_to(&mut self, len: usize) -> Code<'a> {
+        match self {
+            Input::Borrowed(input) => {
+                let code: &'a [u8] = input;
+                let (unit, rest) = code.split_at(len);
+                *input = rest;
+                Code::Borrowed(unit)
+            }
+            Input::Buffered(buffer) => Code::Shared(buffer.split_to(len).freeze()),
+        }
     }
 
-    fn section_import(&mut self) -> WasmResult<Section> {
-        let mut reader = parser_payload(&mut self.reader)?;
-
-        let entities = parser_section_import_entities(&mut reader)?;
-        Ok(Section::Import { entities })
+    // Feeding a parser of a slice copies what is left of it.
+    fn extend(&mut self, chunk: &[u8]) {
+        if let Input::Borrowed(input) = self {
+            *self = Input::Buffered(BytesMut::from(*input));
+        }
+        if let Input::Buffered(buffer) = self {
+            buffer.extend_from_slice(chunk);
+        }
     }
+}
 
-    fn section_table(&mut self) -> WasmResult<Section> {
-        let mut reader = parser_payload(&mut self.reader)?;
-
-        let entities = parser_section_table_entities(&mut reader)?;
-        Ok(Section::Table { entities })
+// The length of the section at the start of `buffer`, or `None` if its size is
+// not buffered yet. A malformed size takes the whole buffer, to fail parsing it.
+fn unit_len(buffer: &[u8]) -> Option<usize> {
+    let mut payload_len = 0u64;
+    for (index, byte) in buffer.iter().enumerate().skip(1) {
+        if index > 5 {
+            return Some(buffer.len());
+        }
+        payload_len |= u64::from(byte & 0x7F) << (7 * (index - 1));
+        if byte & 0x80 == 0 {
+            return Some(index + 1 + payload_len as usize);
+        }
     }
+    None
+}
 
-    fn section_global(&mut self) -> WasmResult<Section> {
-        let mut reader = parser_payload(&mut self.reader)?;
-
-        let entities = parser_section_global_entities(&mut reader)?;
-        Ok(Section::Global { entities })
+// The position of a non-custom section in a module, which must be increasing.
+fn section_order(section_code: u32) -> Option<u32> {
+    match section_code {
+        0x01..=0x09 => Some(section_code),
+        // the data count section comes between the element and code sections
+        0x0C => Some(0x0A),
+        0x0A | 0x0B => Some(section_code + 1),
+        _ => None,
     }
+}
 
-    fn section_start(&mut self) -> WasmResult<Section> {
-        let mut reader = parser_payload(&mut self.reader)?;
-        let signature_index = reader.read_var_u32()?;
-
-        Ok(Section::Start { signature_index })
+// Parses the payload of the "name" custom section. Unknown subsections are skipped.
+pub(crate) fn parser_names(payload: &[u8]) -> WasmResult<Names> {
+    let mut reader = BytesReader::new(Code::Borrowed(payload), 0);
+    let mut names = Names::default();
+    while !reader.is_empty() {
+        let id = reader.read_u8()?;
+        let mut subsection = parser_payload(&mut reader)?;
+        match id {
+            0x00 => {
+                let len = subsection.read_var_u32()? as usize;
+                names.module = Some(subsection.read_str(len)?.into_owned());
+            }
+            0x01 => names.functions = parser_name_map(&mut subsection)?,
+            0x02 => {
+                let count = subsection.read_var_u32()?;
+                for _ in 0..count {
+                    let function_index = subsection.read_var_u32()?;
+                    let locals = parser_name_map(&mut subsection)?;
+                    names.locals.insert(function_index, locals);
+                }
+            }
+            _ => continue,
+        }
     }
 
-    fn section_elem(&mut self) -> WasmResult<Section> {
-        let mut reader = parser_payload(&mut self.reader)?;
+    Ok(names)
+}
 
-        let entities = parser_section_elem_entities(&mut reader)?;
-        Ok(Section::Element { entities })
+fn parser_name_map(reader: &mut BytesReader) -> WasmResult<HashMap<u32, String>> {
+    let count = reader.read_var_u32()?;
+    let mut map = HashMap::new();
+    for _ in 0..count {
+        let index = reader.read_var_u32()?;
+        let len = reader.read_var_u32()? as usize;
+        map.insert(index, reader.read_str(len)?.into_owned());
     }
+
+    Ok(map)
 }
 
 fn parser_section_type_entities(reader: &mut BytesReader) -> WasmResult<Vec<SectionTypeEntity>> {
@@ -211,45 +547,97 @@ fn parser_section_type_entities(reader: &mut BytesReader) -> WasmResult<Vec<Sect
     Ok(entities)
 }
 
-fn parser_section_export_entities(
+fn parser_section_function_entities(
     reader: &mut BytesReader,
-) -> WasmResult<Vec<SectionExportEntity>> {
+) -> WasmResult<Vec<SectionFuncEntity>> {
     let count = reader.read_var_u32()? as usize;
 
     let mut entities = Vec::with_capacity(count);
     for _ in 0..count {
-        let name = String::from_utf8(parser_bytes(reader)?)?;
-        let kind = parser_external_kind(reader)?;
-
-        entities.push(SectionExportEntity { name, kind });
+        entities.push(SectionFuncEntity {
+            signature_index: reader.read_var_u32()? as usize,
+        });
     }
 
     Ok(entities)
 }
 
-fn parser_section_code_entities(reader: &mut BytesReader) -> WasmResult<Vec<SectionCodeEntity>> {
+fn parser_section_export_entities<'a>(
+    reader: &mut BytesReader<'a>,
+) -> WasmResult<Vec<SectionExportEntity<'a>>> {
     let count = reader.read_var_u32()? as usize;
 
     let mut entities = Vec::with_capacity(count);
     for _ in 0..count {
-        let mut payload_reader = parser_payload(reader)?;
-        let mut locals = vec![];
-        let decl_count = payload_reader.read_var_u32()? as usize;
+        let len = reader.read_var_u32()? as usize;
+        let name = reader.read_str(len)?;
+        let kind = parser_external_kind(reader)?;
 
-        for _ in 0..decl_count {
-            let local_count = payload_reader.read_var_u32()? as usize;
-            let t = parser_type(&mut payload_reader)?;
+        entities.push(SectionExportEntity { name, kind });
+    }
 
-            for _ in 0..local_count {
-                locals.push(t.clone());
+    Ok(entities)
+}
+
+// The bodies of the functions defined after `imported` imported ones.
+fn parser_section_code_entities<'a>(
+    reader: &mut BytesReader<'a>,
+    imported: u32,
+    lazy: bool,
+) -> WasmResult<Vec<SectionCodeEntity<'a>>> {
+    let count = reader.read_var_u32()?;
+
+    let mut entities = Vec::with_capacity(count as usize);
+    for function_index in imported..imported.saturating_add(count) {
+        let (payload, size_width) =
+            parser_section_payload(reader).map_err(|err| err.in_function(function_index))?;
+        let body = FunctionBody::new(payload, function_index, size_width);
+        let entity = if lazy {
+            SectionCodeEntity {
+                locals: vec![],
+                code: vec![],
+                range: body.range(),
+                code_ranges: vec![],
+                body: Some(body),
+                lazy: true,
             }
+        } else {
+            parser_section_code_entity(body)?
+        };
+        entities.push(entity);
+    }
+
+    Ok(entities)
+}
+
+// Decodes the locals and operators of `body`.
+pub(crate) fn parser_section_code_entity(body: FunctionBody) -> WasmResult<SectionCodeEntity> {
+    let mut locals = vec![];
+    let mut locals_reader = body.locals_reader()?;
+    for entry in &mut locals_reader {
+        let (local_count, t) = entry?;
+        for _ in 0..local_count {
+            locals.push(t);
         }
-        let code = parser_code(&mut payload_reader)?;
+    }
 
-        entities.push(SectionCodeEntity { locals, code });
+    let mut code = vec![];
+    let mut code_ranges = vec![];
+    let mut operators = locals_reader.into_operators_reader()?;
+    while let Some(operator) = operators.next() {
+        let (operator, offset) = operator?;
+        code.push(operator);
+        code_ranges.push(offset..operators.offset());
     }
 
-    Ok(entities)
+    Ok(SectionCodeEntity {
+        locals,
+        code,
+        range: body.range(),
+        code_ranges,
+        body: Some(body),
+        lazy: false,
+    })
 }
 
 fn parser_section_memory_entities(
@@ -267,24 +655,39 @@ fn parser_section_memory_entities(
     Ok(entities)
 }
 
-fn parser_section_data_entities(reader: &mut BytesReader) -> WasmResult<Vec<SectionDataEntity>> {
+fn parser_section_data_entities<'a>(
+    reader: &mut BytesReader<'a>,
+) -> WasmResult<Vec<SectionDataEntity<'a>>> {
     let count = reader.read_var_u32()? as usize;
 
     let mut entities = Vec::with_capacity(count);
     for _ in 0..count {
-        let memid = reader.read_var_u32()?;
-        let expr = parser_expr(reader)?;
+        let offset = reader.offset();
+        let (mode, memid, expr) = match reader.read_var_u32()? {
+            0 => (SegmentMode::Active, 0, parser_expr(reader)?),
+            1 => (SegmentMode::Passive, 0, vec![]),
+            2 => {
+                let memid = reader.read_var_u32()?;
+                (SegmentMode::Active, memid, parser_expr(reader)?)
+            }
+            flags => return Err(WasmError::InvalidSegmentFlags(flags).at(offset)),
+        };
         let data = parser_bytes(reader)?;
 
-        entities.push(SectionDataEntity { memid, expr, data });
+        entities.push(SectionDataEntity {
+            mode,
+            memid,
+            expr,
+            data,
+        });
     }
 
     Ok(entities)
 }
 
-fn parser_section_import_entities(
-    reader: &mut BytesReader,
-) -> WasmResult<Vec<SectionImportEntity>> {
+fn parser_section_import_entities<'a>(
+    reader: &mut BytesReader<'a>,
+) -> WasmResult<Vec<SectionImportEntity<'a>>> {
     let count = reader.read_var_u32()? as usize;
 
     let mut entities = Vec::with_capacity(count);
@@ -341,16 +744,46 @@ fn parser_section_elem_entities(reader: &mut BytesReader) -> WasmResult<Vec<Sect
 
     let mut entities = Vec::with_capacity(count);
     for _ in 0..count {
-        let table_index = reader.read_var_u32()?;
-        let offset = parser_expr(reader)?;
+        let position = reader.offset();
+        // bit 0 marks passive or declared segments, bit 1 an explicit table index
+        // or a declared segment, and bit 2 elements given as expressions
+        let flags = reader.read_var_u32()?;
+        let (mode, table_index, offset) = match flags {
+            0 | 4 => (SegmentMode::Active, 0, parser_expr(reader)?),
+            1 | 5 => (SegmentMode::Passive, 0, vec![]),
+            2 | 6 => {
+                let table_index = reader.read_var_u32()?;
+                (SegmentMode::Active, table_index, parser_expr(reader)?)
+            }
+            3 | 7 => (SegmentMode::Declared, 0, vec![]),
+            _ => return Err(WasmError::InvalidSegmentFlags(flags).at(position)),
+        };
+        let elem_type = match flags {
+            0 | 4 => Type::AnyFunc,
+            1..=3 => {
+                parser_elem_kind(reader)?;
+                Type::AnyFunc
+            }
+            _ => parser_ref_type(reader)?,
+        };
         let num_elem = reader.read_var_u32()?;
-        let elems = (0..num_elem)
-            .map(|_| reader.read_var_u32())
-            .collect::<WasmResult<Vec<u32>>>()?;
+        let elems = if flags & 4 == 0 {
+            let functions = (0..num_elem)
+                .map(|_| reader.read_var_u32())
+                .collect::<WasmResult<Vec<u32>>>()?;
+            ElemItems::Functions(functions)
+        } else {
+            let exprs = (0..num_elem)
+                .map(|_| parser_expr(reader))
+                .collect::<WasmResult<Vec<Vec<Operator>>>>()?;
+            ElemItems::Expressions(exprs)
+        };
 
         entities.push(SectionElemEntity {
+            mode,
             table_index,
             offset,
+            elem_type,
             elems,
         });
     }
@@ -358,7 +791,17 @@ fn parser_section_elem_entities(reader: &mut BytesReader) -> WasmResult<Vec<Sect
     Ok(entities)
 }
 
+// Reads the kind of the elements of a segment, where 0x00 stands for functions.
+fn parser_elem_kind(reader: &mut BytesReader) -> WasmResult<()> {
+    let offset = reader.offset();
+    match reader.read_var_u8()? {
+        0x00 => Ok(()),
+        kind => Err(WasmError::InvalidKindType(kind).at(offset)),
+    }
+}
+
 fn parser_type(reader: &mut BytesReader) -> WasmResult<Type> {
+    let offset = reader.offset();
     let type_code = reader.read_var_i8()?;
     match type_code {
         -0x01 => Ok(Type::I32),
@@ -366,15 +809,30 @@ fn parser_type(reader: &mut BytesReader) -> WasmResult<Type> {
         -0x03 => Ok(Type::F32),
         -0x04 => Ok(Type::F64),
 
-        -0x10 => Ok(Type::AnyRef),
+        -0x10 => Ok(Type::AnyFunc),
+        -0x11 => Ok(Type::AnyRef),
         -0x20 => Ok(Type::Func),
         -0x40 => Ok(Type::EmptyBlockType),
-        _ => Err(WasmError::InvalidType(type_code)),
+        _ => Err(WasmError::InvalidType(type_code).at(offset)),
+    }
+}
+
+fn parser_ref_type(reader: &mut BytesReader) -> WasmResult<Type> {
+    let offset = reader.offset();
+    let type_code = reader.read_var_i8()?;
+    match type_code {
+        -0x10 => Ok(Type::AnyFunc),
+        -0x11 => Ok(Type::AnyRef),
+        _ => Err(WasmError::InvalidType(type_code).at(offset)),
     }
 }
 
 fn parser_resizable_limit(reader: &mut BytesReader) -> WasmResult<ResizableLimit> {
+    let offset = reader.offset();
     let flag = reader.read_var_u8()?;
+    if flag > 1 && reader.is_strict() {
+        return Err(WasmError::MalformedLimitsFlags(flag).at(offset));
+    }
     if flag == 0 {
         let initial = reader.read_var_u32()?;
         Ok(ResizableLimit { initial, max: None })
@@ -390,6 +848,7 @@ fn parser_resizable_limit(reader: &mut BytesReader) -> WasmResult<ResizableLimit
 }
 
 fn parser_external_kind(reader: &mut BytesReader) -> WasmResult<ExternalKind> {
+    let offset = reader.offset();
     let kind_code = reader.read_var_u8()?;
     let kind = match kind_code {
         0x00 => ExternalKind::Func {
@@ -404,13 +863,14 @@ fn parser_external_kind(reader: &mut BytesReader) -> WasmResult<ExternalKind> {
         0x03 => ExternalKind::Global {
             global_index: reader.read_var_u32()?,
         },
-        _ => return Err(WasmError::InvalidKindType(kind_code)),
+        _ => return Err(WasmError::InvalidKindType(kind_code).at(offset)),
     };
 
     Ok(kind)
 }
 
 fn parser_import_kind(reader: &mut BytesReader) -> WasmResult<ImportKind> {
+    let offset = reader.offset();
     let kind_code = reader.read_var_u8()?;
     let kind = match kind_code {
         0x00 => ImportKind::Func {
@@ -427,7 +887,7 @@ fn parser_import_kind(reader: &mut BytesReader) -> WasmResult<ImportKind> {
         0x03 => ImportKind::Global {
             global_arg: parser_global_arg(reader)?,
         },
-        _ => return Err(WasmError::InvalidKindType(kind_code)),
+        _ => return Err(WasmError::InvalidKindType(kind_code).at(offset)),
     };
 
     Ok(kind)
@@ -435,7 +895,11 @@ fn parser_import_kind(reader: &mut BytesReader) -> WasmResult<ImportKind> {
 
 fn parser_global_arg(reader: &mut BytesReader) -> WasmResult<GlobalArg> {
     let content_type = parser_type(reader)?;
+    let offset = reader.offset();
     let mutability = reader.read_var_u8()?;
+    if mutability > 1 && reader.is_strict() {
+        return Err(WasmError::MalformedMutability(mutability).at(offset));
+    }
     let mutability = if mutability == 0 {
         Mutability::Const
     } else {
@@ -448,16 +912,22 @@ fn parser_global_arg(reader: &mut BytesReader) -> WasmResult<GlobalArg> {
     })
 }
 
-fn parser_payload(reader: &mut BytesReader) -> WasmResult<BytesReader> {
+fn parser_payload<'a>(reader: &mut BytesReader<'a>) -> WasmResult<BytesReader<'a>> {
     let payload_len = reader.read_var_u32()? as usize;
-    let payload = reader.read_range(payload_len)?;
+    reader.read_reader(payload_len)
+}
 
-    Ok(BytesReader::new(Bytes::from(payload), 0))
+// The payload together with the width of its size, which may be padded.
+fn parser_section_payload<'a>(reader: &mut BytesReader<'a>) -> WasmResult<(BytesReader<'a>, u8)> {
+    let offset = reader.offset();
+    let payload_len = reader.read_var_u32()? as usize;
+    let size_width = (reader.offset() - offset) as u8;
+    Ok((reader.read_reader(payload_len)?, size_width))
 }
 
-fn parser_bytes(reader: &mut BytesReader) -> WasmResult<Vec<u8>> {
+fn parser_bytes<'a>(reader: &mut BytesReader<'a>) -> WasmResult<Cow<'a, [u8]>> {
     let len = reader.read_var_u32()? as usize;
-    Ok(reader.read_range(len)?.to_vec())
+    reader.read_bytes(len)
 }
 
 fn parser_expr(reader: &mut BytesReader) -> WasmResult<Vec<Operator>> {
@@ -476,18 +946,6 @@ fn parser_expr(reader: &mut BytesReader) -> WasmResult<Vec<Operator>> {
     Ok(expr)
 }
 
-fn parser_code(reader: &mut BytesReader) -> WasmResult<Vec<Operator>> {
-    let mut expr = vec![];
-    loop {
-        expr.push(parser_operator(reader)?);
-        if reader.is_empty() {
-            break;
-        }
-    }
-
-    Ok(expr)
-}
-
 fn parser_memarg(reader: &mut BytesReader) -> WasmResult<MemArg> {
     let align = reader.read_var_u32()?;
     let offset = reader.read_var_u32()?;
@@ -495,7 +953,20 @@ fn parser_memarg(reader: &mut BytesReader) -> WasmResult<MemArg> {
     Ok(MemArg { align, offset })
 }
 
+// Reads a byte reserved for future extensions, which must be zero in strict mode.
+fn parser_reserved(reader: &mut BytesReader) -> WasmResult<u32> {
+    if !reader.is_strict() {
+        return reader.read_var_u8();
+    }
+    let offset = reader.offset();
+    match reader.read_u8()? {
+        0 => Ok(0),
+        _ => Err(WasmError::ZeroByteExpected.at(offset)),
+    }
+}
+
 fn parser_operator(reader: &mut BytesReader) -> WasmResult<Operator> {
+    let offset = reader.offset();
     let op_code = reader.read_u8()?;
     let op = match op_code {
         0x00 => Operator::Unreachable,
@@ -540,15 +1011,22 @@ fn parser_operator(reader: &mut BytesReader) -> WasmResult<Operator> {
         }
         0x11 => {
             let type_index = reader.read_var_u32()?;
-            let reserved = reader.read_var_u8()?;
+            let table_index = reader.read_var_u32()?;
             Operator::CallIndirect {
                 type_index,
-                reserved,
+                table_index,
             }
         }
 
         0x1A => Operator::Drop,
         0x1B => Operator::Select,
+        0x1C => {
+            let count = reader.read_var_u32()?;
+            let types = (0..count)
+                .map(|_| parser_type(reader))
+                .collect::<WasmResult<Vec<Type>>>()?;
+            Operator::TypedSelect { types }
+        }
 
         0x20 => {
             let local_index = reader.read_var_u32()?;
@@ -570,6 +1048,12 @@ fn parser_operator(reader: &mut BytesReader) -> WasmResult<Operator> {
             let global_index = reader.read_var_u32()?;
             Operator::GlobalSet { global_index }
         }
+        0x25 => Operator::TableGet {
+            table_index: reader.read_var_u32()?,
+        },
+        0x26 => Operator::TableSet {
+            table_index: reader.read_var_u32()?,
+        },
 
         0x28 => Operator::I32Load {
             memarg: parser_memarg(reader)?,
@@ -641,11 +1125,11 @@ fn parser_operator(reader: &mut BytesReader) -> WasmResult<Operator> {
             memarg: parser_memarg(reader)?,
         },
         0x3F => {
-            let size = reader.read_var_u8()?;
+            let size = parser_reserved(reader)?;
             Operator::MemorySize { size }
         }
         0x40 => {
-            let grow = reader.read_var_u8()?;
+            let grow = parser_reserved(reader)?;
             Operator::MemoryGrow { grow }
         }
 
@@ -797,7 +1281,72 @@ fn parser_operator(reader: &mut BytesReader) -> WasmResult<Operator> {
         0xBD => Operator::I64ReinterpretF64,
         0xBE => Operator::F32ReinterpretI32,
         0xBF => Operator::F64ReinterpretI64,
-        _ => return Err(WasmError::InvalidOperator(op_code)),
+        0xC0 => Operator::I32Extend8S,
+        0xC1 => Operator::I32Extend16S,
+        0xC2 => Operator::I64Extend8S,
+        0xC3 => Operator::I64Extend16S,
+        0xC4 => Operator::I64Extend32S,
+        0xD0 => Operator::RefNull {
+            t: parser_ref_type(reader)?,
+        },
+        0xD1 => Operator::RefIsNull,
+        0xD2 => Operator::RefFunc {
+            function_index: reader.read_var_u32()?,
+        },
+        0xFC => parser_misc_operator(reader, offset)?,
+        _ => return Err(WasmError::InvalidOperator(op_code).at(offset)),
+    };
+
+    Ok(op)
+}
+
+// Reads an operator of the `0xFC` prefix space, whose opcode follows as a LEB128 u32.
+fn parser_misc_operator(reader: &mut BytesReader, offset: usize) -> WasmResult<Operator> {
+    let op_code = reader.read_var_u32()?;
+    let op = match op_code {
+        0 => Operator::I32TruncSatSF32,
+        1 => Operator::I32TruncSatUF32,
+        2 => Operator::I32TruncSatSF64,
+        3 => Operator::I32TruncSatUF64,
+        4 => Operator::I64TruncSatSF32,
+        5 => Operator::I64TruncSatUF32,
+        6 => Operator::I64TruncSatSF64,
+        7 => Operator::I64TruncSatUF64,
+        8 => Operator::MemoryInit {
+            data_index: reader.read_var_u32()?,
+            mem: parser_reserved(reader)?,
+        },
+        9 => Operator::DataDrop {
+            data_index: reader.read_var_u32()?,
+        },
+        10 => Operator::MemoryCopy {
+            dst_mem: parser_reserved(reader)?,
+            src_mem: parser_reserved(reader)?,
+        },
+        11 => Operator::MemoryFill {
+            mem: parser_reserved(reader)?,
+        },
+        12 => Operator::TableInit {
+            elem_index: reader.read_var_u32()?,
+            table_index: reader.read_var_u32()?,
+        },
+        13 => Operator::ElemDrop {
+            elem_index: reader.read_var_u32()?,
+        },
+        14 => Operator::TableCopy {
+            dst_table: reader.read_var_u32()?,
+            src_table: reader.read_var_u32()?,
+        },
+        15 => Operator::TableGrow {
+            table_index: reader.read_var_u32()?,
+        },
+        16 => Operator::TableSize {
+            table_index: reader.read_var_u32()?,
+        },
+        17 => Operator::TableFill {
+            table_index: reader.read_var_u32()?,
+        },
+        _ => return Err(WasmError::InvalidPrefixedOperator(0xFC, op_code).at(offset)),
     };
 
     Ok(op)
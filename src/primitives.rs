@@ -1,27 +1,86 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
 use crate::errors::WasmError;
+use crate::parser::FunctionBody;
 
 #[derive(Debug)]
-pub enum Frame {
-    Head { version: u32 },
-    Section { section: Section },
-    ParserError { err: WasmError },
+pub enum Frame<'a> {
+    Head {
+        version: u32,
+    },
+    // `range` is the absolute byte range of the whole section, id included
+    Section {
+        section: Section<'a>,
+        range: Range<usize>,
+    },
+    ParserError {
+        err: WasmError,
+    },
+    // an incremental parser needs at least `hint` more bytes, or the end of the input
+    NeedMoreData {
+        hint: usize,
+    },
     End,
 }
 
-#[derive(Debug, Clone)]
+// What the `Parser` iterator yields: a frame that is neither an error nor the end.
+#[derive(Debug)]
+pub enum Payload<'a> {
+    Head {
+        version: u32,
+    },
+    // `range` is the absolute byte range of the whole section, id included
+    Section {
+        section: Section<'a>,
+        range: Range<usize>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Type {
     I32,
     I64,
     F32,
     F64,
     V128,
+    // a reference to a function, `funcref`
     AnyFunc,
+    // a reference to a host value, `externref`
     AnyRef,
     Func,
     EmptyBlockType,
 }
 
-#[derive(Debug)]
+impl Type {
+    pub fn is_ref(self) -> bool {
+        match self {
+            Type::AnyFunc | Type::AnyRef => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Type::I32 => "i32",
+            Type::I64 => "i64",
+            Type::F32 => "f32",
+            Type::F64 => "f64",
+            Type::V128 => "v128",
+            Type::AnyFunc => "funcref",
+            Type::AnyRef => "externref",
+            Type::Func => "func",
+            Type::EmptyBlockType => "empty",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum ExternalKind {
     Func { signature_index: u32 },
     Table { table_index: u32 },
@@ -46,26 +105,203 @@ pub enum ImportKind {
     },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Mutability {
     Const,
     Var,
 }
 
+// Names and bytes borrow from the input when it is parsed from a slice. Each
+// section keeps the width of the LEB-128 size it was read with, which object
+// files pad to 5 bytes; its size is encoded in at least that many bytes, so 0
+// encodes it minimally.
 #[derive(Debug)]
-pub enum Section {
-    Custom { name: String, payload: Vec<u8> },
-    Type { entities: Vec<SectionTypeEntity> },
-    Import { entities: Vec<SectionImportEntity> },
-    Function { entities: Vec<SectionFuncEntity> },
-    Table { entities: Vec<SectionTableEntity> },
-    Memory { entities: Vec<SectionMemoryEntity> },
-    Global { entities: Vec<SectionGlobalEntity> },
-    Export { entities: Vec<SectionExportEntity> },
-    Start { signature_index: u32 },
-    Element { entities: Vec<SectionElemEntity> },
-    Code { entities: Vec<SectionCodeEntity> },
-    Data { entities: Vec<SectionDataEntity> },
+pub enum Section<'a> {
+    Custom {
+        name: Cow<'a, str>,
+        payload: Cow<'a, [u8]>,
+        size_width: u8,
+    },
+    Type {
+        entities: Vec<SectionTypeEntity>,
+        size_width: u8,
+    },
+    Import {
+        entities: Vec<SectionImportEntity<'a>>,
+        size_width: u8,
+    },
+    Function {
+        entities: Vec<SectionFuncEntity>,
+        size_width: u8,
+    },
+    Table {
+        entities: Vec<SectionTableEntity>,
+        size_width: u8,
+    },
+    Memory {
+        entities: Vec<SectionMemoryEntity>,
+        size_width: u8,
+    },
+    Global {
+        entities: Vec<SectionGlobalEntity>,
+        size_width: u8,
+    },
+    Export {
+        entities: Vec<SectionExportEntity<'a>>,
+        size_width: u8,
+    },
+    Start {
+        signature_index: u32,
+        size_width: u8,
+    },
+    Element {
+        entities: Vec<SectionElemEntity>,
+        size_width: u8,
+    },
+    Code {
+        entities: Vec<SectionCodeEntity<'a>>,
+        size_width: u8,
+    },
+    Data {
+        entities: Vec<SectionDataEntity<'a>>,
+        size_width: u8,
+    },
+    // the number of data segments, read ahead of the code section
+    DataCount {
+        count: u32,
+        size_width: u8,
+    },
+}
+
+impl<'a> Section<'a> {
+    pub fn id(&self) -> u32 {
+        match self {
+            Section::Custom { .. } => 0x00,
+            Section::Type { .. } => 0x01,
+            Section::Import { .. } => 0x02,
+            Section::Function { .. } => 0x03,
+            Section::Table { .. } => 0x04,
+            Section::Memory { .. } => 0x05,
+            Section::Global { .. } => 0x06,
+            Section::Export { .. } => 0x07,
+            Section::Start { .. } => 0x08,
+            Section::Element { .. } => 0x09,
+            Section::Code { .. } => 0x0A,
+            Section::Data { .. } => 0x0B,
+            Section::DataCount { .. } => 0x0C,
+        }
+    }
+
+    pub fn size_width(&self) -> u8 {
+        match self {
+            Section::Custom { size_width, .. }
+            | Section::Type { size_width, .. }
+            | Section::Import { size_width, .. }
+            | Section::Function { size_width, .. }
+            | Section::Table { size_width, .. }
+            | Section::Memory { size_width, .. }
+            | Section::Global { size_width, .. }
+            | Section::Export { size_width, .. }
+            | Section::Start { size_width, .. }
+            | Section::Element { size_width, .. }
+            | Section::Code { size_width, .. }
+            | Section::Data { size_width, .. }
+            | Section::DataCount { size_width, .. } => *size_width,
+        }
+    }
+
+    // Copies what is borrowed from the input.
+    pub fn into_owned(self) -> Section<'static> {
+        match self {
+            Section::Custom {
+                name,
+                payload,
+                size_width,
+            } => Section::Custom {
+                name: Cow::Owned(name.into_owned()),
+                payload: Cow::Owned(payload.into_owned()),
+                size_width,
+            },
+            Section::Type {
+                entities,
+                size_width,
+            } => Section::Type {
+                entities,
+                size_width,
+            },
+            Section::Import {
+                entities,
+                size_width,
+            } => Section::Import {
+                entities: entities.into_iter().map(|e| e.into_owned()).collect(),
+                size_width,
+            },
+            Section::Function {
+                entities,
+                size_width,
+            } => Section::Function {
+                entities,
+                size_width,
+            },
+            Section::Table {
+                entities,
+                size_width,
+            } => Section::Table {
+                entities,
+                size_width,
+            },
+            Section::Memory {
+                entities,
+                size_width,
+            } => Section::Memory {
+                entities,
+                size_width,
+            },
+            Section::Global {
+                entities,
+                size_width,
+            } => Section::Global {
+                entities,
+                size_width,
+            },
+            Section::Export {
+                entities,
+                size_width,
+            } => Section::Export {
+                entities: entities.into_iter().map(|e| e.into_owned()).collect(),
+                size_width,
+            },
+            Section::Start {
+                signature_index,
+                size_width,
+            } => Section::Start {
+                signature_index,
+                size_width,
+            },
+            Section::Element {
+                entities,
+                size_width,
+            } => Section::Element {
+                entities,
+                size_width,
+            },
+            Section::Code {
+                entities,
+                size_width,
+            } => Section::Code {
+                entities: entities.into_iter().map(|e| e.into_owned()).collect(),
+                size_width,
+            },
+            Section::Data {
+                entities,
+                size_width,
+            } => Section::Data {
+                entities: entities.into_iter().map(|e| e.into_owned()).collect(),
+                size_width,
+            },
+            Section::DataCount { count, size_width } => Section::DataCount { count, size_width },
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -115,12 +351,16 @@ pub enum Operator {
     // call a function indirect with an expected signature
     CallIndirect {
         type_index: u32,
-        reserved: u32,
+        table_index: u32,
     }, // 0X11
 
     // Parametric Instructions
     Drop,   // 0x1A
     Select, // 0X1B
+    // select with explicit operand types, which may be references
+    TypedSelect {
+        types: Vec<Type>,
+    }, // 0x1C
 
     // Variable Instructions¶
     LocalGet {
@@ -139,6 +379,14 @@ pub enum Operator {
         global_index: u32,
     }, // 0x24
 
+    // Table Instructions
+    TableGet {
+        table_index: u32,
+    }, // 0x25
+    TableSet {
+        table_index: u32,
+    }, // 0x26
+
     // Memory Instructions
     I32Load {
         memarg: MemArg,
@@ -361,6 +609,76 @@ pub enum Operator {
     I64ReinterpretF64, // 0xBD
     F32ReinterpretI32, // 0xBE
     F64ReinterpretI64, // 0xBF
+    I32Extend8S,       // 0xC0
+    I32Extend16S,      // 0xC1
+    I64Extend8S,       // 0xC2
+    I64Extend16S,      // 0xC3
+    I64Extend32S,      // 0xC4
+
+    // Reference Instructions
+    // the null reference of a reference type
+    RefNull {
+        t: Type,
+    }, // 0xD0
+    RefIsNull, // 0xD1
+    // a reference to a function
+    RefFunc {
+        function_index: u32,
+    }, // 0xD2
+
+    I32TruncSatSF32, // 0xFC 0
+    I32TruncSatUF32, // 0xFC 1
+    I32TruncSatSF64, // 0xFC 2
+    I32TruncSatUF64, // 0xFC 3
+    I64TruncSatSF32, // 0xFC 4
+    I64TruncSatUF32, // 0xFC 5
+    I64TruncSatSF64, // 0xFC 6
+    I64TruncSatUF64, // 0xFC 7
+
+    // Bulk Memory Instructions
+    // copy a range of a data segment into memory
+    MemoryInit {
+        data_index: u32,
+        mem: u32,
+    }, // 0xFC 8
+    // discard the bytes of a data segment
+    DataDrop {
+        data_index: u32,
+    }, // 0xFC 9
+    // copy a possibly overlapping range of memory
+    MemoryCopy {
+        dst_mem: u32,
+        src_mem: u32,
+    }, // 0xFC 10
+    // set a range of memory to a byte
+    MemoryFill {
+        mem: u32,
+    }, // 0xFC 11
+    // copy a range of an element segment into a table
+    TableInit {
+        elem_index: u32,
+        table_index: u32,
+    }, // 0xFC 12
+    // discard the elements of an element segment
+    ElemDrop {
+        elem_index: u32,
+    }, // 0xFC 13
+    // copy a possibly overlapping range of a table
+    TableCopy {
+        dst_table: u32,
+        src_table: u32,
+    }, // 0xFC 14
+    // grow a table by a number of elements, returning the old size or -1
+    TableGrow {
+        table_index: u32,
+    }, // 0xFC 15
+    TableSize {
+        table_index: u32,
+    }, // 0xFC 16
+    // set a range of a table to a reference
+    TableFill {
+        table_index: u32,
+    }, // 0xFC 17
 }
 
 #[derive(Debug)]
@@ -376,15 +694,47 @@ pub struct SectionFuncEntity {
 }
 
 #[derive(Debug)]
-pub struct SectionExportEntity {
-    pub name: String,
+pub struct SectionExportEntity<'a> {
+    pub name: Cow<'a, str>,
     pub kind: ExternalKind,
 }
 
+impl<'a> SectionExportEntity<'a> {
+    pub fn into_owned(self) -> SectionExportEntity<'static> {
+        SectionExportEntity {
+            name: Cow::Owned(self.name.into_owned()),
+            kind: self.kind,
+        }
+    }
+}
+
 #[derive(Debug)]
-pub struct SectionCodeEntity {
+pub struct SectionCodeEntity<'a> {
+    // empty if the body is parsed lazily
     pub locals: Vec<Type>,
+    // empty if the body is parsed lazily
     pub code: Vec<Operator>,
+    // absolute byte range of the function body, local declarations included
+    pub range: Range<usize>,
+    // absolute byte range of each operator in `code`
+    pub code_ranges: Vec<Range<usize>>,
+    // the binary body, `None` for a function that was not parsed from one
+    pub body: Option<FunctionBody<'a>>,
+    // whether `locals` and `code` were left to be read from `body`
+    pub lazy: bool,
+}
+
+impl<'a> SectionCodeEntity<'a> {
+    pub fn into_owned(self) -> SectionCodeEntity<'static> {
+        SectionCodeEntity {
+            locals: self.locals,
+            code: self.code,
+            range: self.range,
+            code_ranges: self.code_ranges,
+            body: self.body.map(FunctionBody::into_owned),
+            lazy: self.lazy,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -392,20 +742,54 @@ pub struct SectionMemoryEntity {
     pub limit: ResizableLimit,
 }
 
+// When the contents of a data or element segment are copied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentMode {
+    // into its memory or table at instantiation
+    Active,
+    // only by `memory.init` or `table.init`
+    Passive,
+    // never, the element segment only declares the functions `ref.func` refers to
+    Declared,
+}
+
 #[derive(Debug)]
-pub struct SectionDataEntity {
+pub struct SectionDataEntity<'a> {
+    pub mode: SegmentMode,
+    // the memory and offset of active segments, 0 and empty for passive ones
     pub memid: u32,
     pub expr: Vec<Operator>,
-    pub data: Vec<u8>,
+    pub data: Cow<'a, [u8]>,
+}
+
+impl<'a> SectionDataEntity<'a> {
+    pub fn into_owned(self) -> SectionDataEntity<'static> {
+        SectionDataEntity {
+            mode: self.mode,
+            memid: self.memid,
+            expr: self.expr,
+            data: Cow::Owned(self.data.into_owned()),
+        }
+    }
 }
 
 #[derive(Debug)]
-pub struct SectionImportEntity {
-    pub mod_name: String,
-    pub field_name: String,
+pub struct SectionImportEntity<'a> {
+    pub mod_name: Cow<'a, str>,
+    pub field_name: Cow<'a, str>,
     pub kind: ImportKind,
 }
 
+impl<'a> SectionImportEntity<'a> {
+    pub fn into_owned(self) -> SectionImportEntity<'static> {
+        SectionImportEntity {
+            mod_name: Cow::Owned(self.mod_name.into_owned()),
+            field_name: Cow::Owned(self.field_name.into_owned()),
+            kind: self.kind,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SectionTableEntity {
     pub elem_type: Type,
@@ -422,12 +806,47 @@ pub struct SectionGlobalEntity {
 
 #[derive(Debug)]
 pub struct SectionElemEntity {
-    // the table index (0 in the MVP)
+    pub mode: SegmentMode,
+    // the table index, 0 for passive and declared segments
     pub table_index: u32,
-    // an i32 initializer expression that computes the offset at which to place the elements
+    // an i32 initializer expression that computes the offset at which to place the
+    // elements, empty for passive and declared segments
     pub offset: Vec<Operator>,
-    // 	number of elements to follow
-    pub elems: Vec<u32>,
+    // the reference type of the elements
+    pub elem_type: Type,
+    pub elems: ElemItems,
+}
+
+// The elements of an element segment.
+#[derive(Debug)]
+pub enum ElemItems {
+    // references to functions, of type `funcref`
+    Functions(Vec<u32>),
+    // constant expressions of the element type, e.g. `ref.func` or `ref.null`
+    Expressions(Vec<Vec<Operator>>),
+}
+
+impl ElemItems {
+    pub fn len(&self) -> usize {
+        match self {
+            ElemItems::Functions(functions) => functions.len(),
+            ElemItems::Expressions(exprs) => exprs.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// The debug names of the "name" custom section.
+#[derive(Debug, Default)]
+pub struct Names {
+    pub module: Option<String>,
+    // function index to name
+    pub functions: HashMap<u32, String>,
+    // function index to the names of its locals, parameters included
+    pub locals: HashMap<u32, HashMap<u32, String>>,
 }
 
 #[derive(Debug)]
@@ -0,0 +1,152 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use crate::primitives::{SectionTypeEntity, Type};
+use crate::runtime::interpreter::Interpreter;
+use crate::runtime::{InstanceData, Trap, Value, WasmParams, WasmResults};
+
+// The parameter and result types of a function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuncType {
+    pub params: Vec<Type>,
+    pub returns: Vec<Type>,
+}
+
+impl FuncType {
+    pub fn new(params: Vec<Type>, returns: Vec<Type>) -> Self {
+        Self { params, returns }
+    }
+}
+
+impl<'a> From<&'a SectionTypeEntity> for FuncType {
+    fn from(entity: &'a SectionTypeEntity) -> Self {
+        Self::new(entity.params.clone(), entity.returns.clone())
+    }
+}
+
+pub type HostFunc = dyn Fn(&[Value]) -> Result<Vec<Value>, Trap>;
+
+// A function that can be imported and called, either a Rust closure or a
+// function of an instance. Clones refer to the same function.
+#[derive(Clone)]
+pub struct Func {
+    inner: Rc<FuncInner>,
+}
+
+struct FuncInner {
+    ty: FuncType,
+    kind: FuncKind,
+}
+
+pub(crate) enum FuncKind {
+    Host(Box<HostFunc>),
+    // the function keeps its instance alive wherever it is stored, as the spec
+    // requires; one kept in a table or global of its own instance forms a cycle
+    // that is only freed with the process
+    Wasm {
+        instance: Rc<InstanceData>,
+        function_index: u32,
+    },
+}
+
+impl Func {
+    // A host function, which is trusted to return values of the types in `ty`.
+    pub fn new<F>(ty: FuncType, f: F) -> Self
+    where
+        F: Fn(&[Value]) -> Result<Vec<Value>, Trap> + 'static,
+    {
+        Self::from_kind(ty, FuncKind::Host(Box::new(f)))
+    }
+
+    pub(crate) fn from_kind(ty: FuncType, kind: FuncKind) -> Self {
+        Self {
+            inner: Rc::new(FuncInner { ty, kind }),
+        }
+    }
+
+    pub fn ty(&self) -> &FuncType {
+        &self.inner.ty
+    }
+
+    pub(crate) fn kind(&self) -> &FuncKind {
+        &self.inner.kind
+    }
+
+    pub fn call(&self, args: &[Value]) -> Result<Vec<Value>, Trap> {
+        if !matches_types(args, &self.inner.ty.params) {
+            return Err(Trap::ArgumentTypeMismatch);
+        }
+        match &self.inner.kind {
+            FuncKind::Host(f) => f(args),
+            FuncKind::Wasm {
+                instance,
+                function_index,
+            } => Interpreter::new().invoke(instance, *function_index, args),
+        }
+    }
+}
+
+// A function whose signature was checked against Rust parameter and result
+// types, e.g. `TypedFunc<(i32, i64), f64>`.
+pub struct TypedFunc<Params, Results> {
+    func: Func,
+    marker: PhantomData<fn(Params) -> Results>,
+}
+
+impl<Params: WasmParams, Results: WasmResults> TypedFunc<Params, Results> {
+    pub fn new(func: Func) -> Result<Self, Trap> {
+        if func.ty().params != Params::types() {
+            return Err(Trap::ArgumentTypeMismatch);
+        }
+        if func.ty().returns != Results::types() {
+            return Err(Trap::ResultTypeMismatch);
+        }
+        Ok(Self {
+            func,
+            marker: PhantomData,
+        })
+    }
+
+    pub fn func(&self) -> &Func {
+        &self.func
+    }
+
+    pub fn call(&self, params: Params) -> Result<Results, Trap> {
+        let results = self.func.call(&params.into_values())?;
+        Ok(Results::from_values(results).expect("result types checked"))
+    }
+}
+
+// Functions of an instance are equal if they have the same index, other functions
+// only if they are clones.
+impl PartialEq for Func {
+    fn eq(&self, other: &Self) -> bool {
+        if Rc::ptr_eq(&self.inner, &other.inner) {
+            return true;
+        }
+        match (self.kind(), other.kind()) {
+            (
+                FuncKind::Wasm {
+                    instance,
+                    function_index,
+                },
+                FuncKind::Wasm {
+                    instance: other_instance,
+                    function_index: other_index,
+                },
+            ) => function_index == other_index && Rc::ptr_eq(instance, other_instance),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for Func {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Func").field("ty", &self.inner.ty).finish()
+    }
+}
+
+pub(crate) fn matches_types(values: &[Value], types: &[Type]) -> bool {
+    values.len() == types.len() && values.iter().map(Value::ty).eq(types.iter().cloned())
+}
@@ -0,0 +1,81 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::primitives::{Mutability, Operator, Type};
+use crate::runtime::Value;
+use crate::{WasmError, WasmResult};
+
+// A global variable.
+#[derive(Debug)]
+pub struct Global {
+    value: Value,
+    mutability: Mutability,
+}
+
+impl Global {
+    pub fn new(value: Value, mutability: Mutability) -> Self {
+        Self { value, mutability }
+    }
+
+    pub fn get(&self) -> Value {
+        self.value.clone()
+    }
+
+    pub fn ty(&self) -> Type {
+        self.value.ty()
+    }
+
+    pub fn mutability(&self) -> Mutability {
+        self.mutability
+    }
+
+    // Stores a value of the global's type, only mutable globals can be set.
+    pub fn set(&mut self, value: Value) -> WasmResult<()> {
+        if self.mutability != Mutability::Var {
+            return Err(WasmError::SetConstGlobal);
+        }
+        if value.ty() != self.ty() {
+            return Err(WasmError::TypeMismatch {
+                expected: Some(self.ty()),
+                actual: Some(value.ty()),
+            });
+        }
+        self.value = value;
+        Ok(())
+    }
+
+    // Sets the initial value of a global whose initializer refers to its instance.
+    pub(crate) fn initialize(&mut self, value: Value) {
+        self.value = value;
+    }
+}
+
+// Evaluates a constant expression: a single constant, `ref.null` or `global.get`
+// of an immutable global in `imported`, followed by `end`. `ref.func` needs the
+// instance and is left to the caller.
+pub(crate) fn evaluate(expr: &[Operator], imported: &[Rc<RefCell<Global>>]) -> WasmResult<Value> {
+    let value = match expr {
+        [op, Operator::End] => match op {
+            Operator::I32Const { val } => Value::I32(*val),
+            Operator::I64Const { val } => Value::I64(*val),
+            Operator::F32Const { val } => Value::F32(f32::from_bits(*val)),
+            Operator::F64Const { val } => Value::F64(f64::from_bits(*val)),
+            Operator::RefNull { t } => {
+                Value::zero(*t).ok_or_else(|| WasmError::InvalidValueType(*t))?
+            }
+            Operator::GlobalGet { global_index } => {
+                let global = imported
+                    .get(*global_index as usize)
+                    .ok_or_else(|| WasmError::UnknownGlobal(*global_index))?
+                    .borrow();
+                if global.mutability() != Mutability::Const {
+                    return Err(WasmError::ConstantExpressionRequired);
+                }
+                global.get()
+            }
+            _ => return Err(WasmError::ConstantExpressionRequired),
+        },
+        _ => return Err(WasmError::ConstantExpressionRequired),
+    };
+    Ok(value)
+}
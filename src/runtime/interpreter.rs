@@ -0,0 +1,911 @@
+// comparisons of floats are exact in WebAssembly
+#![allow(clippy::float_cmp)]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::primitives::{MemArg, Operator, Type};
+use crate::runtime::func::{matches_types, FuncKind};
+use crate::runtime::value::WasmType;
+use crate::runtime::{elem_values, func, Func, InstanceData, Memory, Table, Trap, Value};
+
+// The deepest nesting of calls before the call stack is considered exhausted.
+const MAX_CALL_DEPTH: usize = 16 * 1024;
+
+// Where the structured instruction at an operator index continues.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Jump {
+    // index of the matching `end`
+    end: usize,
+    // index of the `else` of an `if`
+    else_index: Option<usize>,
+}
+
+// Matches each `block`, `loop`, `if` and `else` of a validated body with its `end`.
+pub(crate) fn jumps(code: &[Operator]) -> Vec<Jump> {
+    let mut jumps = vec![Jump::default(); code.len()];
+    let mut open = vec![];
+    for (index, op) in code.iter().enumerate() {
+        match op {
+            Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. } => {
+                open.push(index)
+            }
+            Operator::Else => {
+                if let Some(&start) = open.last() {
+                    jumps[start].else_index = Some(index);
+                }
+            }
+            Operator::End => {
+                if let Some(start) = open.pop() {
+                    jumps[start].end = index;
+                    if let Some(else_index) = jumps[start].else_index {
+                        jumps[else_index].end = index;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    jumps
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Label {
+    // number of values a branch to the label carries
+    arity: usize,
+    // height of the operand stack when the label was entered
+    height: usize,
+    // operator index a branch to the label continues at
+    continuation: usize,
+    // branching to a loop restarts it, so its label stays on the stack
+    is_loop: bool,
+}
+
+struct Frame {
+    instance: Rc<InstanceData>,
+    // index of the function among the functions defined by the module
+    defined_index: usize,
+    // index of the next operator
+    pc: usize,
+    // parameters first
+    locals: Vec<Value>,
+    // height of the label stack below the label of the function body
+    label_height: usize,
+}
+
+// Executes functions with an explicit stack of frames, so deep recursion in
+// wasm does not recurse natively. Each frame executes in its own instance,
+// calls to imported functions may cross into another one.
+pub(crate) struct Interpreter {
+    stack: Vec<Value>,
+    labels: Vec<Label>,
+    frames: Vec<Frame>,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self {
+            stack: vec![],
+            labels: vec![],
+            frames: vec![],
+        }
+    }
+
+    // Runs function `function_index` of `instance` with arguments matching its signature.
+    pub fn invoke(
+        mut self,
+        instance: &Rc<InstanceData>,
+        function_index: u32,
+        args: &[Value],
+    ) -> Result<Vec<Value>, Trap> {
+        self.stack.extend_from_slice(args);
+        self.call(instance, function_index)?;
+        while let Some(frame) = self.frames.last_mut() {
+            let instance = Rc::clone(&frame.instance);
+            let pc = frame.pc;
+            let code = &instance.module.codes[frame.defined_index].code;
+            match code.get(pc) {
+                Some(op) => {
+                    frame.pc += 1;
+                    let jump = instance.jumps[frame.defined_index][pc];
+                    self.execute(&instance, op, pc, jump)?;
+                }
+                // the end of the body was executed or branched to, the results are on the stack
+                None => {
+                    self.frames.pop();
+                }
+            }
+        }
+        Ok(self.stack)
+    }
+
+    fn call(&mut self, instance: &Rc<InstanceData>, function_index: u32) -> Result<(), Trap> {
+        let imported = &instance.imported_funcs;
+        if let Some(func) = imported.get(function_index as usize) {
+            return self.call_func(func);
+        }
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(Trap::CallStackExhausted);
+        }
+
+        let defined_index = function_index as usize - imported.len();
+        let module = &instance.module;
+        let signature = &module.types[module.functions[defined_index].signature_index];
+        let body = &module.codes[defined_index];
+        let mut locals = self
+            .stack
+            .split_off(self.stack.len() - signature.params.len());
+        locals.extend(body.locals.iter().filter_map(|t| Value::zero(*t)));
+        self.labels.push(Label {
+            arity: signature.returns.len(),
+            height: self.stack.len(),
+            continuation: body.code.len(),
+            is_loop: false,
+        });
+        self.frames.push(Frame {
+            instance: Rc::clone(instance),
+            defined_index,
+            pc: 0,
+            locals,
+            label_height: self.labels.len() - 1,
+        });
+        Ok(())
+    }
+
+    fn call_func(&mut self, func: &Func) -> Result<(), Trap> {
+        match func.kind() {
+            FuncKind::Host(f) => {
+                let ty = func.ty();
+                let args = self.stack.split_off(self.stack.len() - ty.params.len());
+                let results = f(&args)?;
+                if !matches_types(&results, &ty.returns) {
+                    return Err(Trap::ResultTypeMismatch);
+                }
+                self.stack.extend(results);
+                Ok(())
+            }
+            FuncKind::Wasm {
+                instance,
+                function_index,
+            } => self.call(instance, *function_index),
+        }
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("no frame")
+    }
+
+    fn push_label(&mut self, arity: usize, continuation: usize, is_loop: bool) {
+        let height = self.stack.len();
+        self.labels.push(Label {
+            arity,
+            height,
+            continuation,
+            is_loop,
+        });
+    }
+
+    // Branches to the label `depth` levels up, keeping the values it carries.
+    fn branch(&mut self, depth: u32) {
+        let index = self.labels.len() - 1 - depth as usize;
+        let label = self.labels[index];
+        let values = self.stack.len() - label.arity;
+        self.stack.drain(label.height..values);
+        self.labels
+            .truncate(if label.is_loop { index + 1 } else { index });
+        self.frame().pc = label.continuation;
+    }
+
+    fn execute(
+        &mut self,
+        instance: &Rc<InstanceData>,
+        op: &Operator,
+        pc: usize,
+        jump: Jump,
+    ) -> Result<(), Trap> {
+        match op {
+            Operator::Unreachable => return Err(Trap::Unreachable),
+            Operator::Nop => {}
+            Operator::Block { t } => self.push_label(block_arity(*t), jump.end + 1, false),
+            Operator::Loop { .. } => self.push_label(0, pc + 1, true),
+            Operator::If { t } => {
+                let condition: i32 = self.pop();
+                self.push_label(block_arity(*t), jump.end + 1, false);
+                if condition == 0 {
+                    // without an `else` the `end` pops the label
+                    self.frame().pc = jump.else_index.map_or(jump.end, |index| index + 1);
+                }
+            }
+            Operator::Else => self.frame().pc = jump.end,
+            Operator::End => {
+                self.labels.pop();
+            }
+            Operator::Br { relative_depth } => self.branch(*relative_depth),
+            Operator::BrIf { relative_depth } => {
+                if self.pop::<i32>() != 0 {
+                    self.branch(*relative_depth);
+                }
+            }
+            Operator::BrTable {
+                target_table,
+                default_target,
+            } => {
+                let index = self.pop::<i32>() as u32 as usize;
+                let depth = target_table.get(index).unwrap_or(default_target);
+                self.branch(*depth);
+            }
+            Operator::Return => {
+                let depth = self.labels.len() - 1 - self.frame().label_height;
+                self.branch(depth as u32);
+            }
+            Operator::Call { function_index } => self.call(instance, *function_index)?,
+            Operator::CallIndirect {
+                type_index,
+                table_index,
+            } => {
+                let index = self.pop::<i32>() as u32;
+                let func = table(instance, *table_index)
+                    .borrow()
+                    .get(index)?
+                    .ok_or(Trap::UninitializedElement)?;
+                let expected = &instance.module.types[*type_index as usize];
+                if expected.params != func.ty().params || expected.returns != func.ty().returns {
+                    return Err(Trap::IndirectCallTypeMismatch);
+                }
+                self.call_func(&func)?;
+            }
+
+            Operator::Drop => {
+                self.pop_value();
+            }
+            Operator::Select | Operator::TypedSelect { .. } => {
+                let condition: i32 = self.pop();
+                let second = self.pop_value();
+                let first = self.pop_value();
+                self.push(if condition != 0 { first } else { second });
+            }
+
+            Operator::LocalGet { local_index } => {
+                let value = self.frame().locals[*local_index as usize].clone();
+                self.push(value);
+            }
+            Operator::LocalSet { local_index } => {
+                let value = self.pop_value();
+                self.frame().locals[*local_index as usize] = value;
+            }
+            Operator::LocalTee { local_index } => {
+                let value = self.stack.last().expect("operand stack underflow").clone();
+                self.frame().locals[*local_index as usize] = value;
+            }
+            Operator::GlobalGet { global_index } => {
+                let value = instance.globals[*global_index as usize].borrow().get();
+                self.push(value);
+            }
+            Operator::GlobalSet { global_index } => {
+                let value = self.pop_value();
+                instance.globals[*global_index as usize]
+                    .borrow_mut()
+                    .set(value)
+                    .expect("validated global.set");
+            }
+
+            Operator::TableGet { table_index } => {
+                let index = self.pop::<i32>() as u32;
+                let value = table(instance, *table_index).borrow().get_ref(index);
+                self.push(value.map_err(|_| Trap::TableOutOfBounds)?);
+            }
+            Operator::TableSet { table_index } => {
+                let value = self.pop_value();
+                let index = self.pop::<i32>() as u32;
+                table(instance, *table_index)
+                    .borrow_mut()
+                    .set_ref(index, value)
+                    .map_err(|_| Trap::TableOutOfBounds)?;
+            }
+            Operator::TableSize { table_index } => {
+                let size = table(instance, *table_index).borrow().size();
+                self.push(size as i32);
+            }
+            Operator::TableGrow { table_index } => {
+                let delta = self.pop::<i32>() as u32;
+                let init = self.pop_value();
+                let result = table(instance, *table_index)
+                    .borrow_mut()
+                    .grow_with(delta, init);
+                self.push(result.map_or(-1, |size| size as i32));
+            }
+            Operator::TableFill { table_index } => {
+                let len = self.pop::<i32>() as u32;
+                let value = self.pop_value();
+                let index = self.pop::<i32>() as u32;
+                table(instance, *table_index)
+                    .borrow_mut()
+                    .fill(index, value, len)?;
+            }
+
+            Operator::I32Load { memarg } => {
+                let bytes = self.load(instance, memarg, 4)?;
+                self.push(LittleEndian::read_i32(&bytes));
+            }
+            Operator::I64Load { memarg } => {
+                let bytes = self.load(instance, memarg, 8)?;
+                self.push(LittleEndian::read_i64(&bytes));
+            }
+            Operator::F32Load { memarg } => {
+                let bytes = self.load(instance, memarg, 4)?;
+                self.push(f32::from_bits(LittleEndian::read_u32(&bytes)));
+            }
+            Operator::F64Load { memarg } => {
+                let bytes = self.load(instance, memarg, 8)?;
+                self.push(f64::from_bits(LittleEndian::read_u64(&bytes)));
+            }
+            Operator::I32Load8s { memarg } => {
+                let bytes = self.load(instance, memarg, 1)?;
+                self.push(i32::from(bytes[0] as i8));
+            }
+            Operator::I32Load8u { memarg } => {
+                let bytes = self.load(instance, memarg, 1)?;
+                self.push(i32::from(bytes[0]));
+            }
+            Operator::I32Load16s { memarg } => {
+                let bytes = self.load(instance, memarg, 2)?;
+                self.push(i32::from(LittleEndian::read_i16(&bytes)));
+            }
+            Operator::I32Load16u { memarg } => {
+                let bytes = self.load(instance, memarg, 2)?;
+                self.push(i32::from(LittleEndian::read_u16(&bytes)));
+            }
+            Operator::I64Load8s { memarg } => {
+                let bytes = self.load(instance, memarg, 1)?;
+                self.push(i64::from(bytes[0] as i8));
+            }
+            Operator::I64Load8u { memarg } => {
+                let bytes = self.load(instance, memarg, 1)?;
+                self.push(i64::from(bytes[0]));
+            }
+            Operator::I64Load16s { memarg } => {
+                let bytes = self.load(instance, memarg, 2)?;
+                self.push(i64::from(LittleEndian::read_i16(&bytes)));
+            }
+            Operator::I64Load16u { memarg } => {
+                let bytes = self.load(instance, memarg, 2)?;
+                self.push(i64::from(LittleEndian::read_u16(&bytes)));
+            }
+            Operator::I64Load32s { memarg } => {
+                let bytes = self.load(instance, memarg, 4)?;
+                self.push(i64::from(LittleEndian::read_i32(&bytes)));
+            }
+            Operator::I64Load32u { memarg } => {
+                let bytes = self.load(instance, memarg, 4)?;
+                self.push(i64::from(LittleEndian::read_u32(&bytes)));
+            }
+            Operator::I32Store { memarg } => {
+                let value: i32 = self.pop();
+                self.store(instance, memarg, &value.to_le_bytes())?;
+            }
+            Operator::I64Store { memarg } => {
+                let value: i64 = self.pop();
+                self.store(instance, memarg, &value.to_le_bytes())?;
+            }
+            Operator::F32Store { memarg } => {
+                let value: f32 = self.pop();
+                self.store(instance, memarg, &value.to_bits().to_le_bytes())?;
+            }
+            Operator::F64Store { memarg } => {
+                let value: f64 = self.pop();
+                self.store(instance, memarg, &value.to_bits().to_le_bytes())?;
+            }
+            Operator::I32Store8 { memarg } => {
+                let value: i32 = self.pop();
+                self.store(instance, memarg, &[value as u8])?;
+            }
+            Operator::I32Store16 { memarg } => {
+                let value: i32 = self.pop();
+                self.store(instance, memarg, &(value as u16).to_le_bytes())?;
+            }
+            Operator::I64Store8 { memarg } => {
+                let value: i64 = self.pop();
+                self.store(instance, memarg, &[value as u8])?;
+            }
+            Operator::I64Store16 { memarg } => {
+                let value: i64 = self.pop();
+                self.store(instance, memarg, &(value as u16).to_le_bytes())?;
+            }
+            Operator::I64Store32 { memarg } => {
+                let value: i64 = self.pop();
+                self.store(instance, memarg, &(value as u32).to_le_bytes())?;
+            }
+            Operator::MemorySize { .. } => {
+                let size = memory(instance).borrow().size();
+                self.push(size as i32);
+            }
+            Operator::MemoryGrow { .. } => {
+                let delta = self.pop::<i32>() as u32;
+                let result = memory(instance).borrow_mut().grow(delta);
+                self.push(result.map_or(-1, |size| size as i32));
+            }
+            Operator::MemoryInit { data_index, .. } => {
+                let (dst, src, len) = self.bulk_operands();
+                let index = *data_index as usize;
+                let bytes: &[u8] = if instance.dropped_data[index].get() {
+                    &[]
+                } else {
+                    &instance.module.data[index].data
+                };
+                if u64::from(src) + u64::from(len) > bytes.len() as u64 {
+                    return Err(Trap::MemoryOutOfBounds);
+                }
+                let bytes = &bytes[src as usize..(src + len) as usize];
+                memory(instance).borrow_mut().write(u64::from(dst), bytes)?;
+            }
+            Operator::DataDrop { data_index } => {
+                instance.dropped_data[*data_index as usize].set(true)
+            }
+            Operator::MemoryCopy { .. } => {
+                let (dst, src, len) = self.bulk_operands();
+                memory(instance)
+                    .borrow_mut()
+                    .copy(u64::from(dst), u64::from(src), len as usize)?;
+            }
+            Operator::MemoryFill { .. } => {
+                let (dst, value, len) = self.bulk_operands();
+                memory(instance)
+                    .borrow_mut()
+                    .fill(u64::from(dst), value as u8, len as usize)?;
+            }
+            Operator::TableInit {
+                elem_index,
+                table_index,
+            } => {
+                let (dst, src, len) = self.bulk_operands();
+                let index = *elem_index as usize;
+                let mut elements = if instance.dropped_elements[index].get() {
+                    vec![]
+                } else {
+                    elem_values(instance, &instance.module.elements[index])
+                };
+                if u64::from(src) + u64::from(len) > elements.len() as u64 {
+                    return Err(Trap::TableOutOfBounds);
+                }
+                elements.truncate((src + len) as usize);
+                elements.drain(..src as usize);
+                table(instance, *table_index)
+                    .borrow_mut()
+                    .init(dst, elements)?;
+            }
+            Operator::ElemDrop { elem_index } => {
+                instance.dropped_elements[*elem_index as usize].set(true)
+            }
+            Operator::TableCopy {
+                dst_table,
+                src_table,
+            } => {
+                let (dst, src, len) = self.bulk_operands();
+                // the tables may be the same
+                let elements = table(instance, *src_table).borrow().elements(src, len)?;
+                table(instance, *dst_table)
+                    .borrow_mut()
+                    .init(dst, elements)?;
+            }
+
+            Operator::I32Const { val } => self.push(*val),
+            Operator::I64Const { val } => self.push(*val),
+            Operator::F32Const { val } => self.push(f32::from_bits(*val)),
+            Operator::F64Const { val } => self.push(f64::from_bits(*val)),
+
+            Operator::I32Eqz => self.unary(|a: i32| i32::from(a == 0)),
+            Operator::I32Eq => self.binary(|a: i32, b| i32::from(a == b)),
+            Operator::I32Ne => self.binary(|a: i32, b| i32::from(a != b)),
+            Operator::I32LtS => self.binary(|a: i32, b| i32::from(a < b)),
+            Operator::I32LtU => self.binary(|a: i32, b| i32::from((a as u32) < (b as u32))),
+            Operator::I32GtS => self.binary(|a: i32, b| i32::from(a > b)),
+            Operator::I32GtU => self.binary(|a: i32, b| i32::from(a as u32 > b as u32)),
+            Operator::I32LeS => self.binary(|a: i32, b| i32::from(a <= b)),
+            Operator::I32LeU => self.binary(|a: i32, b| i32::from(a as u32 <= b as u32)),
+            Operator::I32GeS => self.binary(|a: i32, b| i32::from(a >= b)),
+            Operator::I32GeU => self.binary(|a: i32, b| i32::from(a as u32 >= b as u32)),
+
+            Operator::I64Eqz => self.unary(|a: i64| i32::from(a == 0)),
+            Operator::I64Eq => self.binary(|a: i64, b| i32::from(a == b)),
+            Operator::I64Ne => self.binary(|a: i64, b| i32::from(a != b)),
+            Operator::I64LtS => self.binary(|a: i64, b| i32::from(a < b)),
+            Operator::I64LtU => self.binary(|a: i64, b| i32::from((a as u64) < (b as u64))),
+            Operator::I64GtS => self.binary(|a: i64, b| i32::from(a > b)),
+            Operator::I64GtU => self.binary(|a: i64, b| i32::from(a as u64 > b as u64)),
+            Operator::I64LeS => self.binary(|a: i64, b| i32::from(a <= b)),
+            Operator::I64LeU => self.binary(|a: i64, b| i32::from(a as u64 <= b as u64)),
+            Operator::I64GeS => self.binary(|a: i64, b| i32::from(a >= b)),
+            Operator::I64GeU => self.binary(|a: i64, b| i32::from(a as u64 >= b as u64)),
+
+            Operator::F32Eq => self.binary(|a: f32, b| i32::from(a == b)),
+            Operator::F32Ne => self.binary(|a: f32, b| i32::from(a != b)),
+            Operator::F32Lt => self.binary(|a: f32, b| i32::from(a < b)),
+            Operator::F32Gt => self.binary(|a: f32, b| i32::from(a > b)),
+            Operator::F32Le => self.binary(|a: f32, b| i32::from(a <= b)),
+            Operator::F32Ge => self.binary(|a: f32, b| i32::from(a >= b)),
+
+            Operator::F64Eq => self.binary(|a: f64, b| i32::from(a == b)),
+            Operator::F64Ne => self.binary(|a: f64, b| i32::from(a != b)),
+            Operator::F64Lt => self.binary(|a: f64, b| i32::from(a < b)),
+            Operator::F64Gt => self.binary(|a: f64, b| i32::from(a > b)),
+            Operator::F64Le => self.binary(|a: f64, b| i32::from(a <= b)),
+            Operator::F64Ge => self.binary(|a: f64, b| i32::from(a >= b)),
+
+            Operator::I32Clz => self.unary(|a: i32| a.leading_zeros() as i32),
+            Operator::I32Ctz => self.unary(|a: i32| a.trailing_zeros() as i32),
+            Operator::I32Popcnt => self.unary(|a: i32| a.count_ones() as i32),
+            Operator::I32Add => self.binary(|a: i32, b| a.wrapping_add(b)),
+            Operator::I32Sub => self.binary(|a: i32, b| a.wrapping_sub(b)),
+            Operator::I32Mul => self.binary(|a: i32, b| a.wrapping_mul(b)),
+            Operator::I32DivS => self.try_binary(|a: i32, b| {
+                if b == 0 {
+                    Err(Trap::IntegerDivideByZero)
+                } else {
+                    a.checked_div(b).ok_or(Trap::IntegerOverflow)
+                }
+            })?,
+            Operator::I32DivU => self.try_binary(|a: i32, b| {
+                (a as u32)
+                    .checked_div(b as u32)
+                    .map(|value| value as i32)
+                    .ok_or(Trap::IntegerDivideByZero)
+            })?,
+            Operator::I32RemS => self.try_binary(|a: i32, b| {
+                if b == 0 {
+                    Err(Trap::IntegerDivideByZero)
+                } else {
+                    Ok(a.wrapping_rem(b))
+                }
+            })?,
+            Operator::I32RemU => self.try_binary(|a: i32, b| {
+                (a as u32)
+                    .checked_rem(b as u32)
+                    .map(|value| value as i32)
+                    .ok_or(Trap::IntegerDivideByZero)
+            })?,
+            Operator::I32And => self.binary(|a: i32, b| a & b),
+            Operator::I32Or => self.binary(|a: i32, b| a | b),
+            Operator::I32Xor => self.binary(|a: i32, b| a ^ b),
+            Operator::I32Shl => self.binary(|a: i32, b| a.wrapping_shl(b as u32)),
+            Operator::I32ShrS => self.binary(|a: i32, b| a.wrapping_shr(b as u32)),
+            Operator::I32ShrU => self.binary(|a: i32, b| (a as u32).wrapping_shr(b as u32) as i32),
+            Operator::I32Rotl => self.binary(|a: i32, b| a.rotate_left(b as u32 % 32)),
+            Operator::I32Rotr => self.binary(|a: i32, b| a.rotate_right(b as u32 % 32)),
+
+            Operator::I64Clz => self.unary(|a: i64| i64::from(a.leading_zeros())),
+            Operator::I64Ctz => self.unary(|a: i64| i64::from(a.trailing_zeros())),
+            Operator::I64Popcnt => self.unary(|a: i64| i64::from(a.count_ones())),
+            Operator::I64Add => self.binary(|a: i64, b| a.wrapping_add(b)),
+            Operator::I64Sub => self.binary(|a: i64, b| a.wrapping_sub(b)),
+            Operator::I64Mul => self.binary(|a: i64, b| a.wrapping_mul(b)),
+            Operator::I64DivS => self.try_binary(|a: i64, b| {
+                if b == 0 {
+                    Err(Trap::IntegerDivideByZero)
+                } else {
+                    a.checked_div(b).ok_or(Trap::IntegerOverflow)
+                }
+            })?,
+            Operator::I64DivU => self.try_binary(|a: i64, b| {
+                (a as u64)
+                    .checked_div(b as u64)
+                    .map(|value| value as i64)
+                    .ok_or(Trap::IntegerDivideByZero)
+            })?,
+            Operator::I64RemS => self.try_binary(|a: i64, b| {
+                if b == 0 {
+                    Err(Trap::IntegerDivideByZero)
+                } else {
+                    Ok(a.wrapping_rem(b))
+                }
+            })?,
+            Operator::I64RemU => self.try_binary(|a: i64, b| {
+                (a as u64)
+                    .checked_rem(b as u64)
+                    .map(|value| value as i64)
+                    .ok_or(Trap::IntegerDivideByZero)
+            })?,
+            Operator::I64And => self.binary(|a: i64, b| a & b),
+            Operator::I64Or => self.binary(|a: i64, b| a | b),
+            Operator::I64Xor => self.binary(|a: i64, b| a ^ b),
+            Operator::I64Shl => self.binary(|a: i64, b| a.wrapping_shl(b as u32)),
+            Operator::I64ShrS => self.binary(|a: i64, b| a.wrapping_shr(b as u32)),
+            Operator::I64ShrU => self.binary(|a: i64, b| (a as u64).wrapping_shr(b as u32) as i64),
+            Operator::I64Rotl => self.binary(|a: i64, b| a.rotate_left((b % 64) as u32)),
+            Operator::I64Rotr => self.binary(|a: i64, b| a.rotate_right((b % 64) as u32)),
+
+            // sign operations only touch the sign bit, NaN payloads included
+            Operator::F32Abs => self.unary(|a: f32| f32::from_bits(a.to_bits() & !F32_SIGN)),
+            Operator::F32Neg => self.unary(|a: f32| f32::from_bits(a.to_bits() ^ F32_SIGN)),
+            Operator::F32Ceil => self.unary(|a: f32| a.ceil()),
+            Operator::F32Floor => self.unary(|a: f32| a.floor()),
+            Operator::F32Trunc => self.unary(|a: f32| a.trunc()),
+            Operator::F32Nearest => self.unary(|a: f32| nearest(f64::from(a)) as f32),
+            Operator::F32Sqrt => self.unary(|a: f32| a.sqrt()),
+            Operator::F32Add => self.binary(|a: f32, b| a + b),
+            Operator::F32Sub => self.binary(|a: f32, b| a - b),
+            Operator::F32Mul => self.binary(|a: f32, b| a * b),
+            Operator::F32Div => self.binary(|a: f32, b| a / b),
+            Operator::F32Min => self.binary(|a: f32, b| min(f64::from(a), f64::from(b)) as f32),
+            Operator::F32Max => self.binary(|a: f32, b| max(f64::from(a), f64::from(b)) as f32),
+            Operator::F32Copysign => self.binary(|a: f32, b| {
+                f32::from_bits(a.to_bits() & !F32_SIGN | b.to_bits() & F32_SIGN)
+            }),
+
+            Operator::F64Abs => self.unary(|a: f64| f64::from_bits(a.to_bits() & !F64_SIGN)),
+            Operator::F64Neg => self.unary(|a: f64| f64::from_bits(a.to_bits() ^ F64_SIGN)),
+            Operator::F64Ceil => self.unary(|a: f64| a.ceil()),
+            Operator::F64Floor => self.unary(|a: f64| a.floor()),
+            Operator::F64Trunc => self.unary(|a: f64| a.trunc()),
+            Operator::F64Nearest => self.unary(nearest),
+            Operator::F64Sqrt => self.unary(|a: f64| a.sqrt()),
+            Operator::F64Add => self.binary(|a: f64, b| a + b),
+            Operator::F64Sub => self.binary(|a: f64, b| a - b),
+            Operator::F64Mul => self.binary(|a: f64, b| a * b),
+            Operator::F64Div => self.binary(|a: f64, b| a / b),
+            Operator::F64Min => self.binary(min),
+            Operator::F64Max => self.binary(max),
+            Operator::F64Copysign => self.binary(|a: f64, b| {
+                f64::from_bits(a.to_bits() & !F64_SIGN | b.to_bits() & F64_SIGN)
+            }),
+
+            Operator::I32WrapI64 => self.unary(|a: i64| a as i32),
+            Operator::I32TruncSF32 => self.try_unary(|a: f32| {
+                truncate(f64::from(a), -I32_BOUND, I32_BOUND).map(|t| t as i32)
+            })?,
+            Operator::I32TruncUF32 => self.try_unary(|a: f32| {
+                truncate(f64::from(a), 0.0, U32_BOUND).map(|t| t as u32 as i32)
+            })?,
+            Operator::I32TruncSF64 => {
+                self.try_unary(|a: f64| truncate(a, -I32_BOUND, I32_BOUND).map(|t| t as i32))?
+            }
+            Operator::I32TruncUF64 => {
+                self.try_unary(|a: f64| truncate(a, 0.0, U32_BOUND).map(|t| t as u32 as i32))?
+            }
+            Operator::I64ExtendSI32 => self.unary(<i64 as From<i32>>::from),
+            Operator::I64ExtendUI32 => self.unary(|a: i32| i64::from(a as u32)),
+            Operator::I64TruncSF32 => self.try_unary(|a: f32| {
+                truncate(f64::from(a), -I64_BOUND, I64_BOUND).map(|t| t as i64)
+            })?,
+            Operator::I64TruncUF32 => self.try_unary(|a: f32| {
+                truncate(f64::from(a), 0.0, U64_BOUND).map(|t| t as u64 as i64)
+            })?,
+            Operator::I64TruncSF64 => {
+                self.try_unary(|a: f64| truncate(a, -I64_BOUND, I64_BOUND).map(|t| t as i64))?
+            }
+            Operator::I64TruncUF64 => {
+                self.try_unary(|a: f64| truncate(a, 0.0, U64_BOUND).map(|t| t as u64 as i64))?
+            }
+            Operator::F32ConvertSI32 => self.unary(|a: i32| a as f32),
+            Operator::F32ConvertUI32 => self.unary(|a: i32| a as u32 as f32),
+            Operator::F32ConvertSI64 => self.unary(|a: i64| a as f32),
+            Operator::F32ConvertUI64 => self.unary(|a: i64| a as u64 as f32),
+            Operator::F32DemoteF64 => self.unary(|a: f64| a as f32),
+            Operator::F64ConvertSI32 => self.unary(<f64 as From<i32>>::from),
+            Operator::F64ConvertUI32 => self.unary(|a: i32| f64::from(a as u32)),
+            Operator::F64ConvertSI64 => self.unary(|a: i64| a as f64),
+            Operator::F64ConvertUI64 => self.unary(|a: i64| a as u64 as f64),
+            Operator::F64PromoteF32 => self.unary(<f64 as From<f32>>::from),
+            Operator::I32ReinterpretF32 => self.unary(|a: f32| a.to_bits() as i32),
+            Operator::I64ReinterpretF64 => self.unary(|a: f64| a.to_bits() as i64),
+            Operator::F32ReinterpretI32 => self.unary(|a: i32| f32::from_bits(a as u32)),
+            Operator::F64ReinterpretI64 => self.unary(|a: i64| f64::from_bits(a as u64)),
+            Operator::I32Extend8S => self.unary(|a: i32| i32::from(a as i8)),
+            Operator::I32Extend16S => self.unary(|a: i32| i32::from(a as i16)),
+            Operator::I64Extend8S => self.unary(|a: i64| i64::from(a as i8)),
+            Operator::I64Extend16S => self.unary(|a: i64| i64::from(a as i16)),
+            Operator::I64Extend32S => self.unary(|a: i64| i64::from(a as i32)),
+
+            Operator::RefNull { t } => self.push(Value::zero(*t).expect("validated ref.null")),
+            Operator::RefIsNull => {
+                let value = self.pop_value();
+                self.push(value.is_null() as i32);
+            }
+            Operator::RefFunc { function_index } => {
+                self.push(Value::FuncRef(func(instance, *function_index)));
+            }
+            Operator::I32TruncSatSF32 => self.unary(|a: f32| {
+                let (min, max) = (i32::min_value(), i32::max_value());
+                truncate_sat(f64::from(a), -I32_BOUND, I32_BOUND, min, max, |t| t as i32)
+            }),
+            Operator::I32TruncSatUF32 => self.unary(|a: f32| {
+                truncate_sat(f64::from(a), 0.0, U32_BOUND, 0, -1, |t| t as u32 as i32)
+            }),
+            Operator::I32TruncSatSF64 => self.unary(|a: f64| {
+                let (min, max) = (i32::min_value(), i32::max_value());
+                truncate_sat(a, -I32_BOUND, I32_BOUND, min, max, |t| t as i32)
+            }),
+            Operator::I32TruncSatUF64 => {
+                self.unary(|a: f64| truncate_sat(a, 0.0, U32_BOUND, 0, -1, |t| t as u32 as i32))
+            }
+            Operator::I64TruncSatSF32 => self.unary(|a: f32| {
+                let (min, max) = (i64::min_value(), i64::max_value());
+                truncate_sat(f64::from(a), -I64_BOUND, I64_BOUND, min, max, |t| t as i64)
+            }),
+            Operator::I64TruncSatUF32 => self.unary(|a: f32| {
+                truncate_sat(f64::from(a), 0.0, U64_BOUND, 0, -1, |t| t as u64 as i64)
+            }),
+            Operator::I64TruncSatSF64 => self.unary(|a: f64| {
+                let (min, max) = (i64::min_value(), i64::max_value());
+                truncate_sat(a, -I64_BOUND, I64_BOUND, min, max, |t| t as i64)
+            }),
+            Operator::I64TruncSatUF64 => {
+                self.unary(|a: f64| truncate_sat(a, 0.0, U64_BOUND, 0, -1, |t| t as u64 as i64))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push<T: Into<Value>>(&mut self, value: T) {
+        self.stack.push(value.into());
+    }
+
+    fn pop_value(&mut self) -> Value {
+        self.stack.pop().expect("operand stack underflow")
+    }
+
+    // Validation guarantees the operand types, so a mismatch is a bug.
+    fn pop<T: WasmType>(&mut self) -> T {
+        T::from_value(self.pop_value()).expect("operand type mismatch")
+    }
+
+    fn unary<T: WasmType, R: Into<Value>>(&mut self, f: impl Fn(T) -> R) {
+        let a = self.pop();
+        self.push(f(a));
+    }
+
+    fn binary<T: WasmType, R: Into<Value>>(&mut self, f: impl Fn(T, T) -> R) {
+        let b = self.pop();
+        let a = self.pop();
+        self.push(f(a, b));
+    }
+
+    fn try_unary<T: WasmType, R: Into<Value>>(
+        &mut self,
+        f: impl Fn(T) -> Result<R, Trap>,
+    ) -> Result<(), Trap> {
+        let a = self.pop();
+        self.push(f(a)?);
+        Ok(())
+    }
+
+    fn try_binary<T: WasmType, R: Into<Value>>(
+        &mut self,
+        f: impl Fn(T, T) -> Result<R, Trap>,
+    ) -> Result<(), Trap> {
+        let b = self.pop();
+        let a = self.pop();
+        self.push(f(a, b)?);
+        Ok(())
+    }
+
+    // Pops the destination, the source or value, and the length of a bulk
+    // memory or table operation.
+    fn bulk_operands(&mut self) -> (u32, u32, u32) {
+        let len = self.pop::<i32>() as u32;
+        let src = self.pop::<i32>() as u32;
+        let dst = self.pop::<i32>() as u32;
+        (dst, src, len)
+    }
+
+    // Pops the base address and adds the offset, in 64 bits so that the
+    // effective address does not wrap around.
+    fn address(&mut self, memarg: &MemArg) -> u64 {
+        u64::from(self.pop::<i32>() as u32) + u64::from(memarg.offset)
+    }
+
+    // Reads `size` bytes into the start of an 8-byte buffer.
+    fn load(
+        &mut self,
+        instance: &InstanceData,
+        memarg: &MemArg,
+        size: usize,
+    ) -> Result<[u8; 8], Trap> {
+        let address = self.address(memarg);
+        let mut bytes = [0; 8];
+        memory(instance)
+            .borrow()
+            .read(address, &mut bytes[..size])?;
+        Ok(bytes)
+    }
+
+    fn store(
+        &mut self,
+        instance: &InstanceData,
+        memarg: &MemArg,
+        bytes: &[u8],
+    ) -> Result<(), Trap> {
+        let address = self.address(memarg);
+        memory(instance).borrow_mut().write(address, bytes)
+    }
+}
+
+const F32_SIGN: u32 = 1 << 31;
+const F64_SIGN: u64 = 1 << 63;
+const I32_BOUND: f64 = 2_147_483_648.0;
+const U32_BOUND: f64 = 4_294_967_296.0;
+const I64_BOUND: f64 = 9_223_372_036_854_775_808.0;
+const U64_BOUND: f64 = 18_446_744_073_709_551_616.0;
+
+fn memory(instance: &InstanceData) -> &RefCell<Memory> {
+    instance.memory.as_ref().expect("validated memory")
+}
+
+fn table(instance: &InstanceData, table_index: u32) -> &RefCell<Table> {
+    &instance.tables[table_index as usize]
+}
+
+fn block_arity(t: Type) -> usize {
+    if t == Type::EmptyBlockType {
+        0
+    } else {
+        1
+    }
+}
+
+// Truncates a float towards zero if the result is in `[min, max)`.
+fn truncate(value: f64, min: f64, max: f64) -> Result<f64, Trap> {
+    if value.is_nan() {
+        return Err(Trap::InvalidConversionToInteger);
+    }
+    let value = value.trunc();
+    if value >= min && value < max {
+        Ok(value)
+    } else {
+        Err(Trap::IntegerOverflow)
+    }
+}
+
+// Truncates like `truncate`, but saturates out of range values to `lower` or
+// `upper` and converts NaN to zero.
+fn truncate_sat<T>(
+    value: f64,
+    min: f64,
+    max: f64,
+    lower: T,
+    upper: T,
+    convert: impl Fn(f64) -> T,
+) -> T {
+    match truncate(value, min, max) {
+        Ok(value) => convert(value),
+        Err(_) if value.is_nan() => convert(0.0),
+        Err(_) if value < 0.0 => lower,
+        Err(_) => upper,
+    }
+}
+
+// Rounds to the nearest integer, ties to even. f32 values go through f64,
+// which represents them and their rounded values exactly.
+fn nearest(value: f64) -> f64 {
+    let rounded = value.round();
+    let rounded = if (rounded - value).abs() == 0.5 {
+        2.0 * (value / 2.0).round()
+    } else {
+        rounded
+    };
+    // keeps the sign of values rounded to zero
+    f64::from_bits(rounded.to_bits() & !F64_SIGN | value.to_bits() & F64_SIGN)
+}
+
+// Unlike `f64::min`, NaN operands propagate and -0 is less than +0.
+fn min(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f64::from_bits(a.to_bits() | b.to_bits())
+    } else {
+        a.min(b)
+    }
+}
+
+fn max(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        a + b
+    } else if a == b {
+        f64::from_bits(a.to_bits() & b.to_bits())
+    } else {
+        a.max(b)
+    }
+}
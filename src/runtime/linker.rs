@@ -0,0 +1,136 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::primitives::{ImportKind, ResizableLimit};
+use crate::runtime::{instantiate, Func, FuncType, Global, Instance, Memory, Table, Trap, Value};
+use crate::{Module, WasmError, WasmResult};
+
+// An item that can be imported by a module.
+#[derive(Debug, Clone)]
+pub enum Extern {
+    Func(Func),
+    Memory(Rc<RefCell<Memory>>),
+    Table(Rc<RefCell<Table>>),
+    Global(Rc<RefCell<Global>>),
+}
+
+// The imports of a module resolved against the items of a linker.
+#[derive(Default)]
+pub(crate) struct Imports {
+    pub funcs: Vec<Func>,
+    pub memory: Option<Rc<RefCell<Memory>>>,
+    pub tables: Vec<Rc<RefCell<Table>>>,
+    pub globals: Vec<Rc<RefCell<Global>>>,
+}
+
+// Instantiates modules, resolving their imports against the items registered
+// under a module and field name.
+#[derive(Debug, Default)]
+pub struct Linker {
+    items: HashMap<(String, String), Extern>,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn define(&mut self, mod_name: &str, field_name: &str, item: Extern) -> &mut Self {
+        self.items
+            .insert((mod_name.to_string(), field_name.to_string()), item);
+        self
+    }
+
+    // Registers a Rust closure as a function of type `ty`.
+    pub fn func<F>(&mut self, mod_name: &str, field_name: &str, ty: FuncType, f: F) -> &mut Self
+    where
+        F: Fn(&[Value]) -> Result<Vec<Value>, Trap> + 'static,
+    {
+        self.define(mod_name, field_name, Extern::Func(Func::new(ty, f)))
+    }
+
+    // Registers every export of `instance` under `mod_name`.
+    pub fn instance(&mut self, mod_name: &str, instance: &Instance) -> &mut Self {
+        for entity in &instance.module().exports {
+            if let Some(item) = instance.export(&entity.name) {
+                self.define(mod_name, &entity.name, item);
+            }
+        }
+        self
+    }
+
+    pub fn get(&self, mod_name: &str, field_name: &str) -> Option<&Extern> {
+        self.items
+            .get(&(mod_name.to_string(), field_name.to_string()))
+    }
+
+    // Validates `module` and instantiates it with the registered items as its imports.
+    pub fn instantiate(&self, module: Module) -> WasmResult<Instance> {
+        module.validate()?;
+
+        let mut imports = Imports::default();
+        let mut unknown = vec![];
+        let mut incompatible = vec![];
+        for entity in &module.imports {
+            let names = (entity.mod_name.to_string(), entity.field_name.to_string());
+            let item = match self.get(&entity.mod_name, &entity.field_name) {
+                Some(item) => item,
+                None => {
+                    unknown.push(names);
+                    continue;
+                }
+            };
+
+            let compatible = match (&entity.kind, item) {
+                (ImportKind::Func { signature_index }, Extern::Func(func)) => {
+                    imports.funcs.push(func.clone());
+                    module
+                        .func_type(*signature_index)
+                        .map(FuncType::from)
+                        .as_ref()
+                        == Some(func.ty())
+                }
+                (ImportKind::Memory { limit }, Extern::Memory(memory)) => {
+                    imports.memory = Some(Rc::clone(memory));
+                    let memory = memory.borrow();
+                    matches_limit(memory.size(), memory.max(), limit)
+                }
+                (ImportKind::Table { elem_type, limit }, Extern::Table(table)) => {
+                    imports.tables.push(Rc::clone(table));
+                    let table = table.borrow();
+                    table.elem_type() == *elem_type
+                        && matches_limit(table.size(), table.max(), limit)
+                }
+                (ImportKind::Global { global_arg }, Extern::Global(global)) => {
+                    imports.globals.push(Rc::clone(global));
+                    let global = global.borrow();
+                    global.ty() == global_arg.content_type
+                        && global.mutability() == global_arg.mutability
+                }
+                _ => false,
+            };
+            if !compatible {
+                incompatible.push(names);
+            }
+        }
+        if !unknown.is_empty() || !incompatible.is_empty() {
+            return Err(WasmError::UnresolvedImports {
+                unknown,
+                incompatible,
+            });
+        }
+
+        instantiate(module.into_owned(), imports)
+    }
+}
+
+// Whether an item of `size` and `max` may be imported where `limit` is expected,
+// it must be at least as large and grow no further.
+fn matches_limit(size: u32, max: Option<u32>, limit: &ResizableLimit) -> bool {
+    size >= limit.initial
+        && match limit.max {
+            Some(limit_max) => max.map_or(false, |max| max <= limit_max),
+            None => true,
+        }
+}
@@ -0,0 +1,88 @@
+use crate::primitives::ResizableLimit;
+use crate::runtime::Trap;
+
+pub const PAGE_SIZE: usize = 64 * 1024;
+// the most pages a 32-bit address space holds
+const MAX_PAGES: u32 = 64 * 1024;
+
+// A linear memory, a zero-initialized byte array of 64 KiB pages.
+#[derive(Debug)]
+pub struct Memory {
+    data: Vec<u8>,
+    max: Option<u32>,
+}
+
+impl Memory {
+    pub fn new(limit: &ResizableLimit) -> Self {
+        Self {
+            data: vec![0; limit.initial as usize * PAGE_SIZE],
+            max: limit.max,
+        }
+    }
+
+    // The current size in pages.
+    pub fn size(&self) -> u32 {
+        (self.data.len() / PAGE_SIZE) as u32
+    }
+
+    pub fn max(&self) -> Option<u32> {
+        self.max
+    }
+
+    // Grows the memory by `delta` pages, returning the previous size in pages, or
+    // `None` if it would exceed its maximum.
+    pub fn grow(&mut self, delta: u32) -> Option<u32> {
+        let size = self.size();
+        let new_size = size
+            .checked_add(delta)
+            .filter(|&new_size| new_size <= self.max.unwrap_or(MAX_PAGES))?;
+        self.data.resize(new_size as usize * PAGE_SIZE, 0);
+        Some(size)
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    // Fills `buf` with the bytes at `address`. Addresses are 64-bit so that an
+    // effective address past 4 GiB traps instead of wrapping around.
+    pub fn read(&self, address: u64, buf: &mut [u8]) -> Result<(), Trap> {
+        let range = self.range(address, buf.len())?;
+        buf.copy_from_slice(&self.data[range]);
+        Ok(())
+    }
+
+    pub fn write(&mut self, address: u64, bytes: &[u8]) -> Result<(), Trap> {
+        let range = self.range(address, bytes.len())?;
+        self.data[range].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    // Copies `len` bytes from `src` to `dst`, which may overlap.
+    pub fn copy(&mut self, dst: u64, src: u64, len: usize) -> Result<(), Trap> {
+        let src = self.range(src, len)?;
+        let dst = self.range(dst, len)?;
+        let bytes = self.data[src].to_vec();
+        self.data[dst].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    pub fn fill(&mut self, address: u64, value: u8, len: usize) -> Result<(), Trap> {
+        let range = self.range(address, len)?;
+        for byte in &mut self.data[range] {
+            *byte = value;
+        }
+        Ok(())
+    }
+
+    fn range(&self, address: u64, len: usize) -> Result<std::ops::Range<usize>, Trap> {
+        if address + len as u64 > self.data.len() as u64 {
+            return Err(Trap::MemoryOutOfBounds);
+        }
+        Ok(address as usize..address as usize + len)
+    }
+}
@@ -0,0 +1,277 @@
+mod func;
+mod global;
+mod interpreter;
+mod linker;
+mod memory;
+mod table;
+mod trap;
+mod value;
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::primitives::{ElemItems, ExternalKind, Operator, SectionElemEntity, SegmentMode, Type};
+use crate::runtime::func::FuncKind;
+use crate::runtime::global::evaluate;
+use crate::runtime::interpreter::{jumps, Jump};
+use crate::runtime::linker::Imports;
+use crate::{Module, WasmError, WasmResult};
+
+pub use func::{Func, FuncType, HostFunc, TypedFunc};
+pub use global::Global;
+pub use linker::{Extern, Linker};
+pub use memory::{Memory, PAGE_SIZE};
+pub use table::Table;
+pub use trap::Trap;
+pub use value::{Value, WasmParams, WasmResults, WasmType};
+
+// A module instantiated with its memory, tables and globals, whose functions
+// can be executed.
+// Clones refer to the same instance.
+pub struct Instance {
+    data: Rc<InstanceData>,
+}
+
+impl Clone for Instance {
+    fn clone(&self) -> Self {
+        Self {
+            data: Rc::clone(&self.data),
+        }
+    }
+}
+
+pub(crate) struct InstanceData {
+    module: Module<'static>,
+    // for each defined function, where the blocks of its body end
+    jumps: Vec<Vec<Jump>>,
+    // the imported part of the function index space
+    imported_funcs: Vec<Func>,
+    memory: Option<Rc<RefCell<Memory>>>,
+    // imported tables first
+    tables: Vec<Rc<RefCell<Table>>>,
+    // imported globals first
+    globals: Vec<Rc<RefCell<Global>>>,
+    // whether each element and data segment was dropped, as active and declared
+    // ones are once instantiated
+    dropped_elements: Vec<Cell<bool>>,
+    dropped_data: Vec<Cell<bool>>,
+}
+
+impl Instance {
+    // Validates and instantiates `module`, which must not have imports.
+    pub fn new(module: Module) -> WasmResult<Self> {
+        Linker::new().instantiate(module)
+    }
+
+    pub fn module(&self) -> &Module<'static> {
+        &self.data.module
+    }
+
+    pub fn memory(&self) -> Option<&Rc<RefCell<Memory>>> {
+        self.data.memory.as_ref()
+    }
+
+    // The first table.
+    pub fn table(&self) -> Option<&Rc<RefCell<Table>>> {
+        self.data.tables.first()
+    }
+
+    pub fn tables(&self) -> &[Rc<RefCell<Table>>] {
+        &self.data.tables
+    }
+
+    pub fn global(&self, global_index: u32) -> Option<&Rc<RefCell<Global>>> {
+        self.data.globals.get(global_index as usize)
+    }
+
+    // The function at `function_index` of the function index space.
+    pub fn func(&self, function_index: u32) -> Option<Func> {
+        func(&self.data, function_index)
+    }
+
+    pub fn call(&self, function_index: u32, args: &[Value]) -> Result<Vec<Value>, Trap> {
+        self.func(function_index)
+            .ok_or_else(|| Trap::UnknownFunction(function_index))?
+            .call(args)
+    }
+
+    // Calls the exported function `name`.
+    pub fn invoke(&self, name: &str, args: &[Value]) -> Result<Vec<Value>, Trap> {
+        self.export_func(name)?.call(args)
+    }
+
+    // The exported function `name` with the signature `Params -> Results`.
+    pub fn get_typed_func<Params, Results>(
+        &self,
+        name: &str,
+    ) -> Result<TypedFunc<Params, Results>, Trap>
+    where
+        Params: WasmParams,
+        Results: WasmResults,
+    {
+        TypedFunc::new(self.export_func(name)?)
+    }
+
+    fn export_func(&self, name: &str) -> Result<Func, Trap> {
+        match self.export(name) {
+            Some(Extern::Func(func)) => Ok(func),
+            _ => Err(Trap::UnknownExport(name.to_string())),
+        }
+    }
+
+    pub fn export(&self, name: &str) -> Option<Extern> {
+        match self.module().export(name)?.kind {
+            ExternalKind::Func { signature_index } => self.func(signature_index).map(Extern::Func),
+            ExternalKind::Table { table_index } => self
+                .tables()
+                .get(table_index as usize)
+                .map(|table| Extern::Table(Rc::clone(table))),
+            ExternalKind::Memory { .. } => self
+                .memory()
+                .map(|memory| Extern::Memory(Rc::clone(memory))),
+            ExternalKind::Global { global_index } => self
+                .global(global_index)
+                .map(|global| Extern::Global(Rc::clone(global))),
+        }
+    }
+}
+
+// A function of `instance` that keeps it alive.
+pub(crate) fn func(instance: &Rc<InstanceData>, function_index: u32) -> Option<Func> {
+    let imported = &instance.imported_funcs;
+    if let Some(func) = imported.get(function_index as usize) {
+        return Some(func.clone());
+    }
+
+    let signature = instance.module.function(function_index)?.signature();
+    Some(Func::from_kind(
+        FuncType::from(signature),
+        FuncKind::Wasm {
+            instance: Rc::clone(instance),
+            function_index,
+        },
+    ))
+}
+
+// The references of an element segment, whose expressions are constant.
+pub(crate) fn elem_values(instance: &Rc<InstanceData>, entity: &SectionElemEntity) -> Vec<Value> {
+    match &entity.elems {
+        ElemItems::Functions(functions) => functions
+            .iter()
+            .map(|function_index| Value::FuncRef(func(instance, *function_index)))
+            .collect(),
+        ElemItems::Expressions(exprs) => {
+            let imported = instance.globals.len() - instance.module.globals.len();
+            exprs
+                .iter()
+                .map(|expr| match expr.as_slice() {
+                    [Operator::RefFunc { function_index }, Operator::End] => {
+                        Value::FuncRef(func(instance, *function_index))
+                    }
+                    expr => evaluate(expr, &instance.globals[..imported])
+                        .expect("validated element expression"),
+                })
+                .collect()
+        }
+    }
+}
+
+// Creates the instance of a validated module with its resolved imports. The
+// active segments are written in order, elements before data, and the first one
+// out of bounds traps, keeping what the ones before it wrote to imported tables
+// and memories. Then the start function runs.
+pub(crate) fn instantiate(module: Module<'static>, imports: Imports) -> WasmResult<Instance> {
+    let memory = match imports.memory {
+        Some(memory) => Some(memory),
+        None => module
+            .memories
+            .first()
+            .map(|entity| Rc::new(RefCell::new(Memory::new(&entity.limit)))),
+    };
+    let mut tables = imports.tables;
+    for entity in &module.tables {
+        let table = Table::with_type(entity.elem_type, &entity.limit)?;
+        tables.push(Rc::new(RefCell::new(table)));
+    }
+    // constant expressions only refer to imported globals
+    let imported = imports.globals.len();
+    let mut globals = imports.globals;
+    for entity in &module.globals {
+        let value = match entity.expr.as_slice() {
+            // set once the instance exists
+            [Operator::RefFunc { .. }, Operator::End] => Value::FuncRef(None),
+            expr => evaluate(expr, &globals[..imported])?,
+        };
+        let global = Global::new(value, entity.global_arg.mutability);
+        globals.push(Rc::new(RefCell::new(global)));
+    }
+
+    let instance = Instance {
+        data: Rc::new(InstanceData {
+            jumps: module.codes.iter().map(|body| jumps(&body.code)).collect(),
+            imported_funcs: imports.funcs,
+            memory,
+            tables,
+            globals,
+            dropped_elements: module.elements.iter().map(|e| dropped(e.mode)).collect(),
+            dropped_data: module.data.iter().map(|e| dropped(e.mode)).collect(),
+            module,
+        }),
+    };
+
+    let module = instance.module();
+    for (global, entity) in instance.data.globals[imported..]
+        .iter()
+        .zip(&module.globals)
+    {
+        if let [Operator::RefFunc { function_index }, Operator::End] = entity.expr.as_slice() {
+            let value = Value::FuncRef(func(&instance.data, *function_index));
+            global.borrow_mut().initialize(value);
+        }
+    }
+    // passive and declared segments have no offset
+    let globals = &instance.data.globals[..imported];
+    for entity in module
+        .elements
+        .iter()
+        .filter(|e| e.mode == SegmentMode::Active)
+    {
+        let offset = segment_offset(&entity.offset, globals)?;
+        let values = elem_values(&instance.data, entity);
+        instance.tables()[entity.table_index as usize]
+            .borrow_mut()
+            .init(offset as u32, values)
+            .map_err(WasmError::Trap)?;
+    }
+    for entity in module.data.iter().filter(|e| e.mode == SegmentMode::Active) {
+        let offset = segment_offset(&entity.expr, globals)?;
+        instance
+            .memory()
+            .expect("validated memory")
+            .borrow_mut()
+            .write(offset, &entity.data)
+            .map_err(WasmError::Trap)?;
+    }
+
+    if let Some(function_index) = module.start {
+        instance
+            .call(function_index, &[])
+            .map_err(WasmError::Trap)?;
+    }
+    Ok(instance)
+}
+
+fn dropped(mode: SegmentMode) -> Cell<bool> {
+    Cell::new(mode != SegmentMode::Passive)
+}
+
+// The offset of an element or data segment.
+fn segment_offset(expr: &[Operator], imported: &[Rc<RefCell<Global>>]) -> WasmResult<u64> {
+    match evaluate(expr, imported)? {
+        Value::I32(offset) => Ok(u64::from(offset as u32)),
+        value => Err(WasmError::TypeMismatch {
+            expected: Some(Type::I32),
+            actual: Some(value.ty()),
+        }),
+    }
+}
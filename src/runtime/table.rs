@@ -0,0 +1,143 @@
+use std::ops::Range;
+
+use crate::primitives::{ResizableLimit, Type};
+use crate::runtime::{Func, Trap, Value};
+use crate::{WasmError, WasmResult};
+
+// the most elements of a table, an implementation limit that keeps a growing
+// table from exhausting memory
+const MAX_TABLE_SIZE: u32 = 10_000_000;
+
+// A table of references, of functions for the targets of `call_indirect` or of
+// host values.
+#[derive(Debug)]
+pub struct Table {
+    elem_type: Type,
+    elements: Vec<Value>,
+    max: Option<u32>,
+    // the null reference of `elem_type`, which new elements start as
+    null: Value,
+}
+
+impl Table {
+    // A table of function references.
+    pub fn new(limit: &ResizableLimit) -> Self {
+        Self::with_null(Value::FuncRef(None), limit)
+    }
+
+    // A table of `elem_type` references, which start null. Fails if `elem_type`
+    // is not a reference type.
+    pub fn with_type(elem_type: Type, limit: &ResizableLimit) -> WasmResult<Self> {
+        match Value::zero(elem_type).filter(|_| elem_type.is_ref()) {
+            Some(null) => Ok(Self::with_null(null, limit)),
+            None => Err(WasmError::ReferenceTypeExpected(elem_type)),
+        }
+    }
+
+    fn with_null(null: Value, limit: &ResizableLimit) -> Self {
+        Self {
+            elem_type: null.ty(),
+            elements: vec![null.clone(); limit.initial as usize],
+            max: limit.max,
+            null,
+        }
+    }
+
+    pub fn elem_type(&self) -> Type {
+        self.elem_type
+    }
+
+    pub fn size(&self) -> u32 {
+        self.elements.len() as u32
+    }
+
+    pub fn max(&self) -> Option<u32> {
+        self.max
+    }
+
+    // Grows the table by `delta` null elements, returning the previous size, or
+    // `None` if it would exceed its maximum or the implementation limit.
+    pub fn grow(&mut self, delta: u32) -> Option<u32> {
+        let null = self.null.clone();
+        self.grow_with(delta, null)
+    }
+
+    // Grows the table by `delta` copies of `init`.
+    pub fn grow_with(&mut self, delta: u32, init: Value) -> Option<u32> {
+        let size = self.size();
+        let new_size = size.checked_add(delta).filter(|&new_size| {
+            new_size <= MAX_TABLE_SIZE && self.max.map_or(true, |max| new_size <= max)
+        })?;
+        self.elements.resize(new_size as usize, init);
+        Some(size)
+    }
+
+    // The function at `index`, `None` if it is null or not a function.
+    pub fn get(&self, index: u32) -> Result<Option<Func>, Trap> {
+        match self.get_ref(index)? {
+            Value::FuncRef(func) => Ok(func),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn set(&mut self, index: u32, element: Option<Func>) -> Result<(), Trap> {
+        self.set_ref(index, Value::FuncRef(element))
+    }
+
+    pub fn get_ref(&self, index: u32) -> Result<Value, Trap> {
+        self.elements
+            .get(index as usize)
+            .cloned()
+            .ok_or(Trap::UndefinedElement)
+    }
+
+    // Stores a reference of the table's element type at `index`.
+    pub fn set_ref(&mut self, index: u32, element: Value) -> Result<(), Trap> {
+        if element.ty() != self.elem_type {
+            return Err(Trap::ArgumentTypeMismatch);
+        }
+        let slot = self
+            .elements
+            .get_mut(index as usize)
+            .ok_or(Trap::UndefinedElement)?;
+        *slot = element;
+        Ok(())
+    }
+
+    // The `len` elements from `index` on.
+    pub fn elements(&self, index: u32, len: u32) -> Result<Vec<Value>, Trap> {
+        let range = self.range(index, len)?;
+        Ok(self.elements[range].to_vec())
+    }
+
+    // Writes `elements` from `index` on, or nothing if they do not all fit.
+    pub fn init(&mut self, index: u32, elements: Vec<Value>) -> Result<(), Trap> {
+        let range = self.range(index, elements.len() as u32)?;
+        for (slot, element) in self.elements[range].iter_mut().zip(elements) {
+            *slot = element;
+        }
+        Ok(())
+    }
+
+    // Copies `len` elements from `src` to `dst`, which may overlap.
+    pub fn copy(&mut self, dst: u32, src: u32, len: u32) -> Result<(), Trap> {
+        let elements = self.elements(src, len)?;
+        self.init(dst, elements)
+    }
+
+    // Sets `len` elements from `index` on to `value`.
+    pub fn fill(&mut self, index: u32, value: Value, len: u32) -> Result<(), Trap> {
+        let range = self.range(index, len)?;
+        for slot in &mut self.elements[range] {
+            *slot = value.clone();
+        }
+        Ok(())
+    }
+
+    fn range(&self, index: u32, len: u32) -> Result<Range<usize>, Trap> {
+        if u64::from(index) + u64::from(len) > self.elements.len() as u64 {
+            return Err(Trap::TableOutOfBounds);
+        }
+        Ok(index as usize..index as usize + len as usize)
+    }
+}
@@ -0,0 +1,50 @@
+use std::error::Error;
+use std::fmt;
+
+// An error raised while executing code, which aborts the current invocation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Trap {
+    Unreachable,
+    MemoryOutOfBounds,
+    UndefinedElement,
+    TableOutOfBounds,
+    UninitializedElement,
+    IndirectCallTypeMismatch,
+    IntegerDivideByZero,
+    IntegerOverflow,
+    InvalidConversionToInteger,
+    CallStackExhausted,
+    // the arguments of an invocation do not match the signature of the function
+    ArgumentTypeMismatch,
+    // a host function returned values that do not match its signature
+    ResultTypeMismatch,
+    UnknownFunction(u32),
+    // there is no exported function of that name
+    UnknownExport(String),
+    // an error raised by a host function
+    Host(String),
+}
+
+impl Error for Trap {}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Trap::Unreachable => write!(f, "[wasm]: unreachable"),
+            Trap::MemoryOutOfBounds => write!(f, "[wasm]: out of bounds memory access"),
+            Trap::UndefinedElement => write!(f, "[wasm]: undefined element"),
+            Trap::TableOutOfBounds => write!(f, "[wasm]: out of bounds table access"),
+            Trap::UninitializedElement => write!(f, "[wasm]: uninitialized element"),
+            Trap::IndirectCallTypeMismatch => write!(f, "[wasm]: indirect call type mismatch"),
+            Trap::IntegerDivideByZero => write!(f, "[wasm]: integer divide by zero"),
+            Trap::IntegerOverflow => write!(f, "[wasm]: integer overflow"),
+            Trap::InvalidConversionToInteger => write!(f, "[wasm]: invalid conversion to integer"),
+            Trap::CallStackExhausted => write!(f, "[wasm]: call stack exhausted"),
+            Trap::ArgumentTypeMismatch => write!(f, "[wasm]: argument type mismatch"),
+            Trap::ResultTypeMismatch => write!(f, "[wasm]: result type mismatch"),
+            Trap::UnknownFunction(index) => write!(f, "[wasm]: unknown function {}", index),
+            Trap::UnknownExport(name) => write!(f, "[wasm]: unknown export {}", name),
+            Trap::Host(message) => write!(f, "[wasm]: {}", message),
+        }
+    }
+}
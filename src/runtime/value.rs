@@ -0,0 +1,222 @@
+use std::fmt;
+
+use crate::primitives::Type;
+use crate::runtime::Func;
+
+// A WebAssembly value. Floats are kept as their native type, NaN payloads
+// survive since they are only ever moved or reinterpreted bit for bit.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    // `None` is the null reference
+    FuncRef(Option<Func>),
+    // a host value, opaque to WebAssembly
+    ExternRef(Option<u32>),
+}
+
+impl Value {
+    // The zero value of a value type, which locals start with.
+    pub fn zero(t: Type) -> Option<Self> {
+        match t {
+            Type::I32 => Some(Value::I32(0)),
+            Type::I64 => Some(Value::I64(0)),
+            Type::F32 => Some(Value::F32(0.0)),
+            Type::F64 => Some(Value::F64(0.0)),
+            Type::AnyFunc => Some(Value::FuncRef(None)),
+            Type::AnyRef => Some(Value::ExternRef(None)),
+            _ => None,
+        }
+    }
+
+    pub fn ty(&self) -> Type {
+        match self {
+            Value::I32(_) => Type::I32,
+            Value::I64(_) => Type::I64,
+            Value::F32(_) => Type::F32,
+            Value::F64(_) => Type::F64,
+            Value::FuncRef(_) => Type::AnyFunc,
+            Value::ExternRef(_) => Type::AnyRef,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        match self {
+            Value::FuncRef(None) | Value::ExternRef(None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::I32(value) => write!(f, "i32:{}", value),
+            Value::I64(value) => write!(f, "i64:{}", value),
+            Value::F32(value) => write!(f, "f32:{}", value),
+            Value::F64(value) => write!(f, "f64:{}", value),
+            Value::FuncRef(Some(func)) => write!(f, "funcref:{:?}", func.ty()),
+            Value::ExternRef(Some(value)) => write!(f, "externref:{}", value),
+            Value::FuncRef(None) | Value::ExternRef(None) => write!(f, "{}:null", self.ty()),
+        }
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::I32(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::I64(value)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Value::F32(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::F64(value)
+    }
+}
+
+// A Rust type of WebAssembly values.
+pub trait WasmType: Sized + Into<Value> {
+    fn ty() -> Type;
+    fn from_value(value: Value) -> Option<Self>;
+}
+
+impl WasmType for i32 {
+    fn ty() -> Type {
+        Type::I32
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::I32(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl WasmType for i64 {
+    fn ty() -> Type {
+        Type::I64
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::I64(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl WasmType for f32 {
+    fn ty() -> Type {
+        Type::F32
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::F32(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl WasmType for f64 {
+    fn ty() -> Type {
+        Type::F64
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::F64(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+// The parameters of a typed function, a `WasmType` or a tuple of them.
+pub trait WasmParams {
+    fn types() -> Vec<Type>;
+    fn into_values(self) -> Vec<Value>;
+}
+
+// The results of a typed function, a `WasmType` or a tuple of them.
+pub trait WasmResults: Sized {
+    fn types() -> Vec<Type>;
+    fn from_values(values: Vec<Value>) -> Option<Self>;
+}
+
+impl<T: WasmType> WasmParams for T {
+    fn types() -> Vec<Type> {
+        vec![T::ty()]
+    }
+
+    fn into_values(self) -> Vec<Value> {
+        vec![self.into()]
+    }
+}
+
+impl<T: WasmType> WasmResults for T {
+    fn types() -> Vec<Type> {
+        vec![T::ty()]
+    }
+
+    fn from_values(values: Vec<Value>) -> Option<Self> {
+        match values.as_slice() {
+            [value] => T::from_value(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case, unused_mut, unused_variables)]
+        impl<$($name: WasmType),*> WasmParams for ($($name,)*) {
+            fn types() -> Vec<Type> {
+                vec![$($name::ty()),*]
+            }
+
+            fn into_values(self) -> Vec<Value> {
+                let ($($name,)*) = self;
+                vec![$($name.into()),*]
+            }
+        }
+
+        #[allow(non_snake_case, unused_mut, unused_variables)]
+        impl<$($name: WasmType),*> WasmResults for ($($name,)*) {
+            fn types() -> Vec<Type> {
+                vec![$($name::ty()),*]
+            }
+
+            fn from_values(values: Vec<Value>) -> Option<Self> {
+                let mut values = values.into_iter();
+                let results = ($($name::from_value(values.next()?)?,)*);
+                if values.next().is_some() {
+                    return None;
+                }
+                Some(results)
+            }
+        }
+    };
+}
+
+impl_tuple!();
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+impl_tuple!(A, B, C, D, E);
+impl_tuple!(A, B, C, D, E, F);
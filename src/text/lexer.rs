@@ -0,0 +1,190 @@
+use std::ops::Range;
+
+use crate::text::printer::is_id_char;
+use crate::{WasmError, WasmResult};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TokenKind {
+    LParen,
+    RParen,
+    // starts with a lowercase letter, e.g. `module` or `i32.add` or `offset=8`
+    Keyword(String),
+    // `$name`, with the `$`
+    Id(String),
+    // the bytes of a string literal, escapes resolved
+    Str(Vec<u8>),
+    // any other run of identifier characters, e.g. numbers
+    Reserved(String),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Token {
+    pub kind: TokenKind,
+    // byte range in the source text
+    pub range: Range<usize>,
+}
+
+// Splits the source of a text module into tokens, skipping white space and comments.
+pub(crate) fn tokenize(text: &str) -> WasmResult<Vec<Token>> {
+    let bytes = text.as_bytes();
+    let mut tokens = vec![];
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let start = position;
+        let kind = match bytes[position] {
+            b' ' | b'\t' | b'\n' | b'\r' => {
+                position += 1;
+                continue;
+            }
+            b';' if bytes.get(position + 1) == Some(&b';') => {
+                while position < bytes.len() && bytes[position] != b'\n' {
+                    position += 1;
+                }
+                continue;
+            }
+            b'(' if bytes.get(position + 1) == Some(&b';') => {
+                position = skip_block_comment(bytes, position)?;
+                continue;
+            }
+            b'(' => {
+                position += 1;
+                TokenKind::LParen
+            }
+            b')' => {
+                position += 1;
+                TokenKind::RParen
+            }
+            b'"' => {
+                let (string, end) = read_string(text, position)?;
+                position = end;
+                TokenKind::Str(string)
+            }
+            _ => {
+                while position < bytes.len() && is_id_char(char::from(bytes[position])) {
+                    position += 1;
+                }
+                if position == start {
+                    return Err(WasmError::UnexpectedToken(char_at(text, start)).at(start));
+                }
+
+                let word = text[start..position].to_string();
+                match bytes[start] {
+                    b'$' if word.len() > 1 => TokenKind::Id(word),
+                    b'a'..=b'z' => TokenKind::Keyword(word),
+                    _ => TokenKind::Reserved(word),
+                }
+            }
+        };
+
+        tokens.push(Token {
+            kind,
+            range: start..position,
+        });
+    }
+
+    Ok(tokens)
+}
+
+// Skips a possibly nested `(; ... ;)` comment starting at `position`.
+fn skip_block_comment(bytes: &[u8], mut position: usize) -> WasmResult<usize> {
+    let start = position;
+    let mut depth = 0;
+    while position + 1 < bytes.len() {
+        match (bytes[position], bytes[position + 1]) {
+            (b'(', b';') => {
+                depth += 1;
+                position += 2;
+            }
+            (b';', b')') => {
+                depth -= 1;
+                position += 2;
+                if depth == 0 {
+                    return Ok(position);
+                }
+            }
+            _ => position += 1,
+        }
+    }
+
+    Err(WasmError::EOF.at(start))
+}
+
+// Reads the string literal starting at `position`, returning its bytes and the
+// offset after the closing quote.
+fn read_string(text: &str, position: usize) -> WasmResult<(Vec<u8>, usize)> {
+    let bytes = text.as_bytes();
+    let mut string = vec![];
+    let mut position = position + 1;
+
+    loop {
+        let byte = *bytes
+            .get(position)
+            .ok_or_else(|| WasmError::EOF.at(position))?;
+        match byte {
+            b'"' => return Ok((string, position + 1)),
+            b'\\' => {
+                let escape = *bytes
+                    .get(position + 1)
+                    .ok_or_else(|| WasmError::EOF.at(position))?;
+                position += 2;
+                match escape {
+                    b't' => string.push(b'\t'),
+                    b'n' => string.push(b'\n'),
+                    b'r' => string.push(b'\r'),
+                    b'"' | b'\'' | b'\\' => string.push(escape),
+                    b'u' => {
+                        let end = text[position..]
+                            .find('}')
+                            .map(|end| position + end)
+                            .filter(|_| bytes.get(position) == Some(&b'{'))
+                            .ok_or_else(|| invalid_escape(text, position - 2))?;
+                        let c = u32::from_str_radix(&text[position + 1..end], 16)
+                            .ok()
+                            .and_then(std::char::from_u32)
+                            .ok_or_else(|| invalid_escape(text, position - 2))?;
+                        let mut buf = [0; 4];
+                        string.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                        position = end + 1;
+                    }
+                    _ => {
+                        let high = hex_digit(escape);
+                        let low = bytes.get(position).cloned().and_then(hex_digit);
+                        match (high, low) {
+                            (Some(high), Some(low)) => string.push(high << 4 | low),
+                            _ => return Err(invalid_escape(text, position - 2)),
+                        }
+                        position += 1;
+                    }
+                }
+            }
+            0x00..=0x1F | 0x7F => {
+                return Err(WasmError::UnexpectedToken(char_at(text, position)).at(position))
+            }
+            _ => {
+                string.push(byte);
+                position += 1;
+            }
+        }
+    }
+}
+
+fn invalid_escape(text: &str, position: usize) -> WasmError {
+    WasmError::UnexpectedToken(text[position..].chars().take(2).collect()).at(position)
+}
+
+fn char_at(text: &str, position: usize) -> String {
+    text[position..]
+        .chars()
+        .next()
+        .map_or_else(String::new, |c| c.to_string())
+}
+
+pub(crate) fn hex_digit(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
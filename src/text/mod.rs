@@ -0,0 +1,9 @@
+mod lexer;
+mod number;
+mod parser;
+mod printer;
+mod wast;
+
+pub use parser::parse;
+pub use printer::{format_f32, format_f64, print, Printer};
+pub use wast::{Outcome, WastRunner};
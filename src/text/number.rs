@@ -0,0 +1,244 @@
+use crate::text::lexer::hex_digit;
+
+pub(crate) fn parse_u32(text: &str) -> Option<u32> {
+    let value = parse_nat(text)?;
+    if value <= u64::from(std::u32::MAX) {
+        Some(value as u32)
+    } else {
+        None
+    }
+}
+
+// Integers may be written signed or unsigned, `-1` and `0xffffffff` are the same i32.
+pub(crate) fn parse_i32(text: &str) -> Option<i32> {
+    match split_sign(text) {
+        (true, digits) => {
+            let value = parse_nat(digits)?;
+            if value <= 1 << 31 {
+                Some((value as i64).wrapping_neg() as i32)
+            } else {
+                None
+            }
+        }
+        (false, digits) => parse_u32(digits).map(|value| value as i32),
+    }
+}
+
+pub(crate) fn parse_i64(text: &str) -> Option<i64> {
+    match split_sign(text) {
+        (true, digits) => {
+            let value = parse_nat(digits)?;
+            if value <= 1 << 63 {
+                Some((value as i64).wrapping_neg())
+            } else {
+                None
+            }
+        }
+        (false, digits) => parse_nat(digits).map(|value| value as i64),
+    }
+}
+
+// Parses a float literal to its bits, rounding to nearest even. Literals that
+// would round to infinity are rejected.
+pub(crate) fn parse_f32(text: &str) -> Option<u32> {
+    parse_float(text, 8, 23).map(|bits| bits as u32)
+}
+
+pub(crate) fn parse_f64(text: &str) -> Option<u64> {
+    parse_float(text, 11, 52)
+}
+
+fn parse_nat(text: &str) -> Option<u64> {
+    let (digits, radix) = if text.starts_with("0x") {
+        (&text[2..], 16)
+    } else {
+        (text, 10)
+    };
+
+    u64::from_str_radix(&strip_underscores(digits, radix)?, radix).ok()
+}
+
+fn split_sign(text: &str) -> (bool, &str) {
+    if text.starts_with('-') {
+        (true, &text[1..])
+    } else if text.starts_with('+') {
+        (false, &text[1..])
+    } else {
+        (false, text)
+    }
+}
+
+// Removes the `_` separating digits, `None` if `text` is not a run of digits.
+fn strip_underscores(text: &str, radix: u32) -> Option<String> {
+    let mut digits = String::with_capacity(text.len());
+    let mut last_is_digit = false;
+    for c in text.chars() {
+        if c == '_' && last_is_digit {
+            last_is_digit = false;
+        } else if c.is_digit(radix) {
+            digits.push(c);
+            last_is_digit = true;
+        } else {
+            return None;
+        }
+    }
+
+    if last_is_digit {
+        Some(digits)
+    } else {
+        None
+    }
+}
+
+fn parse_float(text: &str, exponent_bits: u32, mantissa_bits: u32) -> Option<u64> {
+    let (negative, body) = split_sign(text);
+    let sign = if negative {
+        1 << (exponent_bits + mantissa_bits)
+    } else {
+        0
+    };
+    let infinity = ((1 << exponent_bits) - 1) << mantissa_bits;
+
+    let magnitude = if body == "inf" {
+        infinity
+    } else if body == "nan" {
+        infinity | 1 << (mantissa_bits - 1)
+    } else if body.starts_with("nan:0x") {
+        let payload = u64::from_str_radix(&strip_underscores(&body[6..], 16)?, 16).ok()?;
+        if payload == 0 || payload >> mantissa_bits != 0 {
+            return None;
+        }
+        infinity | payload
+    } else if body.starts_with("0x") {
+        parse_hex_float(&body[2..], exponent_bits, mantissa_bits)?
+    } else {
+        parse_decimal_float(body, mantissa_bits)?
+    };
+
+    Some(sign | magnitude)
+}
+
+fn parse_decimal_float(body: &str, mantissa_bits: u32) -> Option<u64> {
+    let (mantissa, exponent) = match body.find(|c| c == 'e' || c == 'E') {
+        Some(index) => (&body[..index], Some(&body[index + 1..])),
+        None => (body, None),
+    };
+    let (int, frac) = match mantissa.find('.') {
+        Some(index) => (&mantissa[..index], &mantissa[index + 1..]),
+        None => (mantissa, ""),
+    };
+
+    // rebuilt without separators for the standard library parser, which rounds correctly
+    let mut literal = strip_underscores(int, 10)?;
+    if !frac.is_empty() {
+        literal.push('.');
+        literal.push_str(&strip_underscores(frac, 10)?);
+    }
+    if let Some(exponent) = exponent {
+        let (negative, digits) = split_sign(exponent);
+        literal.push_str(if negative { "e-" } else { "e" });
+        literal.push_str(&strip_underscores(digits, 10)?);
+    }
+
+    if mantissa_bits == 23 {
+        let value = literal.parse::<f32>().ok()?;
+        if value.is_infinite() {
+            None
+        } else {
+            Some(u64::from(value.to_bits()))
+        }
+    } else {
+        let value = literal.parse::<f64>().ok()?;
+        if value.is_infinite() {
+            None
+        } else {
+            Some(value.to_bits())
+        }
+    }
+}
+
+fn parse_hex_float(body: &str, exponent_bits: u32, mantissa_bits: u32) -> Option<u64> {
+    let (mantissa, exponent) = match body.find(|c| c == 'p' || c == 'P') {
+        Some(index) => (&body[..index], parse_exponent(&body[index + 1..])?),
+        None => (body, 0),
+    };
+    let (int, frac) = match mantissa.find('.') {
+        Some(index) => (&mantissa[..index], &mantissa[index + 1..]),
+        None => (mantissa, ""),
+    };
+    let int = strip_underscores(int, 16)?;
+    let frac = if frac.is_empty() {
+        String::new()
+    } else {
+        strip_underscores(frac, 16)?
+    };
+
+    // the value is `significand * 2^exponent`, digits beyond the first 15
+    // significant ones only matter for rounding
+    let mut significand: u64 = 0;
+    let mut exponent = exponent - 4 * frac.len() as i64;
+    let mut sticky = false;
+    let mut count = 0;
+    for digit in int.bytes().chain(frac.bytes()) {
+        let digit = u64::from(hex_digit(digit)?);
+        if significand == 0 && digit == 0 {
+            continue;
+        }
+        if count < 15 {
+            significand = significand << 4 | digit;
+            count += 1;
+        } else {
+            sticky |= digit != 0;
+            exponent += 4;
+        }
+    }
+    if significand == 0 {
+        return Some(0);
+    }
+
+    let mantissa_bits_i64 = i64::from(mantissa_bits);
+    let bias = (1 << (exponent_bits - 1)) - 1;
+    let msb = 63 - i64::from(significand.leading_zeros());
+    // the exponent of the lowest bit kept, subnormals keep fewer bits
+    let mut lowest = (msb + exponent - mantissa_bits_i64).max(1 - bias - mantissa_bits_i64);
+    let shift = lowest - exponent;
+    let mut bits = if shift <= 0 {
+        significand << (-shift) as u32
+    } else if shift >= 64 {
+        0
+    } else {
+        let kept = significand >> shift;
+        let rest = significand & ((1 << shift) - 1);
+        let half = 1 << (shift - 1);
+        if rest > half || rest == half && (sticky || kept & 1 == 1) {
+            kept + 1
+        } else {
+            kept
+        }
+    };
+    // rounding up carried into a new bit
+    if bits >> (mantissa_bits + 1) != 0 {
+        bits >>= 1;
+        lowest += 1;
+    }
+
+    if bits >> mantissa_bits == 0 {
+        return Some(bits);
+    }
+    let biased = lowest + mantissa_bits_i64 + bias;
+    if biased >= (1 << exponent_bits) - 1 {
+        return None;
+    }
+    Some((biased as u64) << mantissa_bits | bits & ((1 << mantissa_bits) - 1))
+}
+
+// The decimal exponent of a hex float, saturated far beyond any float's range.
+fn parse_exponent(text: &str) -> Option<i64> {
+    let (negative, digits) = split_sign(text);
+    let mut value: i64 = 0;
+    for c in strip_underscores(digits, 10)?.chars() {
+        value = (value * 10 + i64::from(c.to_digit(10)?)).min(1 << 32);
+    }
+
+    Some(if negative { -value } else { value })
+}
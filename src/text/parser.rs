@@ -0,0 +1,1698 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::primitives::{
+    ElemItems, ExternalKind, GlobalArg, ImportKind, MemArg, Mutability, Operator, ResizableLimit,
+    Section, SectionCodeEntity, SectionDataEntity, SectionElemEntity, SectionExportEntity,
+    SectionFuncEntity, SectionGlobalEntity, SectionImportEntity, SectionMemoryEntity,
+    SectionTableEntity, SectionTypeEntity, SegmentMode, Type,
+};
+use crate::text::lexer::{tokenize, Token, TokenKind};
+use crate::text::number::{parse_f32, parse_f64, parse_i32, parse_i64, parse_u32};
+use crate::{WasmError, WasmResult};
+
+const PAGE_SIZE: usize = 65536;
+
+// Parses a module in the text format to the sections the binary format
+// would have. Operator ranges are byte ranges in `text`.
+pub fn parse(text: &str) -> WasmResult<Vec<Section<'static>>> {
+    let mut parser = TextParser::new(text)?;
+    let sections = parser.module()?;
+    match parser.peek() {
+        None => Ok(sections),
+        Some(_) => Err(parser.unexpected()),
+    }
+}
+
+// The index spaces identifiers refer to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Space {
+    Type,
+    Func,
+    Table,
+    Memory,
+    Global,
+    Elem,
+    Data,
+}
+
+// The entities of the module being parsed.
+#[derive(Default)]
+struct ModuleBuilder {
+    types: Vec<SectionTypeEntity>,
+    imports: Vec<SectionImportEntity<'static>>,
+    functions: Vec<SectionFuncEntity>,
+    tables: Vec<SectionTableEntity>,
+    memories: Vec<SectionMemoryEntity>,
+    globals: Vec<SectionGlobalEntity>,
+    exports: Vec<SectionExportEntity<'static>>,
+    start: Option<u32>,
+    elements: Vec<SectionElemEntity>,
+    codes: Vec<SectionCodeEntity<'static>>,
+    data: Vec<SectionDataEntity<'static>>,
+
+    ids: HashMap<(Space, String), u32>,
+    // the number of entities declared in each index space
+    declared: HashMap<Space, u32>,
+    // the number of entities of each index space read so far
+    defined: HashMap<Space, u32>,
+    // imports must come before any function, table, memory or global definition
+    has_definitions: bool,
+    // `memory.init` and `data.drop` need a data count section
+    needs_data_count: bool,
+}
+
+impl ModuleBuilder {
+    fn declare(&mut self, space: Space, id: Option<String>, imported: bool) -> WasmResult<()> {
+        match space {
+            Space::Type | Space::Elem | Space::Data => {}
+            _ => {
+                if imported && self.has_definitions {
+                    return Err(WasmError::ImportAfterDefinition);
+                }
+                self.has_definitions |= !imported;
+            }
+        }
+
+        let count = self.declared.entry(space).or_insert(0);
+        let index = *count;
+        *count += 1;
+        if let Some(id) = id {
+            if self.ids.insert((space, id.clone()), index).is_some() {
+                return Err(WasmError::DuplicateIdentifier(id));
+            }
+        }
+        Ok(())
+    }
+
+    // The index of the next entity of `space`, in declaration order.
+    fn next_index(&mut self, space: Space) -> u32 {
+        let count = self.defined.entry(space).or_insert(0);
+        *count += 1;
+        *count - 1
+    }
+
+    // Finds the type of an inline signature, adding it after all others if there is none.
+    fn type_index(&mut self, params: Vec<Type>, returns: Vec<Type>) -> u32 {
+        let found = self.types.iter().position(|entity| {
+            entity.form == Type::Func && entity.params == params && entity.returns == returns
+        });
+
+        match found {
+            Some(index) => index as u32,
+            None => {
+                self.types.push(SectionTypeEntity {
+                    form: Type::Func,
+                    params,
+                    returns,
+                });
+                self.types.len() as u32 - 1
+            }
+        }
+    }
+
+    fn finish(self) -> Vec<Section<'static>> {
+        let mut sections = vec![];
+        if !self.types.is_empty() {
+            sections.push(Section::Type {
+                entities: self.types,
+                size_width: 0,
+            });
+        }
+        if !self.imports.is_empty() {
+            sections.push(Section::Import {
+                entities: self.imports,
+                size_width: 0,
+            });
+        }
+        if !self.functions.is_empty() {
+            sections.push(Section::Function {
+                entities: self.functions,
+                size_width: 0,
+            });
+        }
+        if !self.tables.is_empty() {
+            sections.push(Section::Table {
+                entities: self.tables,
+                size_width: 0,
+            });
+        }
+        if !self.memories.is_empty() {
+            sections.push(Section::Memory {
+                entities: self.memories,
+                size_width: 0,
+            });
+        }
+        if !self.globals.is_empty() {
+            sections.push(Section::Global {
+                entities: self.globals,
+                size_width: 0,
+            });
+        }
+        if !self.exports.is_empty() {
+            sections.push(Section::Export {
+                entities: self.exports,
+                size_width: 0,
+            });
+        }
+        if let Some(signature_index) = self.start {
+            sections.push(Section::Start {
+                signature_index,
+                size_width: 0,
+            });
+        }
+        if !self.elements.is_empty() {
+            sections.push(Section::Element {
+                entities: self.elements,
+                size_width: 0,
+            });
+        }
+        if self.needs_data_count {
+            sections.push(Section::DataCount {
+                count: self.data.len() as u32,
+                size_width: 0,
+            });
+        }
+        if !self.codes.is_empty() {
+            sections.push(Section::Code {
+                entities: self.codes,
+                size_width: 0,
+            });
+        }
+        if !self.data.is_empty() {
+            sections.push(Section::Data {
+                entities: self.data,
+                size_width: 0,
+            });
+        }
+
+        sections
+    }
+}
+
+// The state of the function body or constant expression being parsed.
+#[derive(Default)]
+struct FuncState {
+    locals: HashMap<String, u32>,
+    // the identifier of each enclosing block, innermost last
+    labels: Vec<Option<String>>,
+    code: Vec<Operator>,
+    ranges: Vec<Range<usize>>,
+}
+
+impl FuncState {
+    fn push(&mut self, operator: Operator, range: Range<usize>) {
+        self.code.push(operator);
+        self.ranges.push(range);
+    }
+}
+
+pub(crate) struct TextParser<'a> {
+    text: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl<'a> TextParser<'a> {
+    pub fn new(text: &'a str) -> WasmResult<Self> {
+        Ok(Self {
+            text,
+            tokens: tokenize(text)?,
+            position: 0,
+        })
+    }
+
+    // Parses `(module $id? field*)`, or a bare list of fields.
+    pub fn module(&mut self) -> WasmResult<Vec<Section<'static>>> {
+        let wrapped = self.peek_field() == Some("module");
+        if wrapped {
+            self.position += 2;
+            self.id();
+        }
+
+        let mut builder = ModuleBuilder::default();
+        let start = self.position;
+        while self.peek_field().is_some() {
+            self.declare_field(&mut builder)?;
+        }
+        self.position = start;
+        while self.peek_field().is_some() {
+            self.field(&mut builder)?;
+        }
+
+        if wrapped {
+            self.rparen()?;
+        }
+        Ok(builder.finish())
+    }
+
+    // Declares the identifier of a field ahead of parsing, so that fields can
+    // refer to the ones defined after them.
+    fn declare_field(&mut self, builder: &mut ModuleBuilder) -> WasmResult<()> {
+        let start = self.position;
+        let offset = self.offset();
+        let field = self.field_keyword()?;
+        match field.as_str() {
+            "type" => {
+                let id = self.id();
+                let entity = self.type_definition()?;
+                builder
+                    .declare(Space::Type, id, false)
+                    .map_err(|err| err.at(offset))?;
+                builder.types.push(entity);
+            }
+            "import" => {
+                self.name()?;
+                self.name()?;
+                self.lparen()?;
+                let space = match self.keyword()?.as_str() {
+                    "func" => Space::Func,
+                    "table" => Space::Table,
+                    "memory" => Space::Memory,
+                    "global" => Space::Global,
+                    _ => return Err(self.unexpected_at(self.position - 1)),
+                };
+                let id = self.id();
+                builder
+                    .declare(space, id, true)
+                    .map_err(|err| err.at(offset))?;
+            }
+            "func" | "table" | "memory" | "global" => {
+                let space = match field.as_str() {
+                    "func" => Space::Func,
+                    "table" => Space::Table,
+                    "memory" => Space::Memory,
+                    _ => Space::Global,
+                };
+                let id = self.id();
+                while self.peek_field() == Some("export") {
+                    self.skip_sexpr()?;
+                }
+                let imported = self.peek_field() == Some("import");
+                builder
+                    .declare(space, id, imported)
+                    .map_err(|err| err.at(offset))?;
+                // inline segments take the next index of their space
+                if space == Space::Memory && self.peek_field() == Some("data") {
+                    builder.declare(Space::Data, None, false)?;
+                } else if space == Space::Table && !imported && self.peek_keyword().is_some() {
+                    builder.declare(Space::Elem, None, false)?;
+                }
+            }
+            "elem" | "data" => {
+                let space = if field == "elem" {
+                    Space::Elem
+                } else {
+                    Space::Data
+                };
+                let id = self.id();
+                builder
+                    .declare(space, id, false)
+                    .map_err(|err| err.at(offset))?;
+            }
+            _ => {}
+        }
+
+        self.position = start;
+        self.skip_sexpr()
+    }
+
+    fn field(&mut self, builder: &mut ModuleBuilder) -> WasmResult<()> {
+        let start = self.position;
+        let offset = self.offset();
+        let field = self.field_keyword()?;
+        match field.as_str() {
+            // declared ahead
+            "type" => {
+                self.position = start;
+                return self.skip_sexpr();
+            }
+            "import" => self.import(builder)?,
+            "func" => self.func(builder, offset)?,
+            "table" => self.table(builder)?,
+            "memory" => self.memory(builder)?,
+            "global" => self.global(builder)?,
+            "export" => {
+                let name = self.name()?;
+                self.lparen()?;
+                let kind = match self.keyword()?.as_str() {
+                    "func" => ExternalKind::Func {
+                        signature_index: self.index(builder, Space::Func)?,
+                    },
+                    "table" => ExternalKind::Table {
+                        table_index: self.index(builder, Space::Table)?,
+                    },
+                    "memory" => ExternalKind::Memory {
+                        mem_index: self.index(builder, Space::Memory)?,
+                    },
+                    "global" => ExternalKind::Global {
+                        global_index: self.index(builder, Space::Global)?,
+                    },
+                    _ => return Err(self.unexpected_at(self.position - 1)),
+                };
+                self.rparen()?;
+                builder.exports.push(SectionExportEntity {
+                    name: name.into(),
+                    kind,
+                });
+            }
+            "start" => {
+                if builder.start.is_some() {
+                    return Err(WasmError::MultipleStart.at(offset));
+                }
+                builder.start = Some(self.index(builder, Space::Func)?);
+            }
+            "elem" => self.elem(builder)?,
+            "data" => self.data(builder)?,
+            _ => return Err(self.unexpected_at(start + 1)),
+        }
+
+        self.rparen()
+    }
+
+    // `(func (param ...)* (result ...)*)`
+    fn type_definition(&mut self) -> WasmResult<SectionTypeEntity> {
+        self.lparen()?;
+        self.expect_keyword("func")?;
+        let (params, returns) = self.signature(None)?;
+        self.rparen()?;
+
+        Ok(SectionTypeEntity {
+            form: Type::Func,
+            params,
+            returns,
+        })
+    }
+
+    fn import(&mut self, builder: &mut ModuleBuilder) -> WasmResult<()> {
+        let mod_name = self.name()?;
+        let field_name = self.name()?;
+        self.lparen()?;
+        let kind = match self.keyword()?.as_str() {
+            "func" => {
+                builder.next_index(Space::Func);
+                self.id();
+                ImportKind::Func {
+                    signature_index: self.type_use(builder, None)?,
+                }
+            }
+            "table" => {
+                builder.next_index(Space::Table);
+                self.id();
+                let limit = self.limit()?;
+                ImportKind::Table {
+                    limit,
+                    elem_type: self.elem_type()?,
+                }
+            }
+            "memory" => {
+                builder.next_index(Space::Memory);
+                self.id();
+                ImportKind::Memory {
+                    limit: self.limit()?,
+                }
+            }
+            "global" => {
+                builder.next_index(Space::Global);
+                self.id();
+                ImportKind::Global {
+                    global_arg: self.global_type()?,
+                }
+            }
+            _ => return Err(self.unexpected_at(self.position - 1)),
+        };
+        self.rparen()?;
+
+        builder.imports.push(SectionImportEntity {
+            mod_name: mod_name.into(),
+            field_name: field_name.into(),
+            kind,
+        });
+        Ok(())
+    }
+
+    // Reads the `(export "name")` and `(import "module" "name")` abbreviations of
+    // a definition, returning the names of the import if there is one.
+    fn inline_exports(
+        &mut self,
+        builder: &mut ModuleBuilder,
+        kind: ExternalKind,
+    ) -> WasmResult<Option<(String, String)>> {
+        let mut names = vec![];
+        while self.peek_field() == Some("export") {
+            self.position += 2;
+            names.push(self.name()?);
+            self.rparen()?;
+        }
+        for name in names {
+            builder.exports.push(SectionExportEntity {
+                name: name.into(),
+                kind,
+            });
+        }
+
+        if self.peek_field() == Some("import") {
+            self.position += 2;
+            let mod_name = self.name()?;
+            let field_name = self.name()?;
+            self.rparen()?;
+            Ok(Some((mod_name, field_name)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn func(&mut self, builder: &mut ModuleBuilder, offset: usize) -> WasmResult<()> {
+        let index = builder.next_index(Space::Func);
+        self.id();
+        let import = self.inline_exports(
+            builder,
+            ExternalKind::Func {
+                signature_index: index,
+            },
+        )?;
+
+        if let Some((mod_name, field_name)) = import {
+            let signature_index = self.type_use(builder, None)?;
+            builder.imports.push(SectionImportEntity {
+                mod_name: mod_name.into(),
+                field_name: field_name.into(),
+                kind: ImportKind::Func { signature_index },
+            });
+            return Ok(());
+        }
+
+        let mut state = FuncState::default();
+        let signature_index = self.type_use(builder, Some(&mut state.locals))?;
+        let params = builder
+            .types
+            .get(signature_index as usize)
+            .map_or(0, |entity| entity.params.len());
+
+        let mut locals = vec![];
+        while self.peek_field() == Some("local") {
+            self.position += 2;
+            match self.id() {
+                Some(id) => {
+                    let local_index = (params + locals.len()) as u32;
+                    if state.locals.insert(id.clone(), local_index).is_some() {
+                        return Err(self.error(WasmError::DuplicateIdentifier(id)));
+                    }
+                    locals.push(self.value_type()?);
+                }
+                None => {
+                    while self.peek() != Some(&TokenKind::RParen) {
+                        locals.push(self.value_type()?);
+                    }
+                }
+            }
+            self.rparen()?;
+        }
+
+        self.instrs(builder, &mut state)?;
+        let end = self.next_range();
+        state.push(Operator::End, end.clone());
+
+        builder.functions.push(SectionFuncEntity {
+            signature_index: signature_index as usize,
+        });
+        builder.codes.push(SectionCodeEntity {
+            locals,
+            code: state.code,
+            range: offset..end.end,
+            code_ranges: state.ranges,
+            body: None,
+            lazy: false,
+        });
+        Ok(())
+    }
+
+    fn table(&mut self, builder: &mut ModuleBuilder) -> WasmResult<()> {
+        let index = builder.next_index(Space::Table);
+        self.id();
+        let import = self.inline_exports(builder, ExternalKind::Table { table_index: index })?;
+
+        if let Some((mod_name, field_name)) = import {
+            let limit = self.limit()?;
+            let elem_type = self.elem_type()?;
+            builder.imports.push(SectionImportEntity {
+                mod_name: mod_name.into(),
+                field_name: field_name.into(),
+                kind: ImportKind::Table { elem_type, limit },
+            });
+            return Ok(());
+        }
+
+        // `(table funcref (elem ...))` declares a table sized for its elements
+        if self.peek_keyword().is_some() {
+            let elem_type = self.elem_type()?;
+            self.lparen()?;
+            self.expect_keyword("elem")?;
+            // expressions are parenthesized, function indices are not
+            let elems = if self.peek() == Some(&TokenKind::LParen) {
+                self.elem_exprs(builder)?
+            } else {
+                self.elem_functions(builder)?
+            };
+            self.rparen()?;
+
+            let size = elems.len() as u32;
+            builder.tables.push(SectionTableEntity {
+                elem_type,
+                limit: ResizableLimit {
+                    initial: size,
+                    max: Some(size),
+                },
+            });
+            builder.elements.push(SectionElemEntity {
+                mode: SegmentMode::Active,
+                table_index: index,
+                offset: vec![Operator::I32Const { val: 0 }, Operator::End],
+                elem_type,
+                elems,
+            });
+            return Ok(());
+        }
+
+        let limit = self.limit()?;
+        let elem_type = self.elem_type()?;
+        builder.tables.push(SectionTableEntity { elem_type, limit });
+        Ok(())
+    }
+
+    fn memory(&mut self, builder: &mut ModuleBuilder) -> WasmResult<()> {
+        let index = builder.next_index(Space::Memory);
+        self.id();
+        let import = self.inline_exports(builder, ExternalKind::Memory { mem_index: index })?;
+
+        if let Some((mod_name, field_name)) = import {
+            let limit = self.limit()?;
+            builder.imports.push(SectionImportEntity {
+                mod_name: mod_name.into(),
+                field_name: field_name.into(),
+                kind: ImportKind::Memory { limit },
+            });
+            return Ok(());
+        }
+
+        // `(memory (data ...))` declares a memory sized for its data
+        if self.peek_field() == Some("data") {
+            self.position += 2;
+            let data = self.strings()?;
+            self.rparen()?;
+
+            let pages = ((data.len() + PAGE_SIZE - 1) / PAGE_SIZE) as u32;
+            builder.memories.push(SectionMemoryEntity {
+                limit: ResizableLimit {
+                    initial: pages,
+                    max: Some(pages),
+                },
+            });
+            builder.data.push(SectionDataEntity {
+                mode: SegmentMode::Active,
+                memid: index,
+                expr: vec![Operator::I32Const { val: 0 }, Operator::End],
+                data: data.into(),
+            });
+            return Ok(());
+        }
+
+        let limit = self.limit()?;
+        builder.memories.push(SectionMemoryEntity { limit });
+        Ok(())
+    }
+
+    fn global(&mut self, builder: &mut ModuleBuilder) -> WasmResult<()> {
+        let index = builder.next_index(Space::Global);
+        self.id();
+        let import = self.inline_exports(
+            builder,
+            ExternalKind::Global {
+                global_index: index,
+            },
+        )?;
+        let global_arg = self.global_type()?;
+
+        if let Some((mod_name, field_name)) = import {
+            builder.imports.push(SectionImportEntity {
+                mod_name: mod_name.into(),
+                field_name: field_name.into(),
+                kind: ImportKind::Global { global_arg },
+            });
+            return Ok(());
+        }
+
+        let expr = self.const_expr(builder)?;
+        builder
+            .globals
+            .push(SectionGlobalEntity { global_arg, expr });
+        Ok(())
+    }
+
+    // `(elem id? tableidx? offset elemlist)`, `(elem id? elemlist)` for a passive
+    // segment or `(elem id? declare elemlist)` for a declared one, where the list
+    // is `func? funcidx*` or `reftype item*`
+    fn elem(&mut self, builder: &mut ModuleBuilder) -> WasmResult<()> {
+        self.id();
+        let table_index = self.segment_target(builder, "table", Space::Table)?;
+        let (mode, offset) = if self.peek() == Some(&TokenKind::LParen) {
+            (SegmentMode::Active, self.folded_expr(builder, "offset")?)
+        } else if self.eat_keyword("declare") {
+            (SegmentMode::Declared, vec![])
+        } else {
+            (SegmentMode::Passive, vec![])
+        };
+        let (elem_type, elems) = match self.peek_keyword() {
+            Some("funcref") | Some("externref") => (self.elem_type()?, self.elem_exprs(builder)?),
+            _ => {
+                self.eat_keyword("func");
+                (Type::AnyFunc, self.elem_functions(builder)?)
+            }
+        };
+
+        builder.elements.push(SectionElemEntity {
+            mode,
+            table_index,
+            offset,
+            elem_type,
+            elems,
+        });
+        Ok(())
+    }
+
+    fn elem_functions(&mut self, builder: &ModuleBuilder) -> WasmResult<ElemItems> {
+        let mut functions = vec![];
+        while self.peek() != Some(&TokenKind::RParen) {
+            functions.push(self.index(builder, Space::Func)?);
+        }
+        Ok(ElemItems::Functions(functions))
+    }
+
+    fn elem_exprs(&mut self, builder: &mut ModuleBuilder) -> WasmResult<ElemItems> {
+        let mut exprs = vec![];
+        while self.peek() != Some(&TokenKind::RParen) {
+            exprs.push(self.folded_expr(builder, "item")?);
+        }
+        Ok(ElemItems::Expressions(exprs))
+    }
+
+    // `(data id? memidx? offset string*)`, or `(data id? string*)` for a passive segment
+    fn data(&mut self, builder: &mut ModuleBuilder) -> WasmResult<()> {
+        self.id();
+        let memid = self.segment_target(builder, "memory", Space::Memory)?;
+        let (mode, expr) = match self.peek() {
+            Some(TokenKind::LParen) => (SegmentMode::Active, self.folded_expr(builder, "offset")?),
+            _ => (SegmentMode::Passive, vec![]),
+        };
+        let data = self.strings()?;
+
+        builder.data.push(SectionDataEntity {
+            mode,
+            memid,
+            expr,
+            data: data.into(),
+        });
+        Ok(())
+    }
+
+    // The table or memory of a segment, written bare or as `(table idx)`, 0 if omitted.
+    fn segment_target(
+        &mut self,
+        builder: &ModuleBuilder,
+        keyword: &str,
+        space: Space,
+    ) -> WasmResult<u32> {
+        if self.peek_field() == Some(keyword) {
+            self.position += 2;
+            let index = self.index(builder, space)?;
+            self.rparen()?;
+            return Ok(index);
+        }
+
+        if self.index_ahead(0) {
+            self.index(builder, space)
+        } else {
+            Ok(0)
+        }
+    }
+
+    // `(offset instr*)` or `(item instr*)` as named by `keyword`, or a single
+    // folded instruction.
+    fn folded_expr(
+        &mut self,
+        builder: &mut ModuleBuilder,
+        keyword: &str,
+    ) -> WasmResult<Vec<Operator>> {
+        let mut state = FuncState::default();
+        if self.peek_field() == Some(keyword) {
+            self.position += 2;
+            self.instrs(builder, &mut state)?;
+            self.rparen()?;
+        } else {
+            self.folded_instr(builder, &mut state)?;
+        }
+
+        state.push(Operator::End, 0..0);
+        Ok(state.code)
+    }
+
+    fn const_expr(&mut self, builder: &mut ModuleBuilder) -> WasmResult<Vec<Operator>> {
+        let mut state = FuncState::default();
+        self.instrs(builder, &mut state)?;
+        state.push(Operator::End, 0..0);
+        Ok(state.code)
+    }
+
+    // `(type idx)? (param ...)* (result ...)*`, adding the type if it is only
+    // given inline. Named parameters are added to `locals`.
+    fn type_use(
+        &mut self,
+        builder: &mut ModuleBuilder,
+        locals: Option<&mut HashMap<String, u32>>,
+    ) -> WasmResult<u32> {
+        let offset = self.offset();
+        let index = if self.peek_field() == Some("type") {
+            self.position += 2;
+            let index = self.index(builder, Space::Type)?;
+            self.rparen()?;
+            Some(index)
+        } else {
+            None
+        };
+
+        let inline = self.peek_field() == Some("param") || self.peek_field() == Some("result");
+        let (params, returns) = self.signature(locals)?;
+        match index {
+            Some(index) => {
+                if inline {
+                    match builder.types.get(index as usize) {
+                        Some(entity) if entity.params == params && entity.returns == returns => {}
+                        _ => return Err(WasmError::InlineFunctionType.at(offset)),
+                    }
+                }
+                Ok(index)
+            }
+            None => Ok(builder.type_index(params, returns)),
+        }
+    }
+
+    fn signature(
+        &mut self,
+        mut locals: Option<&mut HashMap<String, u32>>,
+    ) -> WasmResult<(Vec<Type>, Vec<Type>)> {
+        let mut params = vec![];
+        while self.peek_field() == Some("param") {
+            self.position += 2;
+            match self.id() {
+                Some(id) => {
+                    if let Some(locals) = locals.as_mut() {
+                        if locals.insert(id.clone(), params.len() as u32).is_some() {
+                            return Err(self.error(WasmError::DuplicateIdentifier(id)));
+                        }
+                    }
+                    params.push(self.value_type()?);
+                }
+                None => {
+                    while self.peek() != Some(&TokenKind::RParen) {
+                        params.push(self.value_type()?);
+                    }
+                }
+            }
+            self.rparen()?;
+        }
+
+        let mut returns = vec![];
+        while self.peek_field() == Some("result") {
+            self.position += 2;
+            while self.peek() != Some(&TokenKind::RParen) {
+                returns.push(self.value_type()?);
+            }
+            self.rparen()?;
+        }
+
+        Ok((params, returns))
+    }
+
+    // Parses instructions up to a closing parenthesis or a flat `end` or `else`.
+    fn instrs(&mut self, builder: &mut ModuleBuilder, state: &mut FuncState) -> WasmResult<()> {
+        loop {
+            match self.peek() {
+                Some(TokenKind::LParen) => self.folded_instr(builder, state)?,
+                Some(TokenKind::Keyword(keyword)) if keyword != "end" && keyword != "else" => {
+                    self.flat_instr(builder, state)?
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn flat_instr(&mut self, builder: &mut ModuleBuilder, state: &mut FuncState) -> WasmResult<()> {
+        let start = self.offset();
+        let name = self.keyword()?;
+        match name.as_str() {
+            "block" | "loop" | "if" => {
+                let label = self.id();
+                let t = self.block_type()?;
+                state.push(block_operator(&name, t), start..self.last_end());
+                state.labels.push(label.clone());
+
+                self.instrs(builder, state)?;
+                if name == "if" && self.peek_keyword() == Some("else") {
+                    let start = self.offset();
+                    self.position += 1;
+                    self.end_label(&label)?;
+                    state.push(Operator::Else, start..self.last_end());
+                    self.instrs(builder, state)?;
+                }
+
+                let start = self.offset();
+                self.expect_keyword("end")?;
+                self.end_label(&label)?;
+                state.push(Operator::End, start..self.last_end());
+                state.labels.pop();
+            }
+            _ => {
+                let operator = self.plain_instr(&name, builder, state)?;
+                state.push(operator, start..self.last_end());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn folded_instr(
+        &mut self,
+        builder: &mut ModuleBuilder,
+        state: &mut FuncState,
+    ) -> WasmResult<()> {
+        self.lparen()?;
+        let start = self.offset();
+        let name = self.keyword()?;
+        match name.as_str() {
+            "block" | "loop" => {
+                let label = self.id();
+                let t = self.block_type()?;
+                state.push(block_operator(&name, t), start..self.last_end());
+                state.labels.push(label);
+                self.instrs(builder, state)?;
+                state.push(Operator::End, self.next_range());
+                state.labels.pop();
+            }
+            "if" => {
+                let label = self.id();
+                let t = self.block_type()?;
+                let range = start..self.last_end();
+                while self.peek_field().is_some() && self.peek_field() != Some("then") {
+                    self.folded_instr(builder, state)?;
+                }
+                state.push(Operator::If { t }, range);
+                state.labels.push(label);
+
+                self.lparen()?;
+                self.expect_keyword("then")?;
+                self.instrs(builder, state)?;
+                self.rparen()?;
+                if self.peek_field() == Some("else") {
+                    let start = self.offset();
+                    self.position += 2;
+                    state.push(Operator::Else, start..self.last_end());
+                    self.instrs(builder, state)?;
+                    self.rparen()?;
+                }
+
+                state.push(Operator::End, self.next_range());
+                state.labels.pop();
+            }
+            _ => {
+                let operator = self.plain_instr(&name, builder, state)?;
+                let range = start..self.last_end();
+                self.instrs(builder, state)?;
+                state.push(operator, range);
+            }
+        }
+
+        self.rparen()
+    }
+
+    // The optional identifier after `else` or `end` must repeat the block's label.
+    fn end_label(&mut self, label: &Option<String>) -> WasmResult<()> {
+        if let Some(TokenKind::Id(id)) = self.peek() {
+            if label.as_ref() != Some(id) {
+                return Err(self.unexpected());
+            }
+            self.position += 1;
+        }
+        Ok(())
+    }
+
+    fn block_type(&mut self) -> WasmResult<Type> {
+        if self.peek_field() != Some("result") {
+            return Ok(Type::EmptyBlockType);
+        }
+
+        self.position += 2;
+        let t = self.value_type()?;
+        self.rparen()?;
+        Ok(t)
+    }
+
+    // An instruction other than a block, with `name` read.
+    fn plain_instr(
+        &mut self,
+        name: &str,
+        builder: &mut ModuleBuilder,
+        state: &FuncState,
+    ) -> WasmResult<Operator> {
+        if let Some(operator) = simple_operator(name) {
+            return Ok(operator);
+        }
+
+        let operator = match name {
+            "br" => Operator::Br {
+                relative_depth: self.label(state)?,
+            },
+            "br_if" => Operator::BrIf {
+                relative_depth: self.label(state)?,
+            },
+            "br_table" => {
+                let mut target_table = vec![self.label(state)?];
+                while let Some(TokenKind::Id(_)) | Some(TokenKind::Reserved(_)) = self.peek() {
+                    target_table.push(self.label(state)?);
+                }
+                let default_target = target_table.pop().unwrap_or_default();
+                Operator::BrTable {
+                    target_table,
+                    default_target,
+                }
+            }
+            "call" => Operator::Call {
+                function_index: self.index(builder, Space::Func)?,
+            },
+            // `call_indirect tableidx? typeuse`
+            "call_indirect" => {
+                let table_index = self.table_index(builder)?;
+                Operator::CallIndirect {
+                    type_index: self.type_use(builder, None)?,
+                    table_index,
+                }
+            }
+            // `select (result t)*`
+            "select" => {
+                let mut types = vec![];
+                while self.peek_field() == Some("result") {
+                    self.position += 2;
+                    while self.peek() != Some(&TokenKind::RParen) {
+                        types.push(self.value_type()?);
+                    }
+                    self.rparen()?;
+                }
+                if types.is_empty() {
+                    Operator::Select
+                } else {
+                    Operator::TypedSelect { types }
+                }
+            }
+
+            "local.get" => Operator::LocalGet {
+                local_index: self.local(state)?,
+            },
+            "local.set" => Operator::LocalSet {
+                local_index: self.local(state)?,
+            },
+            "local.tee" => Operator::LocalTee {
+                local_index: self.local(state)?,
+            },
+            "global.get" => Operator::GlobalGet {
+                global_index: self.index(builder, Space::Global)?,
+            },
+            "global.set" => Operator::GlobalSet {
+                global_index: self.index(builder, Space::Global)?,
+            },
+            "table.get" => Operator::TableGet {
+                table_index: self.table_index(builder)?,
+            },
+            "table.set" => Operator::TableSet {
+                table_index: self.table_index(builder)?,
+            },
+            "table.size" => Operator::TableSize {
+                table_index: self.table_index(builder)?,
+            },
+            "table.grow" => Operator::TableGrow {
+                table_index: self.table_index(builder)?,
+            },
+            "table.fill" => Operator::TableFill {
+                table_index: self.table_index(builder)?,
+            },
+            "ref.null" => Operator::RefNull {
+                t: self.heap_type()?,
+            },
+            "ref.func" => Operator::RefFunc {
+                function_index: self.index(builder, Space::Func)?,
+            },
+
+            "i32.load" => Operator::I32Load {
+                memarg: self.memarg(2)?,
+            },
+            "i64.load" => Operator::I64Load {
+                memarg: self.memarg(3)?,
+            },
+            "f32.load" => Operator::F32Load {
+                memarg: self.memarg(2)?,
+            },
+            "f64.load" => Operator::F64Load {
+                memarg: self.memarg(3)?,
+            },
+            "i32.load8_s" => Operator::I32Load8s {
+                memarg: self.memarg(0)?,
+            },
+            "i32.load8_u" => Operator::I32Load8u {
+                memarg: self.memarg(0)?,
+            },
+            "i32.load16_s" => Operator::I32Load16s {
+                memarg: self.memarg(1)?,
+            },
+            "i32.load16_u" => Operator::I32Load16u {
+                memarg: self.memarg(1)?,
+            },
+            "i64.load8_s" => Operator::I64Load8s {
+                memarg: self.memarg(0)?,
+            },
+            "i64.load8_u" => Operator::I64Load8u {
+                memarg: self.memarg(0)?,
+            },
+            "i64.load16_s" => Operator::I64Load16s {
+                memarg: self.memarg(1)?,
+            },
+            "i64.load16_u" => Operator::I64Load16u {
+                memarg: self.memarg(1)?,
+            },
+            "i64.load32_s" => Operator::I64Load32s {
+                memarg: self.memarg(2)?,
+            },
+            "i64.load32_u" => Operator::I64Load32u {
+                memarg: self.memarg(2)?,
+            },
+            "i32.store" => Operator::I32Store {
+                memarg: self.memarg(2)?,
+            },
+            "i64.store" => Operator::I64Store {
+                memarg: self.memarg(3)?,
+            },
+            "f32.store" => Operator::F32Store {
+                memarg: self.memarg(2)?,
+            },
+            "f64.store" => Operator::F64Store {
+                memarg: self.memarg(3)?,
+            },
+            "i32.store8" => Operator::I32Store8 {
+                memarg: self.memarg(0)?,
+            },
+            "i32.store16" => Operator::I32Store16 {
+                memarg: self.memarg(1)?,
+            },
+            "i64.store8" => Operator::I64Store8 {
+                memarg: self.memarg(0)?,
+            },
+            "i64.store16" => Operator::I64Store16 {
+                memarg: self.memarg(1)?,
+            },
+            "i64.store32" => Operator::I64Store32 {
+                memarg: self.memarg(2)?,
+            },
+            "memory.size" => Operator::MemorySize { size: 0 },
+            "memory.grow" => Operator::MemoryGrow { grow: 0 },
+            "memory.init" => {
+                builder.needs_data_count = true;
+                Operator::MemoryInit {
+                    data_index: self.index(builder, Space::Data)?,
+                    mem: 0,
+                }
+            }
+            "data.drop" => {
+                builder.needs_data_count = true;
+                Operator::DataDrop {
+                    data_index: self.index(builder, Space::Data)?,
+                }
+            }
+            "memory.copy" => Operator::MemoryCopy {
+                dst_mem: 0,
+                src_mem: 0,
+            },
+            "memory.fill" => Operator::MemoryFill { mem: 0 },
+            // `table.init tableidx? elemidx`
+            "table.init" => {
+                let table_index = if self.index_ahead(1) {
+                    self.index(builder, Space::Table)?
+                } else {
+                    0
+                };
+                Operator::TableInit {
+                    elem_index: self.index(builder, Space::Elem)?,
+                    table_index,
+                }
+            }
+            "elem.drop" => Operator::ElemDrop {
+                elem_index: self.index(builder, Space::Elem)?,
+            },
+            // `table.copy (tableidx tableidx)?`
+            "table.copy" => {
+                let (dst_table, src_table) = if self.index_ahead(0) {
+                    let dst_table = self.index(builder, Space::Table)?;
+                    (dst_table, self.index(builder, Space::Table)?)
+                } else {
+                    (0, 0)
+                };
+                Operator::TableCopy {
+                    dst_table,
+                    src_table,
+                }
+            }
+
+            "i32.const" => Operator::I32Const {
+                val: self.number(parse_i32)?,
+            },
+            "i64.const" => Operator::I64Const {
+                val: self.number(parse_i64)?,
+            },
+            "f32.const" => Operator::F32Const {
+                val: self.number(parse_f32)?,
+            },
+            "f64.const" => Operator::F64Const {
+                val: self.number(parse_f64)?,
+            },
+
+            _ => return Err(WasmError::UnknownOperator(name.to_string()).at(self.last_start())),
+        };
+
+        Ok(operator)
+    }
+
+    // `offset=N? align=N?`, the alignment given in bytes and stored as an exponent.
+    fn memarg(&mut self, natural: u32) -> WasmResult<MemArg> {
+        let offset = match self.peek_keyword() {
+            Some(keyword) if keyword.starts_with("offset=") => {
+                let value = keyword[7..].to_string();
+                self.position += 1;
+                parse_u32(&value).ok_or_else(|| self.invalid_number(&value))?
+            }
+            _ => 0,
+        };
+        let align = match self.peek_keyword() {
+            Some(keyword) if keyword.starts_with("align=") => {
+                let value = keyword[6..].to_string();
+                self.position += 1;
+                match parse_u32(&value) {
+                    Some(align) if align.is_power_of_two() => align.trailing_zeros(),
+                    _ => return Err(self.invalid_number(&value)),
+                }
+            }
+            _ => natural,
+        };
+
+        Ok(MemArg { align, offset })
+    }
+
+    fn limit(&mut self) -> WasmResult<ResizableLimit> {
+        let initial = self.number(parse_u32)?;
+        let max = match self.peek() {
+            Some(TokenKind::Reserved(_)) => Some(self.number(parse_u32)?),
+            _ => None,
+        };
+        Ok(ResizableLimit { initial, max })
+    }
+
+    fn value_type(&mut self) -> WasmResult<Type> {
+        match self.keyword()?.as_str() {
+            "i32" => Ok(Type::I32),
+            "i64" => Ok(Type::I64),
+            "f32" => Ok(Type::F32),
+            "f64" => Ok(Type::F64),
+            "funcref" => Ok(Type::AnyFunc),
+            "externref" => Ok(Type::AnyRef),
+            _ => Err(self.unexpected_at(self.position - 1)),
+        }
+    }
+
+    fn elem_type(&mut self) -> WasmResult<Type> {
+        match self.keyword()?.as_str() {
+            "funcref" | "anyfunc" => Ok(Type::AnyFunc),
+            "externref" => Ok(Type::AnyRef),
+            _ => Err(self.unexpected_at(self.position - 1)),
+        }
+    }
+
+    // The type of `ref.null`, `func` or `extern`.
+    fn heap_type(&mut self) -> WasmResult<Type> {
+        match self.keyword()?.as_str() {
+            "func" => Ok(Type::AnyFunc),
+            "extern" => Ok(Type::AnyRef),
+            _ => Err(self.unexpected_at(self.position - 1)),
+        }
+    }
+
+    // `t` or `(mut t)`
+    fn global_type(&mut self) -> WasmResult<GlobalArg> {
+        if self.peek_field() == Some("mut") {
+            self.position += 2;
+            let content_type = self.value_type()?;
+            self.rparen()?;
+            return Ok(GlobalArg {
+                content_type,
+                mutability: Mutability::Var,
+            });
+        }
+
+        Ok(GlobalArg {
+            content_type: self.value_type()?,
+            mutability: Mutability::Const,
+        })
+    }
+
+    // A numeric index or an identifier of `space`.
+    fn index(&mut self, builder: &ModuleBuilder, space: Space) -> WasmResult<u32> {
+        if let Some(TokenKind::Id(id)) = self.peek() {
+            let id = id.clone();
+            let index = builder.ids.get(&(space, id.clone())).cloned();
+            return match index {
+                Some(index) => {
+                    self.position += 1;
+                    Ok(index)
+                }
+                None => Err(self.error(WasmError::UnknownIdentifier(id))),
+            };
+        }
+
+        self.number(parse_u32)
+    }
+
+    // Whether the token `n` places ahead is an index.
+    // An optional table index, 0 if omitted.
+    fn table_index(&mut self, builder: &ModuleBuilder) -> WasmResult<u32> {
+        if self.index_ahead(0) {
+            self.index(builder, Space::Table)
+        } else {
+            Ok(0)
+        }
+    }
+
+    fn index_ahead(&self, n: usize) -> bool {
+        match self.tokens.get(self.position + n).map(|token| &token.kind) {
+            Some(TokenKind::Id(_)) | Some(TokenKind::Reserved(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn local(&mut self, state: &FuncState) -> WasmResult<u32> {
+        if let Some(TokenKind::Id(id)) = self.peek() {
+            let index = state.locals.get(id).cloned();
+            return match index {
+                Some(index) => {
+                    self.position += 1;
+                    Ok(index)
+                }
+                None => Err(self.error(WasmError::UnknownIdentifier(id.clone()))),
+            };
+        }
+
+        self.number(parse_u32)
+    }
+
+    // A label as the relative depth of the block it names.
+    fn label(&mut self, state: &FuncState) -> WasmResult<u32> {
+        if let Some(TokenKind::Id(id)) = self.peek() {
+            let depth = state
+                .labels
+                .iter()
+                .rev()
+                .position(|label| label.as_ref() == Some(id));
+            return match depth {
+                Some(depth) => {
+                    self.position += 1;
+                    Ok(depth as u32)
+                }
+                None => Err(self.error(WasmError::UnknownIdentifier(id.clone()))),
+            };
+        }
+
+        self.number(parse_u32)
+    }
+
+    pub fn number<T, F: Fn(&str) -> Option<T>>(&mut self, parse: F) -> WasmResult<T> {
+        match self.peek() {
+            Some(TokenKind::Reserved(text)) | Some(TokenKind::Keyword(text)) => {
+                let value = parse(text).ok_or_else(|| self.invalid_number(text))?;
+                self.position += 1;
+                Ok(value)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    pub fn strings(&mut self) -> WasmResult<Vec<u8>> {
+        let mut bytes = vec![];
+        while let Some(TokenKind::Str(string)) = self.peek() {
+            bytes.extend_from_slice(string);
+            self.position += 1;
+        }
+        Ok(bytes)
+    }
+
+    pub fn name(&mut self) -> WasmResult<String> {
+        let offset = self.offset();
+        match self.peek() {
+            Some(TokenKind::Str(string)) => {
+                let name = String::from_utf8(string.clone())
+                    .map_err(|_| WasmError::MalformedUtf8.at(offset))?;
+                self.position += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    pub fn id(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(TokenKind::Id(id)) => {
+                let id = id.clone();
+                self.position += 1;
+                Some(id)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn keyword(&mut self) -> WasmResult<String> {
+        match self.peek() {
+            Some(TokenKind::Keyword(keyword)) => {
+                let keyword = keyword.clone();
+                self.position += 1;
+                Ok(keyword)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    pub fn expect_keyword(&mut self, expected: &str) -> WasmResult<()> {
+        match self.peek_keyword() {
+            Some(keyword) if keyword == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    pub fn eat_keyword(&mut self, expected: &str) -> bool {
+        let found = self.peek_keyword() == Some(expected);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    // `(` and the keyword of a module field.
+    fn field_keyword(&mut self) -> WasmResult<String> {
+        self.lparen()?;
+        self.keyword()
+    }
+
+    pub fn lparen(&mut self) -> WasmResult<()> {
+        match self.peek() {
+            Some(TokenKind::LParen) => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    pub fn rparen(&mut self) -> WasmResult<()> {
+        match self.peek() {
+            Some(TokenKind::RParen) => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    // Skips a parenthesized expression.
+    pub fn skip_sexpr(&mut self) -> WasmResult<()> {
+        self.lparen()?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.peek() {
+                Some(TokenKind::LParen) => depth += 1,
+                Some(TokenKind::RParen) => depth -= 1,
+                Some(_) => {}
+                None => return Err(self.unexpected()),
+            }
+            self.position += 1;
+        }
+        Ok(())
+    }
+
+    // The position in the tokens, to backtrack to with `reset`.
+    pub fn mark(&self) -> usize {
+        self.position
+    }
+
+    pub fn reset(&mut self, mark: usize) {
+        self.position = mark;
+    }
+
+    pub fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.position).map(|token| &token.kind)
+    }
+
+    fn peek_keyword(&self) -> Option<&str> {
+        match self.peek() {
+            Some(TokenKind::Keyword(keyword)) => Some(keyword),
+            _ => None,
+        }
+    }
+
+    // The keyword after an opening parenthesis.
+    pub fn peek_field(&self) -> Option<&str> {
+        match (self.peek(), self.tokens.get(self.position + 1)) {
+            (Some(TokenKind::LParen), Some(token)) => match &token.kind {
+                TokenKind::Keyword(keyword) => Some(keyword),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    // The offset of the next token, or of the end of the text.
+    pub fn offset(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map_or(self.text.len(), |token| token.range.start)
+    }
+
+    // The range of the next token, where the `end` of a folded block is.
+    fn next_range(&self) -> Range<usize> {
+        let end = self.text.len();
+        self.tokens
+            .get(self.position)
+            .map_or(end..end, |token| token.range.clone())
+    }
+
+    fn last_start(&self) -> usize {
+        self.tokens[self.position - 1].range.start
+    }
+
+    fn last_end(&self) -> usize {
+        self.tokens[self.position - 1].range.end
+    }
+
+    fn error(&self, err: WasmError) -> WasmError {
+        err.at(self.offset())
+    }
+
+    fn invalid_number(&self, text: &str) -> WasmError {
+        self.error(WasmError::InvalidNumber(text.to_string()))
+    }
+
+    pub fn unexpected(&self) -> WasmError {
+        self.unexpected_at(self.position)
+    }
+
+    fn unexpected_at(&self, position: usize) -> WasmError {
+        match self.tokens.get(position) {
+            Some(token) => {
+                let text = self.text[token.range.clone()].to_string();
+                WasmError::UnexpectedToken(text).at(token.range.start)
+            }
+            None => WasmError::EOF.at(self.text.len()),
+        }
+    }
+}
+
+fn block_operator(name: &str, t: Type) -> Operator {
+    match name {
+        "block" => Operator::Block { t },
+        "loop" => Operator::Loop { t },
+        _ => Operator::If { t },
+    }
+}
+
+// The instructions without immediates.
+fn simple_operator(name: &str) -> Option<Operator> {
+    let operator = match name {
+        "unreachable" => Operator::Unreachable,
+        "nop" => Operator::Nop,
+        "return" => Operator::Return,
+        "drop" => Operator::Drop,
+
+        "i32.eqz" => Operator::I32Eqz,
+        "i32.eq" => Operator::I32Eq,
+        "i32.ne" => Operator::I32Ne,
+        "i32.lt_s" => Operator::I32LtS,
+        "i32.lt_u" => Operator::I32LtU,
+        "i32.gt_s" => Operator::I32GtS,
+        "i32.gt_u" => Operator::I32GtU,
+        "i32.le_s" => Operator::I32LeS,
+        "i32.le_u" => Operator::I32LeU,
+        "i32.ge_s" => Operator::I32GeS,
+        "i32.ge_u" => Operator::I32GeU,
+
+        "i64.eqz" => Operator::I64Eqz,
+        "i64.eq" => Operator::I64Eq,
+        "i64.ne" => Operator::I64Ne,
+        "i64.lt_s" => Operator::I64LtS,
+        "i64.lt_u" => Operator::I64LtU,
+        "i64.gt_s" => Operator::I64GtS,
+        "i64.gt_u" => Operator::I64GtU,
+        "i64.le_s" => Operator::I64LeS,
+        "i64.le_u" => Operator::I64LeU,
+        "i64.ge_s" => Operator::I64GeS,
+        "i64.ge_u" => Operator::I64GeU,
+
+        "f32.eq" => Operator::F32Eq,
+        "f32.ne" => Operator::F32Ne,
+        "f32.lt" => Operator::F32Lt,
+        "f32.gt" => Operator::F32Gt,
+        "f32.le" => Operator::F32Le,
+        "f32.ge" => Operator::F32Ge,
+
+        "f64.eq" => Operator::F64Eq,
+        "f64.ne" => Operator::F64Ne,
+        "f64.lt" => Operator::F64Lt,
+        "f64.gt" => Operator::F64Gt,
+        "f64.le" => Operator::F64Le,
+        "f64.ge" => Operator::F64Ge,
+
+        "i32.clz" => Operator::I32Clz,
+        "i32.ctz" => Operator::I32Ctz,
+        "i32.popcnt" => Operator::I32Popcnt,
+        "i32.add" => Operator::I32Add,
+        "i32.sub" => Operator::I32Sub,
+        "i32.mul" => Operator::I32Mul,
+        "i32.div_s" => Operator::I32DivS,
+        "i32.div_u" => Operator::I32DivU,
+        "i32.rem_s" => Operator::I32RemS,
+        "i32.rem_u" => Operator::I32RemU,
+        "i32.and" => Operator::I32And,
+        "i32.or" => Operator::I32Or,
+        "i32.xor" => Operator::I32Xor,
+        "i32.shl" => Operator::I32Shl,
+        "i32.shr_s" => Operator::I32ShrS,
+        "i32.shr_u" => Operator::I32ShrU,
+        "i32.rotl" => Operator::I32Rotl,
+        "i32.rotr" => Operator::I32Rotr,
+
+        "i64.clz" => Operator::I64Clz,
+        "i64.ctz" => Operator::I64Ctz,
+        "i64.popcnt" => Operator::I64Popcnt,
+        "i64.add" => Operator::I64Add,
+        "i64.sub" => Operator::I64Sub,
+        "i64.mul" => Operator::I64Mul,
+        "i64.div_s" => Operator::I64DivS,
+        "i64.div_u" => Operator::I64DivU,
+        "i64.rem_s" => Operator::I64RemS,
+        "i64.rem_u" => Operator::I64RemU,
+        "i64.and" => Operator::I64And,
+        "i64.or" => Operator::I64Or,
+        "i64.xor" => Operator::I64Xor,
+        "i64.shl" => Operator::I64Shl,
+        "i64.shr_s" => Operator::I64ShrS,
+        "i64.shr_u" => Operator::I64ShrU,
+        "i64.rotl" => Operator::I64Rotl,
+        "i64.rotr" => Operator::I64Rotr,
+
+        "f32.abs" => Operator::F32Abs,
+        "f32.neg" => Operator::F32Neg,
+        "f32.ceil" => Operator::F32Ceil,
+        "f32.floor" => Operator::F32Floor,
+        "f32.trunc" => Operator::F32Trunc,
+        "f32.nearest" => Operator::F32Nearest,
+        "f32.sqrt" => Operator::F32Sqrt,
+        "f32.add" => Operator::F32Add,
+        "f32.sub" => Operator::F32Sub,
+        "f32.mul" => Operator::F32Mul,
+        "f32.div" => Operator::F32Div,
+        "f32.min" => Operator::F32Min,
+        "f32.max" => Operator::F32Max,
+        "f32.copysign" => Operator::F32Copysign,
+
+        "f64.abs" => Operator::F64Abs,
+        "f64.neg" => Operator::F64Neg,
+        "f64.ceil" => Operator::F64Ceil,
+        "f64.floor" => Operator::F64Floor,
+        "f64.trunc" => Operator::F64Trunc,
+        "f64.nearest" => Operator::F64Nearest,
+        "f64.sqrt" => Operator::F64Sqrt,
+        "f64.add" => Operator::F64Add,
+        "f64.sub" => Operator::F64Sub,
+        "f64.mul" => Operator::F64Mul,
+        "f64.div" => Operator::F64Div,
+        "f64.min" => Operator::F64Min,
+        "f64.max" => Operator::F64Max,
+        "f64.copysign" => Operator::F64Copysign,
+
+        "i32.wrap_i64" => Operator::I32WrapI64,
+        "i32.trunc_f32_s" => Operator::I32TruncSF32,
+        "i32.trunc_f32_u" => Operator::I32TruncUF32,
+        "i32.trunc_f64_s" => Operator::I32TruncSF64,
+        "i32.trunc_f64_u" => Operator::I32TruncUF64,
+        "i64.extend_i32_s" => Operator::I64ExtendSI32,
+        "i64.extend_i32_u" => Operator::I64ExtendUI32,
+        "i64.trunc_f32_s" => Operator::I64TruncSF32,
+        "i64.trunc_f32_u" => Operator::I64TruncUF32,
+        "i64.trunc_f64_s" => Operator::I64TruncSF64,
+        "i64.trunc_f64_u" => Operator::I64TruncUF64,
+        "f32.convert_i32_s" => Operator::F32ConvertSI32,
+        "f32.convert_i32_u" => Operator::F32ConvertUI32,
+        "f32.convert_i64_s" => Operator::F32ConvertSI64,
+        "f32.convert_i64_u" => Operator::F32ConvertUI64,
+        "f32.demote_f64" => Operator::F32DemoteF64,
+        "f64.convert_i32_s" => Operator::F64ConvertSI32,
+        "f64.convert_i32_u" => Operator::F64ConvertUI32,
+        "f64.convert_i64_s" => Operator::F64ConvertSI64,
+        "f64.convert_i64_u" => Operator::F64ConvertUI64,
+        "f64.promote_f32" => Operator::F64PromoteF32,
+        "i32.reinterpret_f32" => Operator::I32ReinterpretF32,
+        "i64.reinterpret_f64" => Operator::I64ReinterpretF64,
+        "f32.reinterpret_i32" => Operator::F32ReinterpretI32,
+        "f64.reinterpret_i64" => Operator::F64ReinterpretI64,
+        "i32.extend8_s" => Operator::I32Extend8S,
+        "i32.extend16_s" => Operator::I32Extend16S,
+        "i64.extend8_s" => Operator::I64Extend8S,
+        "i64.extend16_s" => Operator::I64Extend16S,
+        "i64.extend32_s" => Operator::I64Extend32S,
+        "ref.is_null" => Operator::RefIsNull,
+        "i32.trunc_sat_f32_s" => Operator::I32TruncSatSF32,
+        "i32.trunc_sat_f32_u" => Operator::I32TruncSatUF32,
+        "i32.trunc_sat_f64_s" => Operator::I32TruncSatSF64,
+        "i32.trunc_sat_f64_u" => Operator::I32TruncSatUF64,
+        "i64.trunc_sat_f32_s" => Operator::I64TruncSatSF32,
+        "i64.trunc_sat_f32_u" => Operator::I64TruncSatUF32,
+        "i64.trunc_sat_f64_s" => Operator::I64TruncSatSF64,
+        "i64.trunc_sat_f64_u" => Operator::I64TruncSatUF64,
+
+        _ => return None,
+    };
+
+    Some(operator)
+}
@@ -0,0 +1,1232 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::module::Module;
+use crate::primitives::{
+    ElemItems, ExternalKind, GlobalArg, ImportKind, MemArg, Mutability, Operator, ResizableLimit,
+    SectionCodeEntity, SegmentMode, Type,
+};
+
+const INDENT: &str = "  ";
+
+// Renders `module` in the text format, one instruction per line.
+pub fn print(module: &Module) -> String {
+    Printer::new(module).print()
+}
+
+// `{}` prints a module with flat instructions and `{:#}` with folded ones.
+impl fmt::Display for Module<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&Printer::new(self).folded(f.alternate()).print())
+    }
+}
+
+// Renders a module in the WebAssembly text format.
+pub struct Printer<'a> {
+    module: &'a Module<'a>,
+    folded: bool,
+    module_name: Option<String>,
+    // the `$identifier` of functions and of the locals of each function
+    functions: HashMap<u32, String>,
+    locals: HashMap<u32, HashMap<u32, String>>,
+    out: String,
+    indent: usize,
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(module: &'a Module<'a>) -> Self {
+        // the name section is only debug information, a malformed one is ignored
+        let names = module.names().unwrap_or_default();
+
+        Self {
+            module,
+            folded: false,
+            module_name: names.module.as_ref().and_then(|name| identifier(name)),
+            functions: identifiers(&names.functions),
+            locals: names
+                .locals
+                .iter()
+                .map(|(index, locals)| (*index, identifiers(locals)))
+                .collect(),
+            out: String::new(),
+            indent: 0,
+        }
+    }
+
+    // Prints instructions as nested s-expressions instead of one per line.
+    pub fn folded(mut self, folded: bool) -> Self {
+        self.folded = folded;
+        self
+    }
+
+    pub fn print(mut self) -> String {
+        match self.module_name.take() {
+            Some(name) => self.out.push_str(&format!("(module {}", name)),
+            None => self.out.push_str("(module"),
+        }
+        self.indent += 1;
+
+        let module = self.module;
+        for (index, entity) in module.types.iter().enumerate() {
+            let mut text = format!("(type (;{};) (func", index);
+            push_signature(&mut text, &entity.params, &entity.returns, None);
+            text.push_str("))");
+            self.line(&text);
+        }
+        self.print_imports();
+        let imported = module.imported_functions().count();
+        for (index, entity) in module.functions.iter().enumerate() {
+            self.print_function(
+                (imported + index) as u32,
+                entity.signature_index as u32,
+                module.codes.get(index),
+            );
+        }
+        let imported = module.imported_tables().count();
+        for (index, entity) in module.tables.iter().enumerate() {
+            self.line(&format!(
+                "(table (;{};) {} {})",
+                imported + index,
+                limit_text(&entity.limit),
+                entity.elem_type
+            ));
+        }
+        let imported = module.imported_memories().count();
+        for (index, entity) in module.memories.iter().enumerate() {
+            self.line(&format!(
+                "(memory (;{};) {})",
+                imported + index,
+                limit_text(&entity.limit)
+            ));
+        }
+        let imported = module.imported_globals().count();
+        for (index, entity) in module.globals.iter().enumerate() {
+            self.line(&format!(
+                "(global (;{};) {} {})",
+                imported + index,
+                global_type_text(&entity.global_arg),
+                self.const_expr_text(&entity.expr)
+            ));
+        }
+        for entity in &module.exports {
+            let mut text = String::from("(export ");
+            push_string(&mut text, entity.name.as_bytes());
+            text.push_str(&match entity.kind {
+                ExternalKind::Func { signature_index } => {
+                    format!(" (func {}))", self.function_ref(signature_index))
+                }
+                ExternalKind::Table { table_index } => format!(" (table {}))", table_index),
+                ExternalKind::Memory { mem_index } => format!(" (memory {}))", mem_index),
+                ExternalKind::Global { global_index } => format!(" (global {}))", global_index),
+            });
+            self.line(&text);
+        }
+        if let Some(index) = module.start {
+            self.line(&format!("(start {})", self.function_ref(index)));
+        }
+        for (index, entity) in module.elements.iter().enumerate() {
+            let mut text = format!("(elem (;{};)", index);
+            match entity.mode {
+                SegmentMode::Active => {
+                    if entity.table_index != 0 {
+                        text.push_str(&format!(" {}", entity.table_index));
+                    }
+                    text.push_str(&format!(" {}", self.const_expr_text(&entity.offset)));
+                }
+                SegmentMode::Passive => {}
+                SegmentMode::Declared => text.push_str(" declare"),
+            }
+            match &entity.elems {
+                ElemItems::Functions(functions) => {
+                    // active segments keep the abbreviation without `func`
+                    if entity.mode != SegmentMode::Active {
+                        text.push_str(" func");
+                    }
+                    for function_index in functions {
+                        text.push_str(&format!(" {}", self.function_ref(*function_index)));
+                    }
+                }
+                ElemItems::Expressions(exprs) => {
+                    text.push_str(&format!(" {}", entity.elem_type));
+                    for expr in exprs {
+                        text.push_str(&format!(" {}", self.const_expr_text(expr)));
+                    }
+                }
+            }
+            text.push(')');
+            self.line(&text);
+        }
+        for (index, entity) in module.data.iter().enumerate() {
+            let mut text = format!("(data (;{};) ", index);
+            if entity.mode == SegmentMode::Active {
+                if entity.memid != 0 {
+                    text.push_str(&format!("{} ", entity.memid));
+                }
+                text.push_str(&format!("{} ", self.const_expr_text(&entity.expr)));
+            }
+            push_string(&mut text, &entity.data);
+            text.push(')');
+            self.line(&text);
+        }
+
+        self.out.push_str(")\n");
+        self.out
+    }
+
+    fn print_imports(&mut self) {
+        let (mut functions, mut tables, mut memories, mut globals) = (0, 0, 0, 0);
+        for entity in &self.module.imports {
+            let mut text = String::from("(import ");
+            push_string(&mut text, entity.mod_name.as_bytes());
+            text.push(' ');
+            push_string(&mut text, entity.field_name.as_bytes());
+            match &entity.kind {
+                ImportKind::Func { signature_index } => {
+                    text.push_str(&format!(
+                        " (func {} (type {})",
+                        self.function_id(functions),
+                        signature_index
+                    ));
+                    if let Some(signature) = self.module.func_type(*signature_index) {
+                        push_signature(&mut text, &signature.params, &signature.returns, None);
+                    }
+                    text.push(')');
+                    functions += 1;
+                }
+                ImportKind::Table { elem_type, limit } => {
+                    text.push_str(&format!(
+                        " (table (;{};) {} {})",
+                        tables,
+                        limit_text(limit),
+                        elem_type
+                    ));
+                    tables += 1;
+                }
+                ImportKind::Memory { limit } => {
+                    text.push_str(&format!(" (memory (;{};) {})", memories, limit_text(limit)));
+                    memories += 1;
+                }
+                ImportKind::Global { global_arg } => {
+                    text.push_str(&format!(
+                        " (global (;{};) {})",
+                        globals,
+                        global_type_text(global_arg)
+                    ));
+                    globals += 1;
+                }
+            }
+            text.push(')');
+            self.line(&text);
+        }
+    }
+
+    fn print_function(
+        &mut self,
+        function_index: u32,
+        signature_index: u32,
+        body: Option<&SectionCodeEntity>,
+    ) {
+        let mut text = format!(
+            "(func {} (type {})",
+            self.function_id(function_index),
+            signature_index
+        );
+        let names = self.locals.get(&function_index).cloned();
+        let names = names.as_ref();
+        let params = match self.module.func_type(signature_index) {
+            Some(signature) => {
+                push_signature(&mut text, &signature.params, &signature.returns, names);
+                signature.params.len()
+            }
+            None => 0,
+        };
+        self.line(&text);
+
+        self.indent += 1;
+        if let Some(body) = body {
+            if !body.locals.is_empty() {
+                let mut text = String::new();
+                push_locals(&mut text, "local", params, &body.locals, names);
+                self.line(text.trim_start());
+            }
+
+            let folded = if self.folded {
+                self.fold_function(function_index, &body.code)
+            } else {
+                None
+            };
+            match folded {
+                Some(nodes) => {
+                    for node in &nodes {
+                        self.print_node(node);
+                    }
+                }
+                None => self.print_flat(function_index, &body.code),
+            }
+        }
+        self.indent -= 1;
+        self.close();
+    }
+
+    fn print_flat(&mut self, function_index: u32, code: &[Operator]) {
+        // the `end` of the function body is implicit
+        let code = match code.split_last() {
+            Some((Operator::End, rest)) => rest,
+            _ => code,
+        };
+
+        for operator in code {
+            let text = self.operator_text(function_index, operator);
+            match operator {
+                Operator::Block { .. } | Operator::Loop { .. } | Operator::If { .. } => {
+                    self.line(&text);
+                    self.indent += 1;
+                }
+                Operator::Else => {
+                    self.indent = self.indent.saturating_sub(1);
+                    self.line(&text);
+                    self.indent += 1;
+                }
+                Operator::End => {
+                    self.indent = self.indent.saturating_sub(1);
+                    self.line(&text);
+                }
+                _ => self.line(&text),
+            }
+        }
+    }
+
+    // Folds a function body into s-expressions, or returns `None` if its
+    // blocks are not well nested.
+    fn fold_function(&self, function_index: u32, code: &[Operator]) -> Option<Vec<Node>> {
+        let results = self
+            .module
+            .function(function_index)
+            .map_or(0, |function| function.signature().returns.len());
+        let mut folder = Folder {
+            printer: self,
+            function_index,
+            code,
+            position: 0,
+            labels: vec![results],
+        };
+
+        let (nodes, end) = folder.fold_block()?;
+        match end {
+            Operator::End if folder.position == code.len() => Some(nodes),
+            _ => None,
+        }
+    }
+
+    fn print_node(&mut self, node: &Node) {
+        match &node.kind {
+            NodeKind::Plain { text, children } => {
+                self.line(&format!("({}", text));
+                self.print_nodes(children);
+                self.close();
+            }
+            NodeKind::If {
+                text,
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.line(&format!("({}", text));
+                self.print_nodes(condition);
+                self.indent += 1;
+                self.line("(then");
+                self.print_nodes(then);
+                self.close();
+                if let Some(otherwise) = otherwise {
+                    self.line("(else");
+                    self.print_nodes(otherwise);
+                    self.close();
+                }
+                self.indent -= 1;
+                self.close();
+            }
+        }
+    }
+
+    fn print_nodes(&mut self, nodes: &[Node]) {
+        self.indent += 1;
+        for node in nodes {
+            self.print_node(node);
+        }
+        self.indent -= 1;
+    }
+
+    fn line(&mut self, text: &str) {
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(text);
+    }
+
+    fn close(&mut self) {
+        self.out.push(')');
+    }
+
+    // The identifier of a function where it is defined.
+    fn function_id(&self, index: u32) -> String {
+        match self.functions.get(&index) {
+            Some(id) => id.clone(),
+            None => format!("(;{};)", index),
+        }
+    }
+
+    // The identifier of a function where it is referenced.
+    fn function_ref(&self, index: u32) -> String {
+        match self.functions.get(&index) {
+            Some(id) => id.clone(),
+            None => index.to_string(),
+        }
+    }
+
+    fn local_ref(&self, function_index: u32, index: u32) -> String {
+        match self
+            .locals
+            .get(&function_index)
+            .and_then(|locals| locals.get(&index))
+        {
+            Some(id) => id.clone(),
+            None => index.to_string(),
+        }
+    }
+
+    // A constant expression with each instruction folded, e.g. `(i32.const 0)`.
+    fn const_expr_text(&self, expr: &[Operator]) -> String {
+        let code = match expr.split_last() {
+            Some((Operator::End, rest)) => rest,
+            _ => expr,
+        };
+
+        code.iter()
+            .map(|operator| format!("({})", self.operator_text(0, operator)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn operator_text(&self, function_index: u32, operator: &Operator) -> String {
+        let name = operator_name(operator);
+        match operator {
+            Operator::Block { t } | Operator::Loop { t } | Operator::If { t } => {
+                match block_type_text(*t) {
+                    Some(result) => format!("{} {}", name, result),
+                    None => name.to_string(),
+                }
+            }
+            Operator::Br { relative_depth } | Operator::BrIf { relative_depth } => {
+                format!("{} {}", name, relative_depth)
+            }
+            Operator::BrTable {
+                target_table,
+                default_target,
+            } => {
+                let mut text = name.to_string();
+                for depth in target_table.iter().chain(Some(default_target)) {
+                    text.push_str(&format!(" {}", depth));
+                }
+                text
+            }
+            Operator::Call { function_index } => {
+                format!("{} {}", name, self.function_ref(*function_index))
+            }
+            Operator::CallIndirect {
+                type_index,
+                table_index: 0,
+            } => format!("{} (type {})", name, type_index),
+            Operator::CallIndirect {
+                type_index,
+                table_index,
+            } => format!("{} {} (type {})", name, table_index, type_index),
+            Operator::TypedSelect { types } => {
+                let mut text = name.to_string();
+                for t in types {
+                    text.push_str(&format!(" (result {})", t));
+                }
+                text
+            }
+            Operator::LocalGet { local_index }
+            | Operator::LocalSet { local_index }
+            | Operator::LocalTee { local_index } => {
+                format!("{} {}", name, self.local_ref(function_index, *local_index))
+            }
+            Operator::GlobalGet { global_index } | Operator::GlobalSet { global_index } => {
+                format!("{} {}", name, global_index)
+            }
+            Operator::TableGet { table_index }
+            | Operator::TableSet { table_index }
+            | Operator::TableSize { table_index }
+            | Operator::TableGrow { table_index }
+            | Operator::TableFill { table_index } => format!("{} {}", name, table_index),
+            Operator::RefNull { t } => format!("{} {}", name, heap_type_text(*t)),
+            Operator::RefFunc { function_index } => {
+                format!("{} {}", name, self.function_ref(*function_index))
+            }
+            Operator::MemoryInit { data_index, .. } | Operator::DataDrop { data_index } => {
+                format!("{} {}", name, data_index)
+            }
+            Operator::TableInit {
+                elem_index,
+                table_index: 0,
+            } => format!("{} {}", name, elem_index),
+            Operator::TableInit {
+                elem_index,
+                table_index,
+            } => format!("{} {} {}", name, table_index, elem_index),
+            Operator::ElemDrop { elem_index } => format!("{} {}", name, elem_index),
+            Operator::TableCopy {
+                dst_table: 0,
+                src_table: 0,
+            } => name.to_string(),
+            Operator::TableCopy {
+                dst_table,
+                src_table,
+            } => format!("{} {} {}", name, dst_table, src_table),
+            Operator::I32Const { val } => format!("{} {}", name, val),
+            Operator::I64Const { val } => format!("{} {}", name, val),
+            Operator::F32Const { val } => format!("{} {}", name, format_f32(*val)),
+            Operator::F64Const { val } => format!("{} {}", name, format_f64(*val)),
+            _ => match memarg(operator) {
+                Some((memarg, natural)) => {
+                    let mut text = name.to_string();
+                    if memarg.offset != 0 {
+                        text.push_str(&format!(" offset={}", memarg.offset));
+                    }
+                    if memarg.align != natural {
+                        let align = 1u64.checked_shl(memarg.align).unwrap_or(0);
+                        text.push_str(&format!(" align={}", align));
+                    }
+                    text
+                }
+                None => name.to_string(),
+            },
+        }
+    }
+}
+
+// An instruction folded together with the instructions computing its operands.
+struct Node {
+    kind: NodeKind,
+    // the number of values the instruction leaves on the stack
+    results: usize,
+}
+
+enum NodeKind {
+    // also covers `block` and `loop`, whose children are their body
+    Plain {
+        text: String,
+        children: Vec<Node>,
+    },
+    If {
+        text: String,
+        condition: Vec<Node>,
+        then: Vec<Node>,
+        otherwise: Option<Vec<Node>>,
+    },
+}
+
+struct Folder<'a, 'b> {
+    printer: &'b Printer<'a>,
+    function_index: u32,
+    code: &'b [Operator],
+    position: usize,
+    // the arity of each enclosing label, innermost last
+    labels: Vec<usize>,
+}
+
+impl<'a, 'b> Folder<'a, 'b> {
+    // Folds instructions up to the next `else` or `end`, which is returned
+    // along with the nodes.
+    fn fold_block(&mut self) -> Option<(Vec<Node>, &'b Operator)> {
+        let mut stack: Vec<Node> = vec![];
+        loop {
+            let operator = self.code.get(self.position)?;
+            self.position += 1;
+
+            let text = self.printer.operator_text(self.function_index, operator);
+            let node = match operator {
+                Operator::Else | Operator::End => return Some((stack, operator)),
+                Operator::Block { t } | Operator::Loop { t } => {
+                    let arity = block_arity(*t);
+                    let label = match operator {
+                        Operator::Loop { .. } => 0,
+                        _ => arity,
+                    };
+                    self.labels.push(label);
+                    let (body, end) = self.fold_block()?;
+                    self.labels.pop();
+                    match end {
+                        Operator::End => {}
+                        _ => return None,
+                    }
+
+                    Node {
+                        kind: NodeKind::Plain {
+                            text,
+                            children: body,
+                        },
+                        results: arity,
+                    }
+                }
+                Operator::If { t } => {
+                    let arity = block_arity(*t);
+                    let condition = take_operands(&mut stack, 1);
+                    self.labels.push(arity);
+                    let (then, end) = self.fold_block()?;
+                    let otherwise = match end {
+                        Operator::Else => match self.fold_block()? {
+                            (otherwise, Operator::End) => Some(otherwise),
+                            _ => return None,
+                        },
+                        _ => None,
+                    };
+                    self.labels.pop();
+
+                    Node {
+                        kind: NodeKind::If {
+                            text,
+                            condition,
+                            then,
+                            otherwise,
+                        },
+                        results: arity,
+                    }
+                }
+                _ => {
+                    let (params, results) = self.arity(operator);
+                    Node {
+                        kind: NodeKind::Plain {
+                            text,
+                            children: take_operands(&mut stack, params),
+                        },
+                        results,
+                    }
+                }
+            };
+            stack.push(node);
+        }
+    }
+
+    fn label_arity(&self, relative_depth: u32) -> usize {
+        let depth = relative_depth as usize;
+        if depth < self.labels.len() {
+            self.labels[self.labels.len() - 1 - depth]
+        } else {
+            0
+        }
+    }
+
+    // The number of operands an instruction pops and of results it pushes.
+    fn arity(&self, operator: &Operator) -> (usize, usize) {
+        let module = self.printer.module;
+        match operator {
+            Operator::Br { relative_depth } => (self.label_arity(*relative_depth), 0),
+            Operator::BrIf { relative_depth } => {
+                let arity = self.label_arity(*relative_depth);
+                (arity + 1, arity)
+            }
+            Operator::BrTable { default_target, .. } => (self.label_arity(*default_target) + 1, 0),
+            Operator::Return => (self.labels[0], 0),
+            Operator::Call { function_index } => match module.function(*function_index) {
+                Some(function) => {
+                    let signature = function.signature();
+                    (signature.params.len(), signature.returns.len())
+                }
+                None => (0, 0),
+            },
+            Operator::CallIndirect { type_index, .. } => match module.func_type(*type_index) {
+                Some(signature) => (signature.params.len() + 1, signature.returns.len()),
+                None => (1, 0),
+            },
+            Operator::Drop | Operator::LocalSet { .. } | Operator::GlobalSet { .. } => (1, 0),
+            Operator::Select | Operator::TypedSelect { .. } => (3, 1),
+            Operator::LocalGet { .. }
+            | Operator::GlobalGet { .. }
+            | Operator::TableSize { .. }
+            | Operator::RefNull { .. }
+            | Operator::RefFunc { .. }
+            | Operator::MemorySize { .. }
+            | Operator::I32Const { .. }
+            | Operator::I64Const { .. }
+            | Operator::F32Const { .. }
+            | Operator::F64Const { .. } => (0, 1),
+            Operator::LocalTee { .. }
+            | Operator::MemoryGrow { .. }
+            | Operator::TableGet { .. }
+            | Operator::RefIsNull => (1, 1),
+            Operator::TableSet { .. } => (2, 0),
+            Operator::TableGrow { .. } => (2, 1),
+            Operator::MemoryInit { .. }
+            | Operator::MemoryCopy { .. }
+            | Operator::MemoryFill { .. }
+            | Operator::TableInit { .. }
+            | Operator::TableCopy { .. }
+            | Operator::TableFill { .. } => (3, 0),
+            Operator::I32Store { .. }
+            | Operator::I64Store { .. }
+            | Operator::F32Store { .. }
+            | Operator::F64Store { .. }
+            | Operator::I32Store8 { .. }
+            | Operator::I32Store16 { .. }
+            | Operator::I64Store8 { .. }
+            | Operator::I64Store16 { .. }
+            | Operator::I64Store32 { .. } => (2, 0),
+            Operator::Unreachable
+            | Operator::Nop
+            | Operator::Block { .. }
+            | Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Else
+            | Operator::End
+            | Operator::DataDrop { .. }
+            | Operator::ElemDrop { .. } => (0, 0),
+            _ if memarg(operator).is_some() => (1, 1),
+            _ if is_binary(operator) => (2, 1),
+            // the remaining numeric instructions are unary
+            _ => (1, 1),
+        }
+    }
+}
+
+// Takes the nodes on top of `stack` producing exactly `count` values, or none
+// if they do not line up.
+fn take_operands(stack: &mut Vec<Node>, count: usize) -> Vec<Node> {
+    let mut needed = count;
+    let mut taken = 0;
+    for node in stack.iter().rev() {
+        if needed == 0 || node.results == 0 || node.results > needed {
+            break;
+        }
+        needed -= node.results;
+        taken += 1;
+    }
+
+    if needed == 0 {
+        stack.split_off(stack.len() - taken)
+    } else {
+        vec![]
+    }
+}
+
+fn block_arity(t: Type) -> usize {
+    match t {
+        Type::EmptyBlockType => 0,
+        _ => 1,
+    }
+}
+
+fn is_binary(operator: &Operator) -> bool {
+    match operator {
+        Operator::I32Eq
+        | Operator::I32Ne
+        | Operator::I32LtS
+        | Operator::I32LtU
+        | Operator::I32GtS
+        | Operator::I32GtU
+        | Operator::I32LeS
+        | Operator::I32LeU
+        | Operator::I32GeS
+        | Operator::I32GeU
+        | Operator::I64Eq
+        | Operator::I64Ne
+        | Operator::I64LtS
+        | Operator::I64LtU
+        | Operator::I64GtS
+        | Operator::I64GtU
+        | Operator::I64LeS
+        | Operator::I64LeU
+        | Operator::I64GeS
+        | Operator::I64GeU
+        | Operator::F32Eq
+        | Operator::F32Ne
+        | Operator::F32Lt
+        | Operator::F32Gt
+        | Operator::F32Le
+        | Operator::F32Ge
+        | Operator::F64Eq
+        | Operator::F64Ne
+        | Operator::F64Lt
+        | Operator::F64Gt
+        | Operator::F64Le
+        | Operator::F64Ge
+        | Operator::I32Add
+        | Operator::I32Sub
+        | Operator::I32Mul
+        | Operator::I32DivS
+        | Operator::I32DivU
+        | Operator::I32RemS
+        | Operator::I32RemU
+        | Operator::I32And
+        | Operator::I32Or
+        | Operator::I32Xor
+        | Operator::I32Shl
+        | Operator::I32ShrS
+        | Operator::I32ShrU
+        | Operator::I32Rotl
+        | Operator::I32Rotr
+        | Operator::I64Add
+        | Operator::I64Sub
+        | Operator::I64Mul
+        | Operator::I64DivS
+        | Operator::I64DivU
+        | Operator::I64RemS
+        | Operator::I64RemU
+        | Operator::I64And
+        | Operator::I64Or
+        | Operator::I64Xor
+        | Operator::I64Shl
+        | Operator::I64ShrS
+        | Operator::I64ShrU
+        | Operator::I64Rotl
+        | Operator::I64Rotr
+        | Operator::F32Add
+        | Operator::F32Sub
+        | Operator::F32Mul
+        | Operator::F32Div
+        | Operator::F32Min
+        | Operator::F32Max
+        | Operator::F32Copysign
+        | Operator::F64Add
+        | Operator::F64Sub
+        | Operator::F64Mul
+        | Operator::F64Div
+        | Operator::F64Min
+        | Operator::F64Max
+        | Operator::F64Copysign => true,
+        _ => false,
+    }
+}
+
+// The memory argument of a load or store with the exponent of its natural alignment.
+fn memarg(operator: &Operator) -> Option<(&MemArg, u32)> {
+    match operator {
+        Operator::I32Load8s { memarg }
+        | Operator::I32Load8u { memarg }
+        | Operator::I64Load8s { memarg }
+        | Operator::I64Load8u { memarg }
+        | Operator::I32Store8 { memarg }
+        | Operator::I64Store8 { memarg } => Some((memarg, 0)),
+        Operator::I32Load16s { memarg }
+        | Operator::I32Load16u { memarg }
+        | Operator::I64Load16s { memarg }
+        | Operator::I64Load16u { memarg }
+        | Operator::I32Store16 { memarg }
+        | Operator::I64Store16 { memarg } => Some((memarg, 1)),
+        Operator::I32Load { memarg }
+        | Operator::F32Load { memarg }
+        | Operator::I64Load32s { memarg }
+        | Operator::I64Load32u { memarg }
+        | Operator::I32Store { memarg }
+        | Operator::F32Store { memarg }
+        | Operator::I64Store32 { memarg } => Some((memarg, 2)),
+        Operator::I64Load { memarg }
+        | Operator::F64Load { memarg }
+        | Operator::I64Store { memarg }
+        | Operator::F64Store { memarg } => Some((memarg, 3)),
+        _ => None,
+    }
+}
+
+// The mnemonic of an instruction, without its immediates.
+pub(crate) fn operator_name(operator: &Operator) -> &'static str {
+    match operator {
+        Operator::Unreachable => "unreachable",
+        Operator::Nop => "nop",
+        Operator::Block { .. } => "block",
+        Operator::Loop { .. } => "loop",
+        Operator::If { .. } => "if",
+        Operator::Else => "else",
+        Operator::End => "end",
+        Operator::Br { .. } => "br",
+        Operator::BrIf { .. } => "br_if",
+        Operator::BrTable { .. } => "br_table",
+        Operator::Return => "return",
+        Operator::Call { .. } => "call",
+        Operator::CallIndirect { .. } => "call_indirect",
+
+        Operator::Drop => "drop",
+        Operator::Select | Operator::TypedSelect { .. } => "select",
+
+        Operator::LocalGet { .. } => "local.get",
+        Operator::LocalSet { .. } => "local.set",
+        Operator::LocalTee { .. } => "local.tee",
+        Operator::GlobalGet { .. } => "global.get",
+        Operator::GlobalSet { .. } => "global.set",
+
+        Operator::TableGet { .. } => "table.get",
+        Operator::TableSet { .. } => "table.set",
+
+        Operator::I32Load { .. } => "i32.load",
+        Operator::I64Load { .. } => "i64.load",
+        Operator::F32Load { .. } => "f32.load",
+        Operator::F64Load { .. } => "f64.load",
+        Operator::I32Load8s { .. } => "i32.load8_s",
+        Operator::I32Load8u { .. } => "i32.load8_u",
+        Operator::I32Load16s { .. } => "i32.load16_s",
+        Operator::I32Load16u { .. } => "i32.load16_u",
+        Operator::I64Load8s { .. } => "i64.load8_s",
+        Operator::I64Load8u { .. } => "i64.load8_u",
+        Operator::I64Load16s { .. } => "i64.load16_s",
+        Operator::I64Load16u { .. } => "i64.load16_u",
+        Operator::I64Load32s { .. } => "i64.load32_s",
+        Operator::I64Load32u { .. } => "i64.load32_u",
+        Operator::I32Store { .. } => "i32.store",
+        Operator::I64Store { .. } => "i64.store",
+        Operator::F32Store { .. } => "f32.store",
+        Operator::F64Store { .. } => "f64.store",
+        Operator::I32Store8 { .. } => "i32.store8",
+        Operator::I32Store16 { .. } => "i32.store16",
+        Operator::I64Store8 { .. } => "i64.store8",
+        Operator::I64Store16 { .. } => "i64.store16",
+        Operator::I64Store32 { .. } => "i64.store32",
+        Operator::MemorySize { .. } => "memory.size",
+        Operator::MemoryGrow { .. } => "memory.grow",
+
+        Operator::I32Const { .. } => "i32.const",
+        Operator::I64Const { .. } => "i64.const",
+        Operator::F32Const { .. } => "f32.const",
+        Operator::F64Const { .. } => "f64.const",
+
+        Operator::I32Eqz => "i32.eqz",
+        Operator::I32Eq => "i32.eq",
+        Operator::I32Ne => "i32.ne",
+        Operator::I32LtS => "i32.lt_s",
+        Operator::I32LtU => "i32.lt_u",
+        Operator::I32GtS => "i32.gt_s",
+        Operator::I32GtU => "i32.gt_u",
+        Operator::I32LeS => "i32.le_s",
+        Operator::I32LeU => "i32.le_u",
+        Operator::I32GeS => "i32.ge_s",
+        Operator::I32GeU => "i32.ge_u",
+
+        Operator::I64Eqz => "i64.eqz",
+        Operator::I64Eq => "i64.eq",
+        Operator::I64Ne => "i64.ne",
+        Operator::I64LtS => "i64.lt_s",
+        Operator::I64LtU => "i64.lt_u",
+        Operator::I64GtS => "i64.gt_s",
+        Operator::I64GtU => "i64.gt_u",
+        Operator::I64LeS => "i64.le_s",
+        Operator::I64LeU => "i64.le_u",
+        Operator::I64GeS => "i64.ge_s",
+        Operator::I64GeU => "i64.ge_u",
+
+        Operator::F32Eq => "f32.eq",
+        Operator::F32Ne => "f32.ne",
+        Operator::F32Lt => "f32.lt",
+        Operator::F32Gt => "f32.gt",
+        Operator::F32Le => "f32.le",
+        Operator::F32Ge => "f32.ge",
+
+        Operator::F64Eq => "f64.eq",
+        Operator::F64Ne => "f64.ne",
+        Operator::F64Lt => "f64.lt",
+        Operator::F64Gt => "f64.gt",
+        Operator::F64Le => "f64.le",
+        Operator::F64Ge => "f64.ge",
+
+        Operator::I32Clz => "i32.clz",
+        Operator::I32Ctz => "i32.ctz",
+        Operator::I32Popcnt => "i32.popcnt",
+        Operator::I32Add => "i32.add",
+        Operator::I32Sub => "i32.sub",
+        Operator::I32Mul => "i32.mul",
+        Operator::I32DivS => "i32.div_s",
+        Operator::I32DivU => "i32.div_u",
+        Operator::I32RemS => "i32.rem_s",
+        Operator::I32RemU => "i32.rem_u",
+        Operator::I32And => "i32.and",
+        Operator::I32Or => "i32.or",
+        Operator::I32Xor => "i32.xor",
+        Operator::I32Shl => "i32.shl",
+        Operator::I32ShrS => "i32.shr_s",
+        Operator::I32ShrU => "i32.shr_u",
+        Operator::I32Rotl => "i32.rotl",
+        Operator::I32Rotr => "i32.rotr",
+
+        Operator::I64Clz => "i64.clz",
+        Operator::I64Ctz => "i64.ctz",
+        Operator::I64Popcnt => "i64.popcnt",
+        Operator::I64Add => "i64.add",
+        Operator::I64Sub => "i64.sub",
+        Operator::I64Mul => "i64.mul",
+        Operator::I64DivS => "i64.div_s",
+        Operator::I64DivU => "i64.div_u",
+        Operator::I64RemS => "i64.rem_s",
+        Operator::I64RemU => "i64.rem_u",
+        Operator::I64And => "i64.and",
+        Operator::I64Or => "i64.or",
+        Operator::I64Xor => "i64.xor",
+        Operator::I64Shl => "i64.shl",
+        Operator::I64ShrS => "i64.shr_s",
+        Operator::I64ShrU => "i64.shr_u",
+        Operator::I64Rotl => "i64.rotl",
+        Operator::I64Rotr => "i64.rotr",
+
+        Operator::F32Abs => "f32.abs",
+        Operator::F32Neg => "f32.neg",
+        Operator::F32Ceil => "f32.ceil",
+        Operator::F32Floor => "f32.floor",
+        Operator::F32Trunc => "f32.trunc",
+        Operator::F32Nearest => "f32.nearest",
+        Operator::F32Sqrt => "f32.sqrt",
+        Operator::F32Add => "f32.add",
+        Operator::F32Sub => "f32.sub",
+        Operator::F32Mul => "f32.mul",
+        Operator::F32Div => "f32.div",
+        Operator::F32Min => "f32.min",
+        Operator::F32Max => "f32.max",
+        Operator::F32Copysign => "f32.copysign",
+
+        Operator::F64Abs => "f64.abs",
+        Operator::F64Neg => "f64.neg",
+        Operator::F64Ceil => "f64.ceil",
+        Operator::F64Floor => "f64.floor",
+        Operator::F64Trunc => "f64.trunc",
+        Operator::F64Nearest => "f64.nearest",
+        Operator::F64Sqrt => "f64.sqrt",
+        Operator::F64Add => "f64.add",
+        Operator::F64Sub => "f64.sub",
+        Operator::F64Mul => "f64.mul",
+        Operator::F64Div => "f64.div",
+        Operator::F64Min => "f64.min",
+        Operator::F64Max => "f64.max",
+        Operator::F64Copysign => "f64.copysign",
+
+        Operator::I32WrapI64 => "i32.wrap_i64",
+        Operator::I32TruncSF32 => "i32.trunc_f32_s",
+        Operator::I32TruncUF32 => "i32.trunc_f32_u",
+        Operator::I32TruncSF64 => "i32.trunc_f64_s",
+        Operator::I32TruncUF64 => "i32.trunc_f64_u",
+        Operator::I64ExtendSI32 => "i64.extend_i32_s",
+        Operator::I64ExtendUI32 => "i64.extend_i32_u",
+        Operator::I64TruncSF32 => "i64.trunc_f32_s",
+        Operator::I64TruncUF32 => "i64.trunc_f32_u",
+        Operator::I64TruncSF64 => "i64.trunc_f64_s",
+        Operator::I64TruncUF64 => "i64.trunc_f64_u",
+        Operator::F32ConvertSI32 => "f32.convert_i32_s",
+        Operator::F32ConvertUI32 => "f32.convert_i32_u",
+        Operator::F32ConvertSI64 => "f32.convert_i64_s",
+        Operator::F32ConvertUI64 => "f32.convert_i64_u",
+        Operator::F32DemoteF64 => "f32.demote_f64",
+        Operator::F64ConvertSI32 => "f64.convert_i32_s",
+        Operator::F64ConvertUI32 => "f64.convert_i32_u",
+        Operator::F64ConvertSI64 => "f64.convert_i64_s",
+        Operator::F64ConvertUI64 => "f64.convert_i64_u",
+        Operator::F64PromoteF32 => "f64.promote_f32",
+        Operator::I32ReinterpretF32 => "i32.reinterpret_f32",
+        Operator::I64ReinterpretF64 => "i64.reinterpret_f64",
+        Operator::F32ReinterpretI32 => "f32.reinterpret_i32",
+        Operator::F64ReinterpretI64 => "f64.reinterpret_i64",
+        Operator::I32Extend8S => "i32.extend8_s",
+        Operator::I32Extend16S => "i32.extend16_s",
+        Operator::I64Extend8S => "i64.extend8_s",
+        Operator::I64Extend16S => "i64.extend16_s",
+        Operator::I64Extend32S => "i64.extend32_s",
+
+        Operator::RefNull { .. } => "ref.null",
+        Operator::RefIsNull => "ref.is_null",
+        Operator::RefFunc { .. } => "ref.func",
+        Operator::I32TruncSatSF32 => "i32.trunc_sat_f32_s",
+        Operator::I32TruncSatUF32 => "i32.trunc_sat_f32_u",
+        Operator::I32TruncSatSF64 => "i32.trunc_sat_f64_s",
+        Operator::I32TruncSatUF64 => "i32.trunc_sat_f64_u",
+        Operator::I64TruncSatSF32 => "i64.trunc_sat_f32_s",
+        Operator::I64TruncSatUF32 => "i64.trunc_sat_f32_u",
+        Operator::I64TruncSatSF64 => "i64.trunc_sat_f64_s",
+        Operator::I64TruncSatUF64 => "i64.trunc_sat_f64_u",
+        Operator::MemoryInit { .. } => "memory.init",
+        Operator::DataDrop { .. } => "data.drop",
+        Operator::MemoryCopy { .. } => "memory.copy",
+        Operator::MemoryFill { .. } => "memory.fill",
+        Operator::TableInit { .. } => "table.init",
+        Operator::ElemDrop { .. } => "elem.drop",
+        Operator::TableCopy { .. } => "table.copy",
+        Operator::TableGrow { .. } => "table.grow",
+        Operator::TableSize { .. } => "table.size",
+        Operator::TableFill { .. } => "table.fill",
+    }
+}
+
+// Formats the bits of an f32 as a hexadecimal float, `inf` or `nan`, which
+// keeps the exact value and the NaN payload.
+pub fn format_f32(bits: u32) -> String {
+    format_float(u64::from(bits), 8, 23)
+}
+
+pub fn format_f64(bits: u64) -> String {
+    format_float(bits, 11, 52)
+}
+
+fn format_float(bits: u64, exponent_bits: u32, mantissa_bits: u32) -> String {
+    let sign = if (bits >> (exponent_bits + mantissa_bits)) & 1 == 1 {
+        "-"
+    } else {
+        ""
+    };
+    let max_exponent = (1 << exponent_bits) - 1;
+    let exponent = (bits >> mantissa_bits) & max_exponent;
+    let mantissa = bits & ((1 << mantissa_bits) - 1);
+
+    if exponent == max_exponent {
+        return if mantissa == 0 {
+            format!("{}inf", sign)
+        } else if mantissa == 1 << (mantissa_bits - 1) {
+            format!("{}nan", sign)
+        } else {
+            format!("{}nan:{:#x}", sign, mantissa)
+        };
+    }
+    if exponent == 0 && mantissa == 0 {
+        return format!("{}0x0p+0", sign);
+    }
+
+    // the mantissa aligned to whole hex digits, without trailing zeros
+    let digits = (mantissa_bits + 3) / 4;
+    let mut fraction = format!(
+        "{:0width$x}",
+        mantissa << (digits * 4 - mantissa_bits),
+        width = digits as usize
+    );
+    while fraction.ends_with('0') {
+        fraction.pop();
+    }
+    let point = if fraction.is_empty() { "" } else { "." };
+
+    let bias = (1 << (exponent_bits - 1)) - 1;
+    // subnormals have no implicit leading one and the exponent of the smallest normal
+    let (lead, exponent) = if exponent == 0 {
+        (0, 1 - bias)
+    } else {
+        (1, exponent as i64 - bias)
+    };
+    format!("{}0x{}{}{}p{:+}", sign, lead, point, fraction, exponent)
+}
+
+fn push_signature(
+    text: &mut String,
+    params: &[Type],
+    returns: &[Type],
+    names: Option<&HashMap<u32, String>>,
+) {
+    push_locals(text, "param", 0, params, names);
+    if !returns.is_empty() {
+        text.push_str(" (result");
+        for t in returns {
+            text.push_str(&format!(" {}", t));
+        }
+        text.push(')');
+    }
+}
+
+// Declares parameters or locals, one per declaration when any of them is named.
+fn push_locals(
+    text: &mut String,
+    keyword: &str,
+    first_index: usize,
+    types: &[Type],
+    names: Option<&HashMap<u32, String>>,
+) {
+    if types.is_empty() {
+        return;
+    }
+
+    let names = names.filter(|names| {
+        (first_index..first_index + types.len()).any(|index| names.contains_key(&(index as u32)))
+    });
+    match names {
+        Some(names) => {
+            for (index, t) in types.iter().enumerate() {
+                match names.get(&((first_index + index) as u32)) {
+                    Some(id) => text.push_str(&format!(" ({} {} {})", keyword, id, t)),
+                    None => text.push_str(&format!(" ({} {})", keyword, t)),
+                }
+            }
+        }
+        None => {
+            text.push_str(&format!(" ({}", keyword));
+            for t in types {
+                text.push_str(&format!(" {}", t));
+            }
+            text.push(')');
+        }
+    }
+}
+
+// Writes a string literal, escaping quotes, backslashes and non-printable bytes.
+fn push_string(text: &mut String, bytes: &[u8]) {
+    text.push('"');
+    for byte in bytes {
+        match byte {
+            b'"' => text.push_str("\\\""),
+            b'\\' => text.push_str("\\\\"),
+            0x20..=0x7E => text.push(char::from(*byte)),
+            _ => text.push_str(&format!("\\{:02x}", byte)),
+        }
+    }
+    text.push('"');
+}
+
+fn limit_text(limit: &ResizableLimit) -> String {
+    match limit.max {
+        Some(max) => format!("{} {}", limit.initial, max),
+        None => limit.initial.to_string(),
+    }
+}
+
+// The heap type of `ref.null`.
+fn heap_type_text(t: Type) -> &'static str {
+    match t {
+        Type::AnyRef => "extern",
+        _ => "func",
+    }
+}
+
+fn global_type_text(global_arg: &GlobalArg) -> String {
+    match global_arg.mutability {
+        Mutability::Const => global_arg.content_type.to_string(),
+        Mutability::Var => format!("(mut {})", global_arg.content_type),
+    }
+}
+
+fn block_type_text(t: Type) -> Option<String> {
+    match t {
+        Type::EmptyBlockType => None,
+        t => Some(format!("(result {})", t)),
+    }
+}
+
+// Turns debug names into `$identifiers`. Characters not allowed in identifiers
+// are replaced with `_`, and names clashing with an earlier one are dropped.
+fn identifiers(names: &HashMap<u32, String>) -> HashMap<u32, String> {
+    let mut indices: Vec<u32> = names.keys().cloned().collect();
+    indices.sort();
+
+    let mut used = HashSet::new();
+    let mut ids = HashMap::new();
+    for index in indices {
+        if let Some(id) = identifier(&names[&index]) {
+            if used.insert(id.clone()) {
+                ids.insert(index, id);
+            }
+        }
+    }
+    ids
+}
+
+fn identifier(name: &str) -> Option<String> {
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut id = String::from("$");
+    id.extend(name.chars().map(|c| if is_id_char(c) { c } else { '_' }));
+    Some(id)
+}
+
+pub(crate) fn is_id_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-./:<=>?@\\^_`|~".contains(c)
+}
@@ -0,0 +1,450 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::primitives::{Mutability, ResizableLimit, Type};
+use crate::runtime::{Extern, FuncType, Global, Instance, Linker, Memory, Table, Trap, Value};
+use crate::text::lexer::TokenKind;
+use crate::text::number::{parse_f32, parse_f64, parse_i32, parse_i64};
+use crate::text::parser::TextParser;
+use crate::{Module, WasmError, WasmResult};
+
+// The outcome of a directive of a `.wast` script.
+#[derive(Debug)]
+pub struct Outcome {
+    // line of the directive, starting at 1
+    pub line: usize,
+    // e.g. `assert_return`
+    pub directive: String,
+    // why the directive failed
+    pub result: Result<(), String>,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.result {
+            Ok(()) => write!(f, "line {}: {}: passed", self.line, self.directive),
+            Err(reason) => write!(
+                f,
+                "line {}: {}: failed: {}",
+                self.line, self.directive, reason
+            ),
+        }
+    }
+}
+
+// A module of a script, which may fail to parse.
+struct ScriptModule {
+    id: Option<String>,
+    module: WasmResult<Module<'static>>,
+}
+
+enum Action {
+    Invoke {
+        id: Option<String>,
+        name: String,
+        args: Vec<Value>,
+    },
+    Get {
+        id: Option<String>,
+        name: String,
+    },
+}
+
+enum Expected {
+    Value(Value),
+    // a NaN with only the most significant bit of the payload set
+    CanonicalNan(Type),
+    // a NaN with the most significant bit of the payload set
+    ArithmeticNan(Type),
+    // any non-null function reference
+    FuncRef,
+}
+
+// Runs the directives of `.wast` spec test scripts. Modules can import from the
+// `spectest` module, and from the instances registered by earlier directives.
+pub struct WastRunner {
+    linker: Linker,
+    // the most recently instantiated module
+    current: Option<Instance>,
+    instances: HashMap<String, Instance>,
+}
+
+impl Default for WastRunner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WastRunner {
+    pub fn new() -> Self {
+        let mut linker = Linker::new();
+        define_spectest(&mut linker);
+        Self {
+            linker,
+            current: None,
+            instances: HashMap::new(),
+        }
+    }
+
+    // Runs the directives of `script` in order. An error is returned only if
+    // the script itself cannot be parsed, failed directives are outcomes.
+    pub fn run(&mut self, script: &str) -> WasmResult<Vec<Outcome>> {
+        let mut parser = TextParser::new(script)?;
+        let mut outcomes = vec![];
+        while parser.peek().is_some() {
+            let offset = parser.offset();
+            let directive = parser.peek_field().map(str::to_string);
+            let directive = directive.ok_or_else(|| parser.unexpected())?;
+            let result = self.directive(&mut parser, &directive)?;
+            outcomes.push(Outcome {
+                line: script[..offset].matches('\n').count() + 1,
+                directive,
+                result,
+            });
+        }
+        Ok(outcomes)
+    }
+
+    fn directive(
+        &mut self,
+        parser: &mut TextParser,
+        directive: &str,
+    ) -> WasmResult<Result<(), String>> {
+        if directive == "module" {
+            let ScriptModule { id, module } = script_module(parser)?;
+            return Ok(self.instantiate(module).map(|instance| {
+                if let Some(id) = id {
+                    self.instances.insert(id, instance.clone());
+                }
+                self.current = Some(instance);
+            }));
+        }
+
+        if directive == "invoke" || directive == "get" {
+            let action = action(parser)?;
+            return Ok(self
+                .perform(&action)
+                .map(|_| ())
+                .map_err(|trap| trap.to_string()));
+        }
+
+        parser.lparen()?;
+        parser.keyword()?;
+        let result = match directive {
+            "register" => {
+                let name = parser.name()?;
+                let id = parser.id();
+                self.instance(&id).map(|instance| {
+                    self.linker.instance(&name, &instance);
+                })
+            }
+            "assert_return" => {
+                let action = action(parser)?;
+                let mut expected = vec![];
+                while parser.peek() == Some(&TokenKind::LParen) {
+                    expected.push(expected_value(parser)?);
+                }
+                match self.perform(&action) {
+                    Ok(values) => check_values(&values, &expected),
+                    Err(trap) => Err(trap.to_string()),
+                }
+            }
+            "assert_trap" if parser.peek_field() == Some("module") => {
+                let module = script_module(parser)?.module;
+                let message = parser.name()?;
+                match self.instantiate(module) {
+                    Ok(_) => Err(format!("expected trap \"{}\"", message)),
+                    Err(reason) => check_message(&reason, &message),
+                }
+            }
+            "assert_trap" | "assert_exhaustion" => {
+                let action = action(parser)?;
+                let message = parser.name()?;
+                match self.perform(&action) {
+                    Ok(_) => Err(format!("expected trap \"{}\"", message)),
+                    Err(trap) => check_message(&trap.to_string(), &message),
+                }
+            }
+            "assert_malformed" => {
+                let module = script_module(parser)?.module;
+                parser.name()?;
+                match module {
+                    Ok(_) => Err("module is not malformed".to_string()),
+                    Err(_) => Ok(()),
+                }
+            }
+            "assert_invalid" => {
+                let module = script_module(parser)?.module;
+                parser.name()?;
+                match module {
+                    Ok(module) => match module.validate() {
+                        Ok(()) => Err("module is valid".to_string()),
+                        Err(_) => Ok(()),
+                    },
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+            "assert_unlinkable" => {
+                let module = script_module(parser)?.module;
+                parser.name()?;
+                match module.and_then(|module| module.validate().map(|_| module)) {
+                    Ok(module) => match self.linker.instantiate(module) {
+                        Ok(_) => Err("module is linkable".to_string()),
+                        Err(WasmError::Trap(trap)) => Err(trap.to_string()),
+                        Err(_) => Ok(()),
+                    },
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+            _ => return Err(WasmError::UnexpectedToken(directive.to_string()).at(parser.offset())),
+        };
+        parser.rparen()?;
+        Ok(result)
+    }
+
+    fn instantiate(&self, module: WasmResult<Module>) -> Result<Instance, String> {
+        module
+            .and_then(|module| self.linker.instantiate(module))
+            .map_err(|err| err.to_string())
+    }
+
+    // The instance of `id`, or the current one.
+    fn instance(&self, id: &Option<String>) -> Result<Instance, String> {
+        let instance = match id {
+            Some(id) => self.instances.get(id),
+            None => self.current.as_ref(),
+        };
+        instance
+            .cloned()
+            .ok_or_else(|| format!("unknown module {}", id.as_ref().map_or("", String::as_str)))
+    }
+
+    fn perform(&self, action: &Action) -> Result<Vec<Value>, Trap> {
+        match action {
+            Action::Invoke { id, name, args } => {
+                let instance = self.instance(id).map_err(Trap::Host)?;
+                instance.invoke(name, args)
+            }
+            Action::Get { id, name } => {
+                let instance = self.instance(id).map_err(Trap::Host)?;
+                match instance.export(name) {
+                    Some(Extern::Global(global)) => Ok(vec![global.borrow().get()]),
+                    _ => Err(Trap::UnknownExport(name.to_string())),
+                }
+            }
+        }
+    }
+}
+
+// Parses `(module $id? field*)`, `(module $id? binary string*)` or
+// `(module $id? quote string*)`. Modules that fail to parse are skipped.
+fn script_module(parser: &mut TextParser) -> WasmResult<ScriptModule> {
+    let start = parser.mark();
+    parser.lparen()?;
+    parser.expect_keyword("module")?;
+    let id = parser.id();
+
+    if parser.eat_keyword("binary") {
+        let bytes = parser.strings()?;
+        parser.rparen()?;
+        return Ok(ScriptModule {
+            id,
+            module: Module::parse_strict(&bytes).map(Module::into_owned),
+        });
+    }
+    if parser.eat_keyword("quote") {
+        let bytes = parser.strings()?;
+        parser.rparen()?;
+        let module = String::from_utf8(bytes)
+            .map_err(|_| WasmError::MalformedUtf8)
+            .and_then(|text| super::parse(&text))
+            .map(Module::from_sections);
+        return Ok(ScriptModule { id, module });
+    }
+
+    parser.reset(start);
+    let module = parser.module().map(Module::from_sections);
+    if module.is_err() {
+        parser.reset(start);
+        parser.skip_sexpr()?;
+    }
+    Ok(ScriptModule { id, module })
+}
+
+// Parses `(invoke $id? name const*)` or `(get $id? name)`.
+fn action(parser: &mut TextParser) -> WasmResult<Action> {
+    parser.lparen()?;
+    let keyword = parser.keyword()?;
+    let id = parser.id();
+    let name = parser.name()?;
+    let action = match keyword.as_str() {
+        "invoke" => {
+            let mut args = vec![];
+            while parser.peek() == Some(&TokenKind::LParen) {
+                match expected_value(parser)? {
+                    Expected::Value(value) => args.push(value),
+                    _ => return Err(parser.unexpected()),
+                }
+            }
+            Action::Invoke { id, name, args }
+        }
+        "get" => Action::Get { id, name },
+        _ => return Err(WasmError::UnexpectedToken(keyword).at(parser.offset())),
+    };
+    parser.rparen()?;
+    Ok(action)
+}
+
+// Parses a constant like `(i32.const 1)` or `(ref.extern 1)`, a NaN pattern like
+// `(f32.const nan:canonical)`, or `(ref.func)` for any function reference.
+fn expected_value(parser: &mut TextParser) -> WasmResult<Expected> {
+    parser.lparen()?;
+    let keyword = parser.keyword()?;
+    let t = match keyword.as_str() {
+        "i32.const" => Type::I32,
+        "i64.const" => Type::I64,
+        "f32.const" => Type::F32,
+        "f64.const" => Type::F64,
+        "ref.null" => {
+            let value = match parser.keyword()?.as_str() {
+                "func" => Value::FuncRef(None),
+                "extern" => Value::ExternRef(None),
+                heap_type => {
+                    let err = WasmError::UnexpectedToken(heap_type.to_string());
+                    return Err(err.at(parser.offset()));
+                }
+            };
+            parser.rparen()?;
+            return Ok(Expected::Value(value));
+        }
+        "ref.extern" => {
+            let value = Value::ExternRef(Some(parser.number(parse_i32)? as u32));
+            parser.rparen()?;
+            return Ok(Expected::Value(value));
+        }
+        "ref.func" => {
+            parser.rparen()?;
+            return Ok(Expected::FuncRef);
+        }
+        _ => return Err(WasmError::UnexpectedToken(keyword).at(parser.offset())),
+    };
+
+    let expected = if parser.eat_keyword("nan:canonical") {
+        Expected::CanonicalNan(t)
+    } else if parser.eat_keyword("nan:arithmetic") {
+        Expected::ArithmeticNan(t)
+    } else {
+        Expected::Value(match t {
+            Type::I32 => Value::I32(parser.number(parse_i32)?),
+            Type::I64 => Value::I64(parser.number(parse_i64)?),
+            Type::F32 => Value::F32(f32::from_bits(parser.number(parse_f32)?)),
+            _ => Value::F64(f64::from_bits(parser.number(parse_f64)?)),
+        })
+    };
+    parser.rparen()?;
+    Ok(expected)
+}
+
+fn check_values(values: &[Value], expected: &[Expected]) -> Result<(), String> {
+    if values.len() != expected.len() {
+        return Err(format!(
+            "expected {} values, got {}",
+            expected.len(),
+            values.len()
+        ));
+    }
+    for (value, expected) in values.iter().zip(expected) {
+        let matches = match (expected, value) {
+            // floats are compared bit for bit, so that NaN payloads and zero signs count
+            (Expected::Value(Value::F32(a)), Value::F32(b)) => a.to_bits() == b.to_bits(),
+            (Expected::Value(Value::F64(a)), Value::F64(b)) => a.to_bits() == b.to_bits(),
+            (Expected::Value(expected), value) => expected == value,
+            (Expected::FuncRef, Value::FuncRef(func)) => func.is_some(),
+            (Expected::CanonicalNan(Type::F32), Value::F32(value)) => {
+                value.to_bits() & 0x7fff_ffff == 0x7fc0_0000
+            }
+            (Expected::CanonicalNan(Type::F64), Value::F64(value)) => {
+                value.to_bits() & 0x7fff_ffff_ffff_ffff == 0x7ff8_0000_0000_0000
+            }
+            (Expected::ArithmeticNan(Type::F32), Value::F32(value)) => {
+                value.is_nan() && value.to_bits() & 0x0040_0000 != 0
+            }
+            (Expected::ArithmeticNan(Type::F64), Value::F64(value)) => {
+                value.is_nan() && value.to_bits() & 0x0008_0000_0000_0000 != 0
+            }
+            _ => false,
+        };
+        if !matches {
+            return Err(format!("unexpected result {}", value));
+        }
+    }
+    Ok(())
+}
+
+fn check_message(reason: &str, message: &str) -> Result<(), String> {
+    if reason.contains(message) {
+        Ok(())
+    } else {
+        Err(format!("expected \"{}\", got \"{}\"", message, reason))
+    }
+}
+
+// The `spectest` module the spec tests import from.
+fn define_spectest(linker: &mut Linker) {
+    let prints = vec![
+        ("print", vec![]),
+        ("print_i32", vec![Type::I32]),
+        ("print_i64", vec![Type::I64]),
+        ("print_f32", vec![Type::F32]),
+        ("print_f64", vec![Type::F64]),
+        ("print_i32_f32", vec![Type::I32, Type::F32]),
+        ("print_f64_f64", vec![Type::F64, Type::F64]),
+    ];
+    for (name, params) in prints {
+        linker.func("spectest", name, FuncType::new(params, vec![]), |_| {
+            Ok(vec![])
+        });
+    }
+
+    let globals = vec![
+        ("global_i32", Value::I32(666)),
+        ("global_i64", Value::I64(666)),
+        ("global_f32", Value::F32(666.6)),
+        ("global_f64", Value::F64(666.6)),
+    ];
+    for (name, value) in globals {
+        let global = Global::new(value, Mutability::Const);
+        linker.define(
+            "spectest",
+            name,
+            Extern::Global(Rc::new(RefCell::new(global))),
+        );
+    }
+
+    let table = Table::new(&ResizableLimit {
+        initial: 10,
+        max: Some(20),
+    });
+    linker.define(
+        "spectest",
+        "table",
+        Extern::Table(Rc::new(RefCell::new(table))),
+    );
+    let memory = Memory::new(&ResizableLimit {
+        initial: 1,
+        max: Some(2),
+    });
+    linker.define(
+        "spectest",
+        "memory",
+        Extern::Memory(Rc::new(RefCell::new(memory))),
+    );
+}
@@ -0,0 +1,650 @@
+use crate::primitives::{MemArg, Mutability, Operator, SectionCodeEntity, SectionTypeEntity, Type};
+use crate::validate::{validate_ref_type, validate_value_type, Context};
+use crate::{WasmError, WasmResult};
+
+#[derive(PartialEq)]
+enum ControlKind {
+    Function,
+    Block,
+    Loop,
+    If,
+    Else,
+}
+
+struct Control {
+    kind: ControlKind,
+    results: Vec<Type>,
+    // height of the operand stack when the block was entered
+    height: usize,
+    unreachable: bool,
+}
+
+// Type-checks a function body with the operand and control stacks of the
+// validation algorithm; `None` on the operand stack is an unknown type.
+struct FuncValidator<'a> {
+    context: &'a Context<'a>,
+    locals: Vec<Type>,
+    operands: Vec<Option<Type>>,
+    controls: Vec<Control>,
+}
+
+pub(crate) fn validate_function(
+    context: &Context,
+    func_type: &SectionTypeEntity,
+    entity: &SectionCodeEntity,
+) -> WasmResult<()> {
+    let mut locals = func_type.params.clone();
+    for t in &entity.locals {
+        validate_value_type(*t).map_err(|err| err.at(entity.range.start))?;
+        locals.push(*t);
+    }
+
+    let mut validator = FuncValidator {
+        context,
+        locals,
+        operands: vec![],
+        controls: vec![Control {
+            kind: ControlKind::Function,
+            results: func_type.returns.clone(),
+            height: 0,
+            unreachable: false,
+        }],
+    };
+
+    for (index, operator) in entity.code.iter().enumerate() {
+        // bodies built by hand may have no operator ranges
+        let offset = entity
+            .code_ranges
+            .get(index)
+            .map_or(entity.range.start, |range| range.start);
+        let result = if validator.controls.is_empty() {
+            Err(WasmError::OperatorsAfterEnd)
+        } else {
+            validator.operator(operator)
+        };
+        result.map_err(|err| err.at(offset).at_operator(index as u32))?;
+    }
+
+    if !validator.controls.is_empty() {
+        return Err(WasmError::MissingEnd.at(entity.range.end));
+    }
+
+    Ok(())
+}
+
+impl<'a> FuncValidator<'a> {
+    fn operator(&mut self, operator: &Operator) -> WasmResult<()> {
+        match operator {
+            Operator::Unreachable => self.unreachable(),
+            Operator::Nop => {}
+            Operator::Block { t } => self.push_control(ControlKind::Block, *t)?,
+            Operator::Loop { t } => self.push_control(ControlKind::Loop, *t)?,
+            Operator::If { t } => {
+                self.pop_expect(Type::I32)?;
+                self.push_control(ControlKind::If, *t)?;
+            }
+            Operator::Else => {
+                if self.top_control().kind != ControlKind::If {
+                    return Err(WasmError::UnmatchedElse);
+                }
+                let results = self.pop_control()?;
+                self.controls.push(Control {
+                    kind: ControlKind::Else,
+                    results,
+                    height: self.operands.len(),
+                    unreachable: false,
+                });
+            }
+            Operator::End => {
+                let kind_is_if = self.top_control().kind == ControlKind::If;
+                let results = self.pop_control()?;
+                // an `if` without `else` must leave the stack as it found it
+                if kind_is_if && !results.is_empty() {
+                    return Err(WasmError::TypeMismatch {
+                        expected: Some(results[0]),
+                        actual: None,
+                    });
+                }
+                if !self.controls.is_empty() {
+                    self.push_values(&results);
+                }
+            }
+            Operator::Br { relative_depth } => {
+                let types = self.label_types(*relative_depth)?;
+                self.pop_values(&types)?;
+                self.unreachable();
+            }
+            Operator::BrIf { relative_depth } => {
+                self.pop_expect(Type::I32)?;
+                let types = self.label_types(*relative_depth)?;
+                self.pop_values(&types)?;
+                self.push_values(&types);
+            }
+            Operator::BrTable {
+                target_table,
+                default_target,
+            } => {
+                self.pop_expect(Type::I32)?;
+                let types = self.label_types(*default_target)?;
+                for depth in target_table {
+                    let target_types = self.label_types(*depth)?;
+                    if target_types.len() != types.len() {
+                        return Err(WasmError::StackHeightMismatch {
+                            expected: types.len(),
+                            actual: target_types.len(),
+                        });
+                    }
+                    if let Some((expected, actual)) = types
+                        .iter()
+                        .zip(target_types.iter())
+                        .find(|(expected, actual)| expected != actual)
+                    {
+                        return Err(WasmError::TypeMismatch {
+                            expected: Some(*expected),
+                            actual: Some(*actual),
+                        });
+                    }
+                }
+                self.pop_values(&types)?;
+                self.unreachable();
+            }
+            Operator::Return => {
+                let types = self.controls[0].results.clone();
+                self.pop_values(&types)?;
+                self.unreachable();
+            }
+            Operator::Call { function_index } => {
+                let func_type = self.context.function(*function_index)?;
+                self.call(func_type)?;
+            }
+            Operator::CallIndirect {
+                type_index,
+                table_index,
+            } => {
+                self.table_of(*table_index, Type::AnyFunc)?;
+                let func_type = self.context.func_type(*type_index)?;
+                self.pop_expect(Type::I32)?;
+                self.call(func_type)?;
+            }
+
+            Operator::Drop => {
+                self.pop()?;
+            }
+            Operator::Select => {
+                self.pop_expect(Type::I32)?;
+                let t1 = self.pop()?;
+                let t2 = match t1 {
+                    Some(t) => self.pop_expect(t)?,
+                    None => self.pop()?,
+                };
+                match t1.or(t2) {
+                    Some(t) if t.is_ref() => return Err(WasmError::UntypedSelect(t)),
+                    t => self.operands.push(t),
+                }
+            }
+            Operator::TypedSelect { types } => {
+                let t = match types.as_slice() {
+                    [t] => *t,
+                    _ => return Err(WasmError::InvalidResultArity),
+                };
+                validate_value_type(t)?;
+                self.pop_values(&[t, t, Type::I32])?;
+                self.push(t);
+            }
+
+            Operator::LocalGet { local_index } => {
+                let t = self.local(*local_index)?;
+                self.push(t);
+            }
+            Operator::LocalSet { local_index } => {
+                let t = self.local(*local_index)?;
+                self.pop_expect(t)?;
+            }
+            Operator::LocalTee { local_index } => {
+                let t = self.local(*local_index)?;
+                self.pop_expect(t)?;
+                self.push(t);
+            }
+            Operator::GlobalGet { global_index } => {
+                let global = self.context.global(*global_index)?;
+                self.push(global.content_type);
+            }
+            Operator::GlobalSet { global_index } => {
+                let global = self.context.global(*global_index)?;
+                if global.mutability != Mutability::Var {
+                    return Err(WasmError::ImmutableGlobal(*global_index));
+                }
+                self.pop_expect(global.content_type)?;
+            }
+
+            Operator::TableGet { table_index } => {
+                let t = self.context.table(*table_index)?;
+                self.unary(Type::I32, t)?;
+            }
+            Operator::TableSet { table_index } => {
+                let t = self.context.table(*table_index)?;
+                self.pop_values(&[Type::I32, t])?;
+            }
+            Operator::TableSize { table_index } => {
+                self.context.table(*table_index)?;
+                self.push(Type::I32);
+            }
+            Operator::TableGrow { table_index } => {
+                let t = self.context.table(*table_index)?;
+                self.pop_values(&[t, Type::I32])?;
+                self.push(Type::I32);
+            }
+            Operator::TableFill { table_index } => {
+                let t = self.context.table(*table_index)?;
+                self.pop_values(&[Type::I32, t, Type::I32])?;
+            }
+
+            Operator::I32Load { memarg } => self.load(memarg, 2, Type::I32)?,
+            Operator::I64Load { memarg } => self.load(memarg, 3, Type::I64)?,
+            Operator::F32Load { memarg } => self.load(memarg, 2, Type::F32)?,
+            Operator::F64Load { memarg } => self.load(memarg, 3, Type::F64)?,
+            Operator::I32Load8s { memarg } | Operator::I32Load8u { memarg } => {
+                self.load(memarg, 0, Type::I32)?
+            }
+            Operator::I32Load16s { memarg } | Operator::I32Load16u { memarg } => {
+                self.load(memarg, 1, Type::I32)?
+            }
+            Operator::I64Load8s { memarg } | Operator::I64Load8u { memarg } => {
+                self.load(memarg, 0, Type::I64)?
+            }
+            Operator::I64Load16s { memarg } | Operator::I64Load16u { memarg } => {
+                self.load(memarg, 1, Type::I64)?
+            }
+            Operator::I64Load32s { memarg } | Operator::I64Load32u { memarg } => {
+                self.load(memarg, 2, Type::I64)?
+            }
+            Operator::I32Store { memarg } => self.store(memarg, 2, Type::I32)?,
+            Operator::I64Store { memarg } => self.store(memarg, 3, Type::I64)?,
+            Operator::F32Store { memarg } => self.store(memarg, 2, Type::F32)?,
+            Operator::F64Store { memarg } => self.store(memarg, 3, Type::F64)?,
+            Operator::I32Store8 { memarg } => self.store(memarg, 0, Type::I32)?,
+            Operator::I32Store16 { memarg } => self.store(memarg, 1, Type::I32)?,
+            Operator::I64Store8 { memarg } => self.store(memarg, 0, Type::I64)?,
+            Operator::I64Store16 { memarg } => self.store(memarg, 1, Type::I64)?,
+            Operator::I64Store32 { memarg } => self.store(memarg, 2, Type::I64)?,
+            Operator::MemorySize { .. } => {
+                self.context.memory(0)?;
+                self.push(Type::I32);
+            }
+            Operator::MemoryGrow { .. } => {
+                self.context.memory(0)?;
+                self.unary(Type::I32, Type::I32)?;
+            }
+            Operator::MemoryInit { data_index, .. } => {
+                self.context.memory(0)?;
+                self.context.data(*data_index)?;
+                self.pop_values(&[Type::I32; 3])?;
+            }
+            Operator::DataDrop { data_index } => self.context.data(*data_index)?,
+            Operator::MemoryCopy { .. } | Operator::MemoryFill { .. } => {
+                self.context.memory(0)?;
+                self.pop_values(&[Type::I32; 3])?;
+            }
+            Operator::TableInit {
+                elem_index,
+                table_index,
+            } => {
+                let t = self.context.element(*elem_index)?;
+                self.table_of(*table_index, t)?;
+                self.pop_values(&[Type::I32; 3])?;
+            }
+            Operator::ElemDrop { elem_index } => {
+                self.context.element(*elem_index)?;
+            }
+            Operator::TableCopy {
+                dst_table,
+                src_table,
+            } => {
+                let t = self.context.table(*src_table)?;
+                self.table_of(*dst_table, t)?;
+                self.pop_values(&[Type::I32; 3])?;
+            }
+
+            Operator::RefNull { t } => {
+                validate_ref_type(*t)?;
+                self.push(*t);
+            }
+            Operator::RefIsNull => match self.pop()? {
+                Some(t) if !t.is_ref() => return Err(WasmError::ReferenceTypeExpected(t)),
+                _ => self.push(Type::I32),
+            },
+            Operator::RefFunc { function_index } => {
+                self.context.function(*function_index)?;
+                if !self.context.refs.contains(function_index) {
+                    return Err(WasmError::UndeclaredFunctionReference(*function_index));
+                }
+                self.push(Type::AnyFunc);
+            }
+
+            Operator::I32Const { .. } => self.push(Type::I32),
+            Operator::I64Const { .. } => self.push(Type::I64),
+            Operator::F32Const { .. } => self.push(Type::F32),
+            Operator::F64Const { .. } => self.push(Type::F64),
+
+            Operator::I32Eqz => self.unary(Type::I32, Type::I32)?,
+            Operator::I32Eq
+            | Operator::I32Ne
+            | Operator::I32LtS
+            | Operator::I32LtU
+            | Operator::I32GtS
+            | Operator::I32GtU
+            | Operator::I32LeS
+            | Operator::I32LeU
+            | Operator::I32GeS
+            | Operator::I32GeU => self.binary(Type::I32, Type::I32)?,
+
+            Operator::I64Eqz => self.unary(Type::I64, Type::I32)?,
+            Operator::I64Eq
+            | Operator::I64Ne
+            | Operator::I64LtS
+            | Operator::I64LtU
+            | Operator::I64GtS
+            | Operator::I64GtU
+            | Operator::I64LeS
+            | Operator::I64LeU
+            | Operator::I64GeS
+            | Operator::I64GeU => self.binary(Type::I64, Type::I32)?,
+
+            Operator::F32Eq
+            | Operator::F32Ne
+            | Operator::F32Lt
+            | Operator::F32Gt
+            | Operator::F32Le
+            | Operator::F32Ge => self.binary(Type::F32, Type::I32)?,
+
+            Operator::F64Eq
+            | Operator::F64Ne
+            | Operator::F64Lt
+            | Operator::F64Gt
+            | Operator::F64Le
+            | Operator::F64Ge => self.binary(Type::F64, Type::I32)?,
+
+            Operator::I32Clz | Operator::I32Ctz | Operator::I32Popcnt => {
+                self.unary(Type::I32, Type::I32)?
+            }
+            Operator::I32Add
+            | Operator::I32Sub
+            | Operator::I32Mul
+            | Operator::I32DivS
+            | Operator::I32DivU
+            | Operator::I32RemS
+            | Operator::I32RemU
+            | Operator::I32And
+            | Operator::I32Or
+            | Operator::I32Xor
+            | Operator::I32Shl
+            | Operator::I32ShrS
+            | Operator::I32ShrU
+            | Operator::I32Rotl
+            | Operator::I32Rotr => self.binary(Type::I32, Type::I32)?,
+
+            Operator::I64Clz | Operator::I64Ctz | Operator::I64Popcnt => {
+                self.unary(Type::I64, Type::I64)?
+            }
+            Operator::I64Add
+            | Operator::I64Sub
+            | Operator::I64Mul
+            | Operator::I64DivS
+            | Operator::I64DivU
+            | Operator::I64RemS
+            | Operator::I64RemU
+            | Operator::I64And
+            | Operator::I64Or
+            | Operator::I64Xor
+            | Operator::I64Shl
+            | Operator::I64ShrS
+            | Operator::I64ShrU
+            | Operator::I64Rotl
+            | Operator::I64Rotr => self.binary(Type::I64, Type::I64)?,
+
+            Operator::F32Abs
+            | Operator::F32Neg
+            | Operator::F32Ceil
+            | Operator::F32Floor
+            | Operator::F32Trunc
+            | Operator::F32Nearest
+            | Operator::F32Sqrt => self.unary(Type::F32, Type::F32)?,
+            Operator::F32Add
+            | Operator::F32Sub
+            | Operator::F32Mul
+            | Operator::F32Div
+            | Operator::F32Min
+            | Operator::F32Max
+            | Operator::F32Copysign => self.binary(Type::F32, Type::F32)?,
+
+            Operator::F64Abs
+            | Operator::F64Neg
+            | Operator::F64Ceil
+            | Operator::F64Floor
+            | Operator::F64Trunc
+            | Operator::F64Nearest
+            | Operator::F64Sqrt => self.unary(Type::F64, Type::F64)?,
+            Operator::F64Add
+            | Operator::F64Sub
+            | Operator::F64Mul
+            | Operator::F64Div
+            | Operator::F64Min
+            | Operator::F64Max
+            | Operator::F64Copysign => self.binary(Type::F64, Type::F64)?,
+
+            Operator::I32WrapI64 => self.unary(Type::I64, Type::I32)?,
+            Operator::I32TruncSF32
+            | Operator::I32TruncUF32
+            | Operator::I32TruncSatSF32
+            | Operator::I32TruncSatUF32 => self.unary(Type::F32, Type::I32)?,
+            Operator::I32TruncSF64
+            | Operator::I32TruncUF64
+            | Operator::I32TruncSatSF64
+            | Operator::I32TruncSatUF64 => self.unary(Type::F64, Type::I32)?,
+            Operator::I64ExtendSI32 | Operator::I64ExtendUI32 => {
+                self.unary(Type::I32, Type::I64)?
+            }
+            Operator::I64TruncSF32
+            | Operator::I64TruncUF32
+            | Operator::I64TruncSatSF32
+            | Operator::I64TruncSatUF32 => self.unary(Type::F32, Type::I64)?,
+            Operator::I64TruncSF64
+            | Operator::I64TruncUF64
+            | Operator::I64TruncSatSF64
+            | Operator::I64TruncSatUF64 => self.unary(Type::F64, Type::I64)?,
+            Operator::F32ConvertSI32 | Operator::F32ConvertUI32 => {
+                self.unary(Type::I32, Type::F32)?
+            }
+            Operator::F32ConvertSI64 | Operator::F32ConvertUI64 => {
+                self.unary(Type::I64, Type::F32)?
+            }
+            Operator::F32DemoteF64 => self.unary(Type::F64, Type::F32)?,
+            Operator::F64ConvertSI32 | Operator::F64ConvertUI32 => {
+                self.unary(Type::I32, Type::F64)?
+            }
+            Operator::F64ConvertSI64 | Operator::F64ConvertUI64 => {
+                self.unary(Type::I64, Type::F64)?
+            }
+            Operator::F64PromoteF32 => self.unary(Type::F32, Type::F64)?,
+            Operator::I32ReinterpretF32 => self.unary(Type::F32, Type::I32)?,
+            Operator::I64ReinterpretF64 => self.unary(Type::F64, Type::I64)?,
+            Operator::F32ReinterpretI32 => self.unary(Type::I32, Type::F32)?,
+            Operator::F64ReinterpretI64 => self.unary(Type::I64, Type::F64)?,
+            Operator::I32Extend8S | Operator::I32Extend16S => self.unary(Type::I32, Type::I32)?,
+            Operator::I64Extend8S | Operator::I64Extend16S | Operator::I64Extend32S => {
+                self.unary(Type::I64, Type::I64)?
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, t: Type) {
+        self.operands.push(Some(t));
+    }
+
+    fn push_values(&mut self, types: &[Type]) {
+        for t in types {
+            self.push(*t);
+        }
+    }
+
+    fn pop(&mut self) -> WasmResult<Option<Type>> {
+        let control = self.top_control();
+        if self.operands.len() == control.height {
+            if control.unreachable {
+                return Ok(None);
+            }
+            return Err(WasmError::TypeMismatch {
+                expected: None,
+                actual: None,
+            });
+        }
+
+        Ok(self.operands.pop().unwrap_or(None))
+    }
+
+    fn pop_expect(&mut self, expected: Type) -> WasmResult<Option<Type>> {
+        let control = self.top_control();
+        if self.operands.len() == control.height && !control.unreachable {
+            return Err(WasmError::TypeMismatch {
+                expected: Some(expected),
+                actual: None,
+            });
+        }
+
+        match self.pop()? {
+            Some(actual) if actual != expected => Err(WasmError::TypeMismatch {
+                expected: Some(expected),
+                actual: Some(actual),
+            }),
+            _ => Ok(Some(expected)),
+        }
+    }
+
+    fn pop_values(&mut self, types: &[Type]) -> WasmResult<()> {
+        for t in types.iter().rev() {
+            self.pop_expect(*t)?;
+        }
+        Ok(())
+    }
+
+    fn top_control(&self) -> &Control {
+        // the function frame is only popped by its final `end`
+        &self.controls[self.controls.len() - 1]
+    }
+
+    fn push_control(&mut self, kind: ControlKind, block_type: Type) -> WasmResult<()> {
+        let results = match block_type {
+            Type::EmptyBlockType => vec![],
+            t => {
+                validate_value_type(t)?;
+                vec![t]
+            }
+        };
+
+        self.controls.push(Control {
+            kind,
+            results,
+            height: self.operands.len(),
+            unreachable: false,
+        });
+        Ok(())
+    }
+
+    fn pop_control(&mut self) -> WasmResult<Vec<Type>> {
+        let results = self.top_control().results.clone();
+        self.pop_values(&results)?;
+
+        let control = self.top_control();
+        if self.operands.len() != control.height {
+            return Err(WasmError::StackHeightMismatch {
+                expected: results.len(),
+                actual: results.len() + self.operands.len() - control.height,
+            });
+        }
+
+        self.controls.pop();
+        Ok(results)
+    }
+
+    fn label_types(&self, relative_depth: u32) -> WasmResult<Vec<Type>> {
+        let depth = relative_depth as usize;
+        if depth >= self.controls.len() {
+            return Err(WasmError::UnknownLabel(relative_depth));
+        }
+
+        let control = &self.controls[self.controls.len() - 1 - depth];
+        if control.kind == ControlKind::Loop {
+            Ok(vec![])
+        } else {
+            Ok(control.results.clone())
+        }
+    }
+
+    fn unreachable(&mut self) {
+        let index = self.controls.len() - 1;
+        let control = &mut self.controls[index];
+        self.operands.truncate(control.height);
+        control.unreachable = true;
+    }
+
+    fn local(&self, local_index: u32) -> WasmResult<Type> {
+        self.locals
+            .get(local_index as usize)
+            .cloned()
+            .ok_or_else(|| WasmError::UnknownLocal(local_index))
+    }
+
+    // Checks that a table holds elements of type `t`.
+    fn table_of(&self, table_index: u32, t: Type) -> WasmResult<()> {
+        let table_type = self.context.table(table_index)?;
+        if table_type != t {
+            return Err(WasmError::TypeMismatch {
+                expected: Some(table_type),
+                actual: Some(t),
+            });
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, func_type: &SectionTypeEntity) -> WasmResult<()> {
+        self.pop_values(&func_type.params)?;
+        self.push_values(&func_type.returns);
+        Ok(())
+    }
+
+    fn unary(&mut self, input: Type, output: Type) -> WasmResult<()> {
+        self.pop_expect(input)?;
+        self.push(output);
+        Ok(())
+    }
+
+    fn binary(&mut self, input: Type, output: Type) -> WasmResult<()> {
+        self.pop_expect(input)?;
+        self.pop_expect(input)?;
+        self.push(output);
+        Ok(())
+    }
+
+    // `max_align` is the log2 of the natural alignment of the access.
+    fn check_memarg(&self, memarg: &MemArg, max_align: u32) -> WasmResult<()> {
+        self.context.memory(0)?;
+        if memarg.align > max_align {
+            return Err(WasmError::InvalidAlignment(memarg.align));
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, memarg: &MemArg, max_align: u32, t: Type) -> WasmResult<()> {
+        self.check_memarg(memarg, max_align)?;
+        self.unary(Type::I32, t)
+    }
+
+    fn store(&mut self, memarg: &MemArg, max_align: u32, t: Type) -> WasmResult<()> {
+        self.check_memarg(memarg, max_align)?;
+        self.pop_expect(t)?;
+        self.pop_expect(Type::I32)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,480 @@
+mod func;
+
+use std::collections::HashSet;
+
+use crate::parser::parser_section_code_entity;
+use crate::primitives::{
+    ElemItems, ExternalKind, GlobalArg, ImportKind, Mutability, Operator, ResizableLimit, Section,
+    SectionCodeEntity, SectionDataEntity, SectionElemEntity, SectionExportEntity,
+    SectionFuncEntity, SectionGlobalEntity, SectionImportEntity, SectionMemoryEntity,
+    SectionTableEntity, SectionTypeEntity, SegmentMode, Type,
+};
+use crate::validate::func::validate_function;
+use crate::{WasmError, WasmResult};
+
+const MAX_MEMORY_PAGES: u32 = 65536;
+
+const SECTION_CODE: u32 = 0x0A;
+
+// Validates a parsed module against the WebAssembly 1.0 validation rules.
+pub fn validate(sections: &[Section]) -> WasmResult<()> {
+    let mut parts = Parts::default();
+    for section in sections {
+        match section {
+            Section::Custom { .. } => {}
+            Section::Type { entities, .. } => parts.types.extend(entities),
+            Section::Import { entities, .. } => parts.imports.extend(entities),
+            Section::Function { entities, .. } => parts.functions.extend(entities),
+            Section::Table { entities, .. } => parts.tables.extend(entities),
+            Section::Memory { entities, .. } => parts.memories.extend(entities),
+            Section::Global { entities, .. } => parts.globals.extend(entities),
+            Section::Export { entities, .. } => parts.exports.extend(entities),
+            Section::Start {
+                signature_index, ..
+            } => parts.start = Some(*signature_index),
+            Section::Element { entities, .. } => parts.elements.extend(entities),
+            Section::Code { entities, .. } => parts.codes.extend(entities),
+            Section::Data { entities, .. } => parts.data.extend(entities),
+            Section::DataCount { count, .. } => parts.data_count = Some(*count),
+        }
+    }
+
+    validate_parts(&parts)
+}
+
+// The entities of a module grouped by section.
+#[derive(Default)]
+pub(crate) struct Parts<'a> {
+    pub types: Vec<&'a SectionTypeEntity>,
+    pub imports: Vec<&'a SectionImportEntity<'a>>,
+    pub functions: Vec<&'a SectionFuncEntity>,
+    pub tables: Vec<&'a SectionTableEntity>,
+    pub memories: Vec<&'a SectionMemoryEntity>,
+    pub globals: Vec<&'a SectionGlobalEntity>,
+    pub exports: Vec<&'a SectionExportEntity<'a>>,
+    pub start: Option<u32>,
+    pub elements: Vec<&'a SectionElemEntity>,
+    pub codes: Vec<&'a SectionCodeEntity<'a>>,
+    pub data: Vec<&'a SectionDataEntity<'a>>,
+    pub data_count: Option<u32>,
+}
+
+pub(crate) fn validate_parts(parts: &Parts) -> WasmResult<()> {
+    let mut context = Context::default();
+    for entity in &parts.types {
+        validate_type(entity)?;
+        context.types.push(entity);
+    }
+    for entity in &parts.imports {
+        validate_import(&mut context, entity)?;
+    }
+    for entity in &parts.functions {
+        validate_function_declaration(&mut context, entity)?;
+    }
+    context.refs = declared_refs(parts);
+    for entity in &parts.tables {
+        validate_table(&mut context, entity)?;
+    }
+    for entity in &parts.memories {
+        validate_memory(&mut context, entity)?;
+    }
+    for entity in &parts.globals {
+        validate_global(&mut context, entity)?;
+    }
+    validate_exports(&context, &parts.exports)?;
+    if let Some(index) = parts.start {
+        validate_start(&context, index)?;
+    }
+    for entity in &parts.elements {
+        validate_element(&context, entity)?;
+    }
+    context.elements = parts.elements.iter().map(|e| e.elem_type).collect();
+    if let Some(count) = parts.data_count {
+        if count as usize != parts.data.len() {
+            return Err(WasmError::DataCountMismatch {
+                count,
+                segments: parts.data.len(),
+            });
+        }
+    }
+    context.data_count = parts.data_count;
+    validate_codes(&context, &parts.codes)?;
+    for entity in &parts.data {
+        validate_data(&context, entity)?;
+    }
+
+    Ok(())
+}
+
+// The index spaces of a module, imported entities first.
+#[derive(Default)]
+pub(crate) struct Context<'a> {
+    pub types: Vec<&'a SectionTypeEntity>,
+    // the type index of each function
+    pub functions: Vec<u32>,
+    pub imported_functions: usize,
+    // the element type of each table
+    pub tables: Vec<Type>,
+    pub memories: usize,
+    pub globals: Vec<&'a GlobalArg>,
+    pub imported_globals: usize,
+    // the element type of each element segment
+    pub elements: Vec<Type>,
+    // the functions `ref.func` may refer to in function bodies
+    pub refs: HashSet<u32>,
+    // data segments can only be referred to with a data count section
+    pub data_count: Option<u32>,
+}
+
+impl<'a> Context<'a> {
+    pub fn func_type(&self, index: u32) -> WasmResult<&'a SectionTypeEntity> {
+        self.types
+            .get(index as usize)
+            .cloned()
+            .ok_or_else(|| WasmError::UnknownType(index))
+    }
+
+    pub fn function(&self, index: u32) -> WasmResult<&'a SectionTypeEntity> {
+        let type_index = self
+            .functions
+            .get(index as usize)
+            .ok_or_else(|| WasmError::UnknownFunction(index))?;
+        self.func_type(*type_index)
+    }
+
+    pub fn table(&self, index: u32) -> WasmResult<Type> {
+        self.tables
+            .get(index as usize)
+            .cloned()
+            .ok_or_else(|| WasmError::UnknownTable(index))
+    }
+
+    pub fn memory(&self, index: u32) -> WasmResult<()> {
+        if (index as usize) < self.memories {
+            Ok(())
+        } else {
+            Err(WasmError::UnknownMemory(index))
+        }
+    }
+
+    pub fn global(&self, index: u32) -> WasmResult<&'a GlobalArg> {
+        self.globals
+            .get(index as usize)
+            .cloned()
+            .ok_or_else(|| WasmError::UnknownGlobal(index))
+    }
+
+    pub fn element(&self, index: u32) -> WasmResult<Type> {
+        self.elements
+            .get(index as usize)
+            .cloned()
+            .ok_or_else(|| WasmError::UnknownElemSegment(index))
+    }
+
+    pub fn data(&self, index: u32) -> WasmResult<()> {
+        match self.data_count {
+            Some(count) if index < count => Ok(()),
+            Some(_) => Err(WasmError::UnknownDataSegment(index)),
+            None => Err(WasmError::DataCountRequired),
+        }
+    }
+}
+
+pub(crate) fn validate_value_type(t: Type) -> WasmResult<()> {
+    match t {
+        Type::I32 | Type::I64 | Type::F32 | Type::F64 | Type::AnyFunc | Type::AnyRef => Ok(()),
+        _ => Err(WasmError::InvalidValueType(t)),
+    }
+}
+
+pub(crate) fn validate_ref_type(t: Type) -> WasmResult<()> {
+    if t.is_ref() {
+        Ok(())
+    } else {
+        Err(WasmError::InvalidValueType(t))
+    }
+}
+
+// The functions referred to outside of function bodies: in global initializers,
+// element segments and exports.
+fn declared_refs(parts: &Parts) -> HashSet<u32> {
+    let mut refs = HashSet::new();
+    let add_expr = |expr: &[Operator], refs: &mut HashSet<u32>| {
+        for operator in expr {
+            if let Operator::RefFunc { function_index } = operator {
+                refs.insert(*function_index);
+            }
+        }
+    };
+    for entity in &parts.globals {
+        add_expr(&entity.expr, &mut refs);
+    }
+    for entity in &parts.elements {
+        match &entity.elems {
+            ElemItems::Functions(functions) => refs.extend(functions),
+            ElemItems::Expressions(exprs) => {
+                for expr in exprs {
+                    add_expr(expr, &mut refs);
+                }
+            }
+        }
+    }
+    for entity in &parts.exports {
+        if let ExternalKind::Func { signature_index } = entity.kind {
+            refs.insert(signature_index);
+        }
+    }
+    refs
+}
+
+fn validate_type(entity: &SectionTypeEntity) -> WasmResult<()> {
+    if entity.form != Type::Func {
+        return Err(WasmError::InvalidValueType(entity.form));
+    }
+    for t in entity.params.iter().chain(entity.returns.iter()) {
+        validate_value_type(*t)?;
+    }
+    if entity.returns.len() > 1 {
+        return Err(WasmError::InvalidResultArity);
+    }
+
+    Ok(())
+}
+
+fn validate_import<'a>(
+    context: &mut Context<'a>,
+    entity: &'a SectionImportEntity,
+) -> WasmResult<()> {
+    match &entity.kind {
+        ImportKind::Func { signature_index } => {
+            context.func_type(*signature_index)?;
+            context.functions.push(*signature_index);
+            context.imported_functions += 1;
+        }
+        ImportKind::Table { elem_type, limit } => {
+            validate_table_type(context, *elem_type, limit)?;
+        }
+        ImportKind::Memory { limit } => {
+            validate_memory_type(context, limit)?;
+        }
+        ImportKind::Global { global_arg } => {
+            validate_value_type(global_arg.content_type)?;
+            context.globals.push(global_arg);
+            context.imported_globals += 1;
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_function_declaration(
+    context: &mut Context,
+    entity: &SectionFuncEntity,
+) -> WasmResult<()> {
+    let signature_index = entity.signature_index as u32;
+    context.func_type(signature_index)?;
+    context.functions.push(signature_index);
+    Ok(())
+}
+
+fn validate_table(context: &mut Context, entity: &SectionTableEntity) -> WasmResult<()> {
+    validate_table_type(context, entity.elem_type, &entity.limit)
+}
+
+fn validate_table_type(
+    context: &mut Context,
+    elem_type: Type,
+    limit: &ResizableLimit,
+) -> WasmResult<()> {
+    validate_limit(limit)?;
+    validate_ref_type(elem_type)?;
+    context.tables.push(elem_type);
+    Ok(())
+}
+
+fn validate_memory(context: &mut Context, entity: &SectionMemoryEntity) -> WasmResult<()> {
+    validate_memory_type(context, &entity.limit)
+}
+
+fn validate_memory_type(context: &mut Context, limit: &ResizableLimit) -> WasmResult<()> {
+    validate_limit(limit)?;
+    if limit.initial > MAX_MEMORY_PAGES {
+        return Err(WasmError::MemorySizeTooLarge(limit.initial));
+    }
+    if let Some(max) = limit.max {
+        if max > MAX_MEMORY_PAGES {
+            return Err(WasmError::MemorySizeTooLarge(max));
+        }
+    }
+    if context.memories > 0 {
+        return Err(WasmError::MultipleMemories);
+    }
+    context.memories += 1;
+    Ok(())
+}
+
+fn validate_limit(limit: &ResizableLimit) -> WasmResult<()> {
+    match limit.max {
+        Some(max) if limit.initial > max => Err(WasmError::InvalidLimits {
+            initial: limit.initial,
+            max,
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn validate_global<'a>(
+    context: &mut Context<'a>,
+    entity: &'a SectionGlobalEntity,
+) -> WasmResult<()> {
+    validate_value_type(entity.global_arg.content_type)?;
+    validate_const_expr(context, &entity.expr, entity.global_arg.content_type)?;
+    context.globals.push(&entity.global_arg);
+    Ok(())
+}
+
+// Constant expressions may only read imported, immutable globals.
+fn validate_const_expr(context: &Context, expr: &[Operator], expected: Type) -> WasmResult<()> {
+    let mut stack = vec![];
+    for operator in expr {
+        let t = match operator {
+            Operator::I32Const { .. } => Type::I32,
+            Operator::I64Const { .. } => Type::I64,
+            Operator::F32Const { .. } => Type::F32,
+            Operator::F64Const { .. } => Type::F64,
+            Operator::RefNull { t } => {
+                validate_ref_type(*t)?;
+                *t
+            }
+            Operator::RefFunc { function_index } => {
+                context.function(*function_index)?;
+                Type::AnyFunc
+            }
+            Operator::GlobalGet { global_index } => {
+                if *global_index as usize >= context.imported_globals {
+                    return Err(WasmError::UnknownGlobal(*global_index));
+                }
+                let global = context.global(*global_index)?;
+                if global.mutability != Mutability::Const {
+                    return Err(WasmError::ConstantExpressionRequired);
+                }
+                global.content_type
+            }
+            Operator::End => break,
+            _ => return Err(WasmError::ConstantExpressionRequired),
+        };
+        stack.push(t);
+    }
+
+    match stack.as_slice() {
+        [t] if *t == expected => Ok(()),
+        [t] => Err(WasmError::TypeMismatch {
+            expected: Some(expected),
+            actual: Some(*t),
+        }),
+        [] => Err(WasmError::TypeMismatch {
+            expected: Some(expected),
+            actual: None,
+        }),
+        _ => Err(WasmError::StackHeightMismatch {
+            expected: 1,
+            actual: stack.len(),
+        }),
+    }
+}
+
+fn validate_exports(context: &Context, exports: &[&SectionExportEntity]) -> WasmResult<()> {
+    let mut names = HashSet::new();
+    for entity in exports {
+        if !names.insert(&entity.name) {
+            return Err(WasmError::DuplicateExportName(entity.name.to_string()));
+        }
+
+        match entity.kind {
+            ExternalKind::Func { signature_index } => {
+                context.function(signature_index)?;
+            }
+            ExternalKind::Table { table_index } => {
+                context.table(table_index)?;
+            }
+            ExternalKind::Memory { mem_index } => context.memory(mem_index)?,
+            ExternalKind::Global { global_index } => {
+                context.global(global_index)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_start(context: &Context, index: u32) -> WasmResult<()> {
+    let func_type = context.function(index)?;
+    if !func_type.params.is_empty() || !func_type.returns.is_empty() {
+        return Err(WasmError::InvalidStartFunction(index));
+    }
+    Ok(())
+}
+
+fn validate_element(context: &Context, entity: &SectionElemEntity) -> WasmResult<()> {
+    validate_ref_type(entity.elem_type)?;
+    if entity.mode == SegmentMode::Active {
+        let table_type = context.table(entity.table_index)?;
+        if table_type != entity.elem_type {
+            return Err(WasmError::TypeMismatch {
+                expected: Some(table_type),
+                actual: Some(entity.elem_type),
+            });
+        }
+        validate_const_expr(context, &entity.offset, Type::I32)?;
+    }
+    match &entity.elems {
+        ElemItems::Functions(functions) => {
+            for index in functions {
+                context.function(*index)?;
+            }
+        }
+        ElemItems::Expressions(exprs) => {
+            for expr in exprs {
+                validate_const_expr(context, expr, entity.elem_type)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate_codes(context: &Context, codes: &[&SectionCodeEntity]) -> WasmResult<()> {
+    let functions = context.functions.len() - context.imported_functions;
+    if functions != codes.len() {
+        return Err(WasmError::FunctionCodeCountMismatch {
+            functions,
+            codes: codes.len(),
+        });
+    }
+
+    for (index, entity) in codes.iter().enumerate() {
+        let function_index = (context.imported_functions + index) as u32;
+        let func_type = context.function(function_index)?;
+        // lazily parsed bodies are decoded first
+        let decoded;
+        let entity = match &entity.body {
+            Some(body) if entity.lazy => {
+                decoded = parser_section_code_entity(body.clone())
+                    .map_err(|err| err.in_section(SECTION_CODE))?;
+                &decoded
+            }
+            _ => *entity,
+        };
+        validate_function(context, func_type, entity)
+            .map_err(|err| err.in_function(function_index).in_section(SECTION_CODE))?;
+    }
+
+    Ok(())
+}
+
+fn validate_data(context: &Context, entity: &SectionDataEntity) -> WasmResult<()> {
+    if entity.mode != SegmentMode::Active {
+        return Ok(());
+    }
+    context.memory(entity.memid)?;
+    validate_const_expr(context, &entity.expr, Type::I32)
+}
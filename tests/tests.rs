@@ -1,11 +1,25 @@
 #[cfg(test)]
 mod tests {
+    use std::borrow::Cow;
+    use std::cell::RefCell;
     use std::fs::{self, File};
-    use std::io::Read;
+    use std::io::{self, Read};
+    use std::ops::Range;
     use std::path::PathBuf;
+    use std::rc::Rc;
 
-    use rs_wasm::primitives::Frame;
-    use rs_wasm::Parser;
+    use rs_wasm::encoder::encode;
+    use rs_wasm::primitives::ImportKind;
+    use rs_wasm::primitives::{
+        ElemItems, ExternalKind, Frame, Mutability, Operator, Payload, ResizableLimit, Section,
+        SectionCodeEntity, SectionDataEntity, SectionExportEntity, SectionFuncEntity,
+        SectionMemoryEntity, SectionTypeEntity, SegmentMode, Type,
+    };
+    use rs_wasm::runtime::{
+        Extern, Func, FuncType, Global, Instance, Linker, Memory, Table, Trap, Value, PAGE_SIZE,
+    };
+    use rs_wasm::text::{self, format_f32, format_f64, print, WastRunner};
+    use rs_wasm::{validate, CustomSection, Function, Module, Parser, Position, WasmError};
 
     #[test]
     fn test_parser() {
@@ -29,7 +43,7 @@ mod tests {
                         dir.path(),
                         err
                     ),
-                    // Frame::Section { section } => println!("{:?}\n", section),
+                    // Frame::Section { section, .. } => println!("{:?}\n", section),
                     _ => continue,
                 }
             }
@@ -40,10 +54,1787 @@ mod tests {
         println!("Pass the parser test. {:?}/{:?}", current, count);
     }
 
+    #[test]
+    fn test_parser_ranges() {
+        for entry in fs::read_dir("tests/wasm").unwrap() {
+            let path = entry.unwrap().path();
+            let data = read_file_data(&path);
+            let mut r = Parser::new(data.as_slice());
+            let mut end = 8;
+            loop {
+                match r.read() {
+                    Frame::End => break,
+                    Frame::Section { section, range } => {
+                        assert_eq!(range.start, end, "{:?}", path);
+                        end = range.end;
+
+                        if let Section::Code { entities, .. } = section {
+                            for entity in entities {
+                                assert_eq!(entity.code.len(), entity.code_ranges.len());
+                                let mut op_end = entity.code_ranges[0].start;
+                                assert!(entity.range.start < op_end, "{:?}", path);
+                                for op_range in entity.code_ranges {
+                                    assert_eq!(op_range.start, op_end, "{:?}", path);
+                                    op_end = op_range.end;
+                                }
+                                assert_eq!(op_end, entity.range.end, "{:?}", path);
+                                assert!(entity.range.end <= range.end, "{:?}", path);
+                            }
+                        }
+                    }
+                    Frame::ParserError { err } => panic!("{:?} {:?}", path, err),
+                    _ => continue,
+                }
+            }
+            assert_eq!(end, data.len(), "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn test_parser_error_position() {
+        #[rustfmt::skip]
+        let data = [
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00,
+            // type section: () -> ()
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            // function section
+            0x03, 0x02, 0x01, 0x00,
+            // code section: nop, <invalid operator>
+            0x0A, 0x05, 0x01, 0x03, 0x00, 0x01, 0xFF,
+        ];
+
+        let mut r = Parser::new(&data);
+        let err = loop {
+            match r.read() {
+                Frame::ParserError { err } => break err,
+                Frame::End => panic!("[test_parser_error_position] expect an error"),
+                _ => continue,
+            }
+        };
+
+        match err.inner() {
+            WasmError::InvalidOperator(0xFF) => {}
+            err => panic!("[test_parser_error_position] unexpected error {:?}", err),
+        }
+        assert_eq!(
+            err.position(),
+            Some(&Position {
+                offset: 24,
+                section: Some(0x0A),
+                function_index: Some(0),
+                operator_index: Some(1),
+            })
+        );
+        assert_eq!(
+            err.to_string(),
+            "[wasm]: invalid operator code 255 at offset 0x18, section 10, function 0, operator 1"
+        );
+
+        // functions are numbered after the imported ones, also in lazy bodies
+        #[rustfmt::skip]
+        let data = [
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00,
+            // type section: () -> ()
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            // import section: "m" "f" (func (type 0))
+            0x02, 0x07, 0x01, 0x01, 0x6D, 0x01, 0x66, 0x00, 0x00,
+            // function section
+            0x03, 0x02, 0x01, 0x00,
+            // code section: nop, <invalid operator>
+            0x0A, 0x05, 0x01, 0x03, 0x00, 0x01, 0xFF,
+        ];
+        let err = Parser::new(&data).find_map(Result::err).unwrap();
+        assert_eq!(err.position().unwrap().function_index, Some(1));
+        let body = Parser::new(&data)
+            .lazy_bodies()
+            .filter_map(|payload| match payload.unwrap() {
+                Payload::Section {
+                    section: Section::Code { mut entities, .. },
+                    ..
+                } => entities.pop().unwrap().body,
+                _ => None,
+            })
+            .next()
+            .unwrap();
+        let err = body.operators_reader().unwrap().find_map(Result::err);
+        assert_eq!(err.unwrap().position().unwrap().function_index, Some(1));
+    }
+
+    #[test]
+    fn test_parser_strict() {
+        for entry in fs::read_dir("tests/wasm").unwrap() {
+            let path = entry.unwrap().path();
+            let data = read_file_data(&path);
+            if let Err(err) = Module::parse_strict(&data) {
+                panic!("{:?} {}", path, err);
+            }
+        }
+
+        #[rustfmt::skip]
+        // the section, the error in strict mode, and whether lenient mode accepts it
+        let cases: Vec<(&[u8], &str, bool)> = vec![
+            // memory section, then type section
+            (&[0x05, 0x03, 0x01, 0x00, 0x01, 0x01, 0x01, 0x00], "section 1 out of order", true),
+            (&[0x01, 0x01, 0x00, 0x01, 0x01, 0x00], "duplicate section 1", true),
+            (&[0x01, 0x02, 0x00, 0x00], "section size mismatch", true),
+            // a function without a code section
+            (
+                &[0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00],
+                "function and code section have inconsistent lengths, 1 != 0",
+                true,
+            ),
+            // a code section without a function section
+            (
+                &[0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x0A, 0x04, 0x01, 0x02, 0x00, 0x0B],
+                "function and code section have inconsistent lengths, 0 != 1",
+                true,
+            ),
+            // memory.size 1
+            (
+                &[
+                    0x01, 0x04, 0x01, 0x60, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00,
+                    0x05, 0x03, 0x01, 0x00, 0x01,
+                    0x0A, 0x07, 0x01, 0x05, 0x00, 0x3F, 0x01, 0x1A, 0x0B,
+                ],
+                "zero byte expected",
+                true,
+            ),
+            // a count of 0 in 6 bytes
+            (&[0x01, 0x06, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00], "integer representation too long", false),
+            // a count beyond 32 bits
+            (&[0x01, 0x05, 0x80, 0x80, 0x80, 0x80, 0x70], "integer too large", false),
+            // i32.const with bits beyond 32 that do not extend the sign
+            (
+                &[0x06, 0x0A, 0x01, 0x7F, 0x00, 0x41, 0xFF, 0xFF, 0xFF, 0xFF, 0x4F, 0x0B],
+                "integer too large",
+                false,
+            ),
+            // a memory with limits flags 2
+            (&[0x05, 0x04, 0x01, 0x02, 0x00, 0x00], "malformed limits flags 2", true),
+            // an i32 global of mutability 2
+            (&[0x06, 0x06, 0x01, 0x7F, 0x02, 0x41, 0x00, 0x0B], "malformed mutability 2", true),
+        ];
+        for (section, message, lenient) in cases {
+            let mut data = vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+            data.extend_from_slice(section);
+            assert_eq!(Module::parse(&data).is_ok(), lenient, "{}", message);
+            match Module::parse_strict(&data) {
+                Ok(_) => panic!("expected {:?}", message),
+                Err(err) => assert_eq!(err.inner().to_string(), format!("[wasm]: {}", message)),
+            }
+        }
+
+        // i32.const -1 in 5 bytes
+        let data = [
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x06, 0x0A, 0x01, 0x7F, 0x00, 0x41,
+            0xFF, 0xFF, 0xFF, 0xFF, 0x7F, 0x0B,
+        ];
+        assert!(Module::parse_strict(&data).is_ok());
+        // an export name which is not UTF-8
+        let data = [
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x07, 0x05, 0x01, 0x01, 0xFF, 0x00,
+            0x00,
+        ];
+        match Module::parse_strict(&data).map_err(|err| err.inner().to_string()) {
+            Err(ref message) if message == "[wasm]: malformed UTF-8 encoding" => {}
+            result => panic!("unexpected {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_parser_iterator() {
+        for entry in fs::read_dir("tests/wasm").unwrap() {
+            let path = entry.unwrap().path();
+            let data = read_file_data(&path);
+            let payloads = Parser::new(&data).collect::<Result<Vec<_>, _>>().unwrap();
+            match payloads.first() {
+                Some(Payload::Head { version: 1 }) => {}
+                payload => panic!("{:?} unexpected {:?}", path, payload),
+            }
+
+            let sections = Parser::new(&data)
+                .filter_map(|payload| match payload {
+                    Ok(Payload::Section { section, .. }) => Some(section),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(sections.len(), payloads.len() - 1, "{:?}", path);
+            assert_eq!(sections.len(), read_sections(&data).len(), "{:?}", path);
+        }
+
+        // a type section, then a section with an unknown id
+        let data = [
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x0D, 0x00,
+        ];
+        let mut parser = Parser::new(&data);
+        assert!(parser.next().unwrap().is_ok());
+        assert!(parser.next().unwrap().is_ok());
+        match parser.next() {
+            Some(Err(ref err)) if err.inner().to_string() == "[wasm]: invalid section 13" => {}
+            payload => panic!("unexpected {:?}", payload),
+        }
+        assert!(parser.next().is_none());
+        assert!(Parser::new(&data).collect::<Result<Vec<_>, _>>().is_err());
+
+        // running out of data is an error, after which the iterator is done
+        let mut parser = Parser::incremental();
+        parser.feed(&data[..10]);
+        assert!(parser.next().unwrap().is_ok());
+        match parser.next() {
+            Some(Err(ref err)) if err.to_string() == "parser EOF at offset 0xa" => {}
+            payload => panic!("unexpected {:?}", payload),
+        }
+        parser.feed(&data[10..]);
+        assert!(parser.next().is_none());
+    }
+
+    #[test]
+    fn test_parser_incremental() {
+        for entry in fs::read_dir("tests/wasm").unwrap() {
+            let path = entry.unwrap().path();
+            let data = read_file_data(&path);
+            let mut chunks = data.chunks(7);
+            let mut r = Parser::incremental();
+            let mut ranges = vec![];
+            loop {
+                match r.read() {
+                    Frame::NeedMoreData { hint } => {
+                        assert!(hint > 0, "{:?}", path);
+                        match chunks.next() {
+                            Some(chunk) => r.feed(chunk),
+                            None => r.finish(),
+                        }
+                    }
+                    Frame::Section { range, .. } => ranges.push(range),
+                    Frame::ParserError { err } => panic!("{:?} {:?}", path, err),
+                    Frame::End => break,
+                    Frame::Head { .. } => continue,
+                }
+            }
+
+            let expected = Parser::new(&data)
+                .filter_map(|payload| match payload {
+                    Ok(Payload::Section { range, .. }) => Some(range),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(ranges, expected, "{:?}", path);
+        }
+
+        let mut r = Parser::incremental();
+        r.feed(&[0x00, 0x61, 0x73]);
+        match r.read() {
+            Frame::NeedMoreData { hint: 5 } => {}
+            frame => panic!("unexpected {:?}", frame),
+        }
+        // the head, then a type section of 4 bytes of which 1 is buffered
+        r.feed(&[0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0x04, 0x01]);
+        match (r.read(), r.read()) {
+            (Frame::Head { version: 1 }, Frame::NeedMoreData { hint: 3 }) => {}
+            frames => panic!("unexpected {:?}", frames),
+        }
+        r.feed(&[0x60, 0x00]);
+        r.finish();
+        match r.read() {
+            Frame::ParserError { ref err } if err.inner().to_string() == "parser EOF" => {}
+            frame => panic!("unexpected {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn test_parser_from_reader() {
+        fn ranges(parser: Parser) -> Vec<Range<usize>> {
+            parser
+                .map(|payload| match payload {
+                    Ok(Payload::Section { range, .. }) => range,
+                    Ok(Payload::Head { .. }) => 0..8,
+                    Err(err) => panic!("{:?}", err),
+                })
+                .collect()
+        }
+
+        for entry in fs::read_dir("tests/wasm").unwrap() {
+            let path = entry.unwrap().path();
+            let data = read_file_data(&path);
+            let parser = Parser::from_reader(File::open(&path).unwrap());
+            assert_eq!(ranges(parser), ranges(Parser::new(&data)), "{:?}", path);
+        }
+
+        // fails after the head
+        struct FailingReader(usize);
+        impl Read for FailingReader {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let head = [0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+                if self.0 == head.len() {
+                    return Err(io::Error::new(io::ErrorKind::Other, "disconnected"));
+                }
+                buf[0] = head[self.0];
+                self.0 += 1;
+                Ok(1)
+            }
+        }
+        let mut parser = Parser::from_reader(FailingReader(0));
+        match parser.next() {
+            Some(Ok(Payload::Head { version: 1 })) => {}
+            payload => panic!("unexpected {:?}", payload),
+        }
+        let err = match parser.read() {
+            Frame::ParserError { err } => err,
+            frame => panic!("unexpected {:?}", frame),
+        };
+        match err.inner() {
+            WasmError::Io(ref err) if err.kind() == io::ErrorKind::Other => {}
+            err => panic!("unexpected {:?}", err),
+        }
+        assert_eq!(err.to_string(), "[wasm]: disconnected at offset 0x8");
+
+        // a section claiming 4 GiB is read as far as the input goes
+        let data = [
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00, 0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F,
+            0x00,
+        ];
+        let payloads = Parser::from_reader(&data[..]).collect::<Vec<_>>();
+        assert_eq!(payloads.len(), 2);
+        assert!(payloads[1].is_err(), "{:?}", payloads[1]);
+    }
+
+    #[test]
+    fn test_parser_zero_copy() {
+        let source = r#"(module
+  (import "env" "memory" (memory 1))
+  (func (export "run"))
+  (data (i32.const 0) "hello"))"#;
+        let mut data = Module::from_sections(text::parse(source).unwrap()).encode();
+        // a custom section named "meta" with payload [1, 2]
+        data.extend_from_slice(&[0x00, 0x07, 0x04, b'm', b'e', b't', b'a', 0x01, 0x02]);
+        let within = |bytes: &[u8]| {
+            let range = data.as_ptr() as usize..data.as_ptr() as usize + data.len();
+            range.contains(&(bytes.as_ptr() as usize))
+        };
+
+        let module = Module::parse(&data).unwrap();
+        match (&module.imports[0].mod_name, &module.imports[0].field_name) {
+            (Cow::Borrowed(mod_name), Cow::Borrowed(field_name)) => {
+                assert_eq!((*mod_name, *field_name), ("env", "memory"));
+                assert!(within(mod_name.as_bytes()) && within(field_name.as_bytes()));
+            }
+            names => panic!("unexpected {:?}", names),
+        }
+        match &module.exports[0].name {
+            Cow::Borrowed(name) => assert!(*name == "run" && within(name.as_bytes())),
+            name => panic!("unexpected {:?}", name),
+        }
+        match &module.data[0].data {
+            Cow::Borrowed(bytes) => assert!(*bytes == b"hello" && within(bytes)),
+            bytes => panic!("unexpected {:?}", bytes),
+        }
+        match (&module.customs[0].name, &module.customs[0].payload) {
+            (Cow::Borrowed("meta"), Cow::Borrowed(payload)) => {
+                assert!(*payload == [1, 2] && within(payload))
+            }
+            custom => panic!("unexpected {:?}", custom),
+        }
+        assert_eq!(module.encode(), data);
+
+        // fed input is not borrowed
+        let mut r = Parser::incremental();
+        r.feed(&data);
+        r.finish();
+        let sections = r.map(Result::unwrap).filter_map(|payload| match payload {
+            Payload::Section { section, .. } => Some(section),
+            _ => None,
+        });
+        let module = Module::from_sections(sections.collect());
+        match &module.data[0].data {
+            Cow::Owned(bytes) => assert_eq!(bytes.as_slice(), b"hello"),
+            bytes => panic!("unexpected {:?}", bytes),
+        }
+
+        let owned = Module::parse(&data).unwrap().into_owned();
+        match &owned.exports[0].name {
+            Cow::Owned(name) => assert_eq!(name, "run"),
+            name => panic!("unexpected {:?}", name),
+        }
+    }
+
+    #[test]
+    fn test_parser_lazy_bodies() {
+        fn codes(parser: Parser) -> Vec<SectionCodeEntity> {
+            parser
+                .filter_map(|payload| match payload.unwrap() {
+                    Payload::Section {
+                        section: Section::Code { entities, .. },
+                        ..
+                    } => Some(entities),
+                    _ => None,
+                })
+                .flatten()
+                .collect()
+        }
+
+        for entry in fs::read_dir("tests/wasm").unwrap() {
+            let path = entry.unwrap().path();
+            let data = read_file_data(&path);
+            let eager = codes(Parser::new(&data));
+            let lazy = codes(Parser::new(&data).lazy_bodies());
+            assert_eq!(eager.len(), lazy.len(), "{:?}", path);
+            for (eager, lazy) in eager.iter().zip(&lazy) {
+                assert!(lazy.lazy && !eager.lazy);
+                assert!(lazy.locals.is_empty() && lazy.code.is_empty());
+                assert_eq!(eager.range, lazy.range, "{:?}", path);
+                let body = lazy.body.as_ref().unwrap();
+                assert_eq!(body.range(), eager.range, "{:?}", path);
+
+                let mut locals = vec![];
+                for entry in body.locals_reader().unwrap() {
+                    let (count, t) = entry.unwrap();
+                    locals.extend((0..count).map(|_| t));
+                }
+                assert_eq!(locals, eager.locals, "{:?}", path);
+
+                let operators = body.operators_reader().unwrap();
+                let operators = operators.map(Result::unwrap).collect::<Vec<_>>();
+                let code = operators.iter().map(|(op, _)| op).collect::<Vec<_>>();
+                let eager_code = eager.code.iter().collect::<Vec<_>>();
+                assert_eq!(format!("{:?}", code), format!("{:?}", eager_code));
+                let offsets = eager.code_ranges.iter().map(|range| range.start);
+                let lazy_offsets = operators.iter().map(|(_, offset)| *offset);
+                assert!(offsets.eq(lazy_offsets), "{:?}", path);
+            }
+        }
+
+        #[rustfmt::skip]
+        let data = [
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00,
+            // type section: () -> ()
+            0x01, 0x04, 0x01, 0x60, 0x00, 0x00,
+            // function section
+            0x03, 0x02, 0x01, 0x00,
+            // code section: a local i32, nop, <invalid operator>
+            0x0A, 0x07, 0x01, 0x05, 0x01, 0x01, 0x7F, 0x01, 0xFF,
+        ];
+        let lazy = codes(Parser::new(&data).lazy_bodies());
+        let body = lazy[0].body.as_ref().unwrap();
+        let mut locals = body.locals_reader().unwrap();
+        assert_eq!(locals.declaration_count(), 1);
+        assert_eq!(locals.next().unwrap().unwrap(), (1, Type::I32));
+        assert!(locals.next().is_none());
+        let operators = locals.into_operators_reader().unwrap();
+        assert_eq!(operators.offset(), 25);
+
+        // declarations not read are skipped
+        let locals = body.locals_reader().unwrap();
+        let mut operators = locals.into_operators_reader().unwrap();
+        match operators.next() {
+            Some(Ok((Operator::Nop, 25))) => {}
+            operator => panic!("unexpected {:?}", operator),
+        }
+        let err = operators.next().unwrap().unwrap_err();
+        assert!(operators.next().is_none());
+        assert_eq!(
+            err.position(),
+            Some(&Position {
+                offset: 26,
+                section: None,
+                function_index: Some(0),
+                operator_index: Some(1),
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        for entry in fs::read_dir("tests/wasm").unwrap() {
+            let path = entry.unwrap().path();
+            let data = read_file_data(&path);
+            let sections = read_sections(&data);
+            // bodies that were never decoded are validated too
+            let lazy_sections = Parser::new(&data)
+                .lazy_bodies()
+                .filter_map(|payload| match payload.unwrap() {
+                    Payload::Section { section, .. } => Some(section),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+
+            for result in vec![validate(&sections), validate(&lazy_sections)] {
+                // reloc.wasm is an LLVM object file that sets an imported immutable global
+                if path.ends_with("reloc.wasm") {
+                    match result.unwrap_err().inner() {
+                        WasmError::ImmutableGlobal(0) => {}
+                        err => panic!("[test_validate] unexpected error {:?}", err),
+                    }
+                } else if let Err(err) = result {
+                    panic!("[test_validate] file name {:?} err {}", path, err);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_type_mismatch() {
+        #[rustfmt::skip]
+        let data = [
+            0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00,
+            // type section: () -> i32
+            0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7F,
+            // function section
+            0x03, 0x02, 0x01, 0x00,
+            // code section: i64.const 0, end
+            0x0A, 0x06, 0x01, 0x04, 0x00, 0x42, 0x00, 0x0B,
+        ];
+
+        let err = validate(&read_sections(&data)).unwrap_err();
+        match err.inner() {
+            WasmError::TypeMismatch {
+                expected: Some(Type::I32),
+                actual: Some(Type::I64),
+            } => {}
+            err => panic!("[test_validate_type_mismatch] unexpected error {:?}", err),
+        }
+        assert_eq!(
+            err.position(),
+            Some(&Position {
+                offset: 26,
+                section: Some(0x0A),
+                function_index: Some(0),
+                operator_index: Some(1),
+            })
+        );
+
+        let source = r#"(module
+  (import "m" "f" (func))
+  (func (result i32) i64.const 0))"#;
+        let err = validate(&text::parse(source).unwrap()).unwrap_err();
+        assert_eq!(err.position().unwrap().function_index, Some(1));
+    }
+
+    #[test]
+    fn test_module() {
+        for entry in fs::read_dir("tests/wasm").unwrap() {
+            let path = entry.unwrap().path();
+            let data = read_file_data(&path);
+            let module = Module::parse(&data).unwrap();
+
+            let imported = module
+                .imports
+                .iter()
+                .filter(|entity| match entity.kind {
+                    ImportKind::Func { .. } => true,
+                    _ => false,
+                })
+                .count();
+            assert_eq!(module.function_count(), imported + module.codes.len());
+
+            for index in 0..module.function_count() {
+                match module.function(index as u32).unwrap() {
+                    Function::Imported { import, .. } => {
+                        assert!(index < imported, "{:?}", path);
+                        match import.kind {
+                            ImportKind::Func { .. } => {}
+                            _ => panic!("[test_module] {:?} is not a function", import),
+                        }
+                    }
+                    Function::Defined { signature, body } => {
+                        assert!(index >= imported, "{:?}", path);
+                        let entity = &module.functions[index - imported];
+                        assert_eq!(
+                            signature.params,
+                            module.types[entity.signature_index].params
+                        );
+                        assert!(std::ptr::eq(body, &module.codes[index - imported]));
+                    }
+                }
+            }
+            assert!(module.function(module.function_count() as u32).is_none());
+
+            if !path.ends_with("reloc.wasm") {
+                module.validate().unwrap();
+            }
+        }
+
+        // the index spaces follow changes to the imports
+        let source = r#"(module (import "m" "f" (func)) (func (result i32) i32.const 0))"#;
+        let mut module = Module::from_sections(text::parse(source).unwrap());
+        assert_eq!(module.function_count(), 2);
+        module.imports.clear();
+        assert_eq!(module.function_count(), 1);
+        match module.function(0) {
+            Some(Function::Defined { signature, .. }) => assert_eq!(signature.returns.len(), 1),
+            function => panic!("[test_module] {:?}", function),
+        }
+    }
+
+    #[test]
+    fn test_encoder() {
+        let mut failed = vec![];
+        for entry in fs::read_dir("tests/wasm").unwrap() {
+            let path = entry.unwrap().path();
+            let data = read_file_data(&path);
+            if Module::parse(&data).unwrap().encode() != data {
+                failed.push(path.clone());
+            }
+            if encode(&read_sections(&data)) != data {
+                failed.push(path.clone());
+            }
+
+            // bodies that were never decoded are written as they were read
+            let sections = Parser::new(&data)
+                .lazy_bodies()
+                .filter_map(|payload| match payload.unwrap() {
+                    Payload::Section { section, .. } => Some(section),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            if encode(&sections) != data {
+                failed.push(path);
+            }
+        }
+        assert!(
+            failed.is_empty(),
+            "[test_encoder] {} {:?}",
+            failed.len(),
+            failed
+        );
+
+        // a changed section is encoded again, and a changed body canonically
+        let data = read_file_data(&PathBuf::from("tests/wasm/reloc.wasm"));
+        let mut module = Module::parse(&data).unwrap();
+        module.exports.clear();
+        module.codes[0].locals.push(Type::I64);
+        let encoded = module.encode();
+        assert_ne!(encoded, data);
+        let module = Module::parse(&encoded).unwrap();
+        assert!(module.exports.is_empty());
+        assert_eq!(module.codes[0].locals.last(), Some(&Type::I64));
+        assert_eq!(module.encode(), encoded);
+
+        // a decoded body whose code was cleared is not written back as it was read
+        let mut module = Module::parse(&data).unwrap();
+        module.codes[0].code.clear();
+        let encoded = module.encode();
+        assert!(Module::parse(&encoded).unwrap().codes[0].code.is_empty());
+    }
+
+    #[test]
+    fn test_printer() {
+        let mut module = Module::default();
+        module.types.push(SectionTypeEntity {
+            form: Type::Func,
+            params: vec![Type::I32, Type::I32],
+            returns: vec![Type::I32],
+        });
+        module
+            .functions
+            .push(SectionFuncEntity { signature_index: 0 });
+        module.codes.push(SectionCodeEntity {
+            locals: vec![Type::F32],
+            code: vec![
+                Operator::LocalGet { local_index: 0 },
+                Operator::If { t: Type::I32 },
+                Operator::F32Const { val: 0x7FA0_0000 },
+                Operator::Drop,
+                Operator::LocalGet { local_index: 1 },
+                Operator::Else,
+                Operator::I32Const { val: -1 },
+                Operator::End,
+                Operator::End,
+            ],
+            range: 0..0,
+            code_ranges: vec![],
+            body: None,
+            lazy: false,
+        });
+        module.memories.push(SectionMemoryEntity {
+            limit: ResizableLimit {
+                initial: 1,
+                max: None,
+            },
+        });
+        module.exports.push(SectionExportEntity {
+            name: "add".into(),
+            kind: ExternalKind::Func { signature_index: 0 },
+        });
+        module.data.push(SectionDataEntity {
+            mode: SegmentMode::Active,
+            memid: 0,
+            expr: vec![Operator::I32Const { val: 8 }, Operator::End],
+            data: b"hi\n\"".to_vec().into(),
+        });
+        module.customs.push(CustomSection {
+            name: "name".into(),
+            payload: vec![
+                0x01, 0x06, 0x01, 0x00, 0x03, b'a', b'd', b'd', // function names
+                0x02, 0x06, 0x01, 0x00, 0x01, 0x00, 0x01, b'x', // local names
+            ]
+            .into(),
+        });
+
+        let head = r#"(module
+  (type (;0;) (func (param i32 i32) (result i32)))
+  (func $add (type 0) (param $x i32) (param i32) (result i32)
+    (local f32)"#;
+        let tail = r#"
+  (memory (;0;) 1)
+  (export "add" (func $add))
+  (data (;0;) (i32.const 8) "hi\0a\""))
+"#;
+        let flat = r#"
+    local.get $x
+    if (result i32)
+      f32.const nan:0x200000
+      drop
+      local.get 1
+    else
+      i32.const -1
+    end)"#;
+        let folded = r#"
+    (if (result i32)
+      (local.get $x)
+      (then
+        (drop
+          (f32.const nan:0x200000))
+        (local.get 1))
+      (else
+        (i32.const -1))))"#;
+        assert_eq!(print(&module), format!("{}{}{}", head, flat, tail));
+        assert_eq!(
+            format!("{:#}", module),
+            format!("{}{}{}", head, folded, tail)
+        );
+
+        assert_eq!(format_f32(0x3F80_0000), "0x1p+0");
+        assert_eq!(format_f32(0x8000_0000), "-0x0p+0");
+        assert_eq!(format_f32(0x0000_0001), "0x0.000002p-126");
+        assert_eq!(format_f32(0x7F7F_FFFF), "0x1.fffffep+127");
+        assert_eq!(format_f32(0xFF80_0000), "-inf");
+        assert_eq!(format_f32(0x7FC0_0000), "nan");
+        assert_eq!(format_f32(0xFFC0_0001), "-nan:0x400001");
+        assert_eq!(format_f64(0x3FB9_9999_9999_999A), "0x1.999999999999ap-4");
+        assert_eq!(format_f64(0xC000_0000_0000_0000), "-0x1p+1");
+        assert_eq!(format_f64(0x7FF0_0000_0000_0001), "nan:0x1");
+
+        for entry in fs::read_dir("tests/wasm").unwrap() {
+            let data = read_file_data(&entry.unwrap().path());
+            let module = Module::parse(&data).unwrap();
+            for text in &[format!("{}", module), format!("{:#}", module)] {
+                assert!(text.starts_with("(module") && text.ends_with(")\n"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_text_parser() {
+        for entry in fs::read_dir("tests/wasm").unwrap() {
+            let path = entry.unwrap().path();
+            let data = read_file_data(&path);
+            let module = Module::parse(&data).unwrap();
+
+            // custom sections have no text format
+            let sections = read_sections(&data)
+                .into_iter()
+                .filter(|section| match section {
+                    Section::Custom { .. } => false,
+                    _ => true,
+                })
+                .collect();
+            let expected = print(&Module::from_sections(sections));
+
+            for text in &[format!("{}", module), format!("{:#}", module)] {
+                let sections = text::parse(text)
+                    .unwrap_or_else(|err| panic!("[test_text_parser] {:?} {}", path, err));
+                assert_eq!(
+                    print(&Module::from_sections(sections)),
+                    expected,
+                    "{:?}",
+                    path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_text_parser_abbreviations() {
+        let source = r#"(module $m
+  ;; a line comment
+  (type $sig (func (param i32) (result i32)))
+  (import "env" "log" (func $log (param i32)))
+  (func $fac (export "fac") (export "factorial") (type $sig) (param $n i32) (result i32)
+    (local $acc i32)
+    (local.set $acc (i32.const 1))
+    (block $done
+      (loop $again
+        (br_if $done (i32.eqz (local.get $n)))
+        (local.set $acc (i32.mul (local.get $acc) (local.get $n)))
+        (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+        (br $again)))
+    local.get $acc (; a block (; nested ;) comment ;)
+    call $log
+    (local.get $acc))
+  (func $pick (param f32 f64) (result f64)
+    (if (result f64) (f32.gt (local.get 0) (f32.const 0x1.8p+1))
+      (then (f64.const -nan:0x1_0000))
+      (else (f64.const 1_0.25e1))))
+  (func $main
+    f32.const 0x1.000001p0 drop
+    f32.const 0x1.000003p0 drop
+    f32.const 1e-45 drop)
+  (table funcref (elem $fac $pick))
+  (memory (export "mem") (data "hi\00\u{263a}"))
+  (global $g (mut i64) (i64.const 0xffff_ffff_ffff_ffff))
+  (data (offset (i32.const 16)) "a" "b")
+  (start $main))"#;
+        let expected = r#"(module
+  (type (;0;) (func (param i32) (result i32)))
+  (type (;1;) (func (param i32)))
+  (type (;2;) (func (param f32 f64) (result f64)))
+  (type (;3;) (func))
+  (import "env" "log" (func (;0;) (type 1) (param i32)))
+  (func (;1;) (type 0) (param i32) (result i32)
+    (local i32)
+    i32.const 1
+    local.set 1
+    block
+      loop
+        local.get 0
+        i32.eqz
+        br_if 1
+        local.get 1
+        local.get 0
+        i32.mul
+        local.set 1
+        local.get 0
+        i32.const 1
+        i32.sub
+        local.set 0
+        br 0
+      end
+    end
+    local.get 1
+    call 0
+    local.get 1)
+  (func (;2;) (type 2) (param f32 f64) (result f64)
+    local.get 0
+    f32.const 0x1.8p+1
+    f32.gt
+    if (result f64)
+      f64.const -nan:0x10000
+    else
+      f64.const 0x1.9ap+6
+    end)
+  (func (;3;) (type 3)
+    f32.const 0x1p+0
+    drop
+    f32.const 0x1.000004p+0
+    drop
+    f32.const 0x0.000002p-126
+    drop)
+  (table (;0;) 2 2 funcref)
+  (memory (;0;) 1 1)
+  (global (;0;) (mut i64) (i64.const -1))
+  (export "fac" (func 1))
+  (export "factorial" (func 1))
+  (export "mem" (memory 0))
+  (start 3)
+  (elem (;0;) (i32.const 0) 1 2)
+  (data (;0;) (i32.const 0) "hi\00\e2\98\ba")
+  (data (;1;) (i32.const 16) "ab"))
+"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        assert_eq!(print(&module), expected);
+        module.validate().unwrap();
+
+        // operators keep their range in the source
+        let range = module.codes[0].code_ranges[1].clone();
+        assert_eq!(&source[range], "local.set $acc");
+
+        let err = text::parse("(module (func (call $nope)))").unwrap_err();
+        match err.inner() {
+            WasmError::UnknownIdentifier(id) => assert_eq!(id, "$nope"),
+            err => panic!("[test_text_parser_abbreviations] {:?}", err),
+        }
+        assert_eq!(err.position().unwrap().offset, 20);
+
+        let err = text::parse("(module (func (i32.const 0x1_0000_0000)))").unwrap_err();
+        match err.inner() {
+            WasmError::InvalidNumber(number) => assert_eq!(number, "0x1_0000_0000"),
+            err => panic!("[test_text_parser_abbreviations] {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_interpreter() {
+        let source = r#"(module
+  (memory 1 2)
+  (global $counter (mut i32) (i32.const 10))
+  (func $fac (param i64) (result i64)
+    (if (result i64) (i64.eqz (local.get 0))
+      (then (i64.const 1))
+      (else (i64.mul (local.get 0) (call $fac (i64.sub (local.get 0) (i64.const 1)))))))
+  (func $sum (param $n i32) (result i32) (local $acc i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.eqz (local.get $n)))
+        (local.set $acc (i32.add (local.get $acc) (local.get $n)))
+        (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+        (br $next)))
+    (local.get $acc))
+  (func $switch (param i32) (result i32)
+    (block $default
+      (block $one
+        (block $zero
+          (br_table $zero $one $default (local.get 0)))
+        (return (i32.const 100)))
+      (return (i32.const 101)))
+    (i32.const 102))
+  (func $div (param i32 i32) (result i32)
+    (i32.div_s (local.get 0) (local.get 1)))
+  (func $trap
+    (unreachable))
+  (func $store (param i32 i64) (result i32)
+    (i64.store offset=4 (local.get 0) (local.get 1))
+    (i32.load16_s offset=4 (local.get 0)))
+  (func $grow (param i32) (result i32)
+    (drop (memory.grow (local.get 0)))
+    (memory.size))
+  (func $count (result i32)
+    (global.set $counter (i32.add (global.get $counter) (i32.const 1)))
+    (global.get $counter))
+  (func $runaway
+    (call $runaway))
+  (func $float (param f32 f32) (result f32)
+    (f32.min (f32.nearest (local.get 0)) (local.get 1)))
+  (func $truncate (param f64) (result i32)
+    (i32.trunc_f64_u (local.get 0))))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        let instance = Instance::new(module).unwrap();
+
+        let i32s = |values: &[i32]| values.iter().map(|v| Value::I32(*v)).collect::<Vec<_>>();
+        let cases = vec![
+            (
+                0,
+                vec![Value::I64(20)],
+                Ok(vec![Value::I64(2_432_902_008_176_640_000)]),
+            ),
+            (1, i32s(&[100]), Ok(i32s(&[5050]))),
+            (2, i32s(&[0]), Ok(i32s(&[100]))),
+            (2, i32s(&[1]), Ok(i32s(&[101]))),
+            (2, i32s(&[2]), Ok(i32s(&[102]))),
+            (2, i32s(&[-1]), Ok(i32s(&[102]))),
+            (3, i32s(&[-7, 2]), Ok(i32s(&[-3]))),
+            (3, i32s(&[1, 0]), Err(Trap::IntegerDivideByZero)),
+            (3, i32s(&[std::i32::MIN, -1]), Err(Trap::IntegerOverflow)),
+            (4, vec![], Err(Trap::Unreachable)),
+            (
+                5,
+                vec![Value::I32(8), Value::I64(0x1234_8765)],
+                Ok(i32s(&[-30875])),
+            ),
+            (
+                5,
+                vec![Value::I32(65_528), Value::I64(0)],
+                Err(Trap::MemoryOutOfBounds),
+            ),
+            // the effective address does not wrap around
+            (
+                5,
+                vec![Value::I32(-4), Value::I64(0)],
+                Err(Trap::MemoryOutOfBounds),
+            ),
+            // the second grow is beyond the maximum of the memory
+            (6, i32s(&[1]), Ok(i32s(&[2]))),
+            (6, i32s(&[1]), Ok(i32s(&[2]))),
+            (5, vec![Value::I32(65_528), Value::I64(0)], Ok(i32s(&[0]))),
+            (7, vec![], Ok(i32s(&[11]))),
+            (7, vec![], Ok(i32s(&[12]))),
+            (8, vec![], Err(Trap::CallStackExhausted)),
+            (
+                9,
+                vec![Value::F32(2.5), Value::F32(3.0)],
+                Ok(vec![Value::F32(2.0)]),
+            ),
+            (10, vec![Value::F64(-0.9)], Ok(i32s(&[0]))),
+            (
+                10,
+                vec![Value::F64(4_294_967_296.0)],
+                Err(Trap::IntegerOverflow),
+            ),
+            (
+                10,
+                vec![Value::F64(std::f64::NAN)],
+                Err(Trap::InvalidConversionToInteger),
+            ),
+            (1, vec![Value::I64(1)], Err(Trap::ArgumentTypeMismatch)),
+        ];
+        for (index, args, expected) in cases {
+            assert_eq!(instance.call(index, &args), expected, "function {}", index);
+        }
+
+        // nearest keeps the sign of zero and min orders -0 below +0
+        let zero = instance.call(9, &[Value::F32(-0.4), Value::F32(0.0)]);
+        match zero.as_ref().map(|values| values[0].clone()) {
+            Ok(Value::F32(value)) => assert!(value == 0.0 && value.is_sign_negative()),
+            value => panic!("[test_interpreter] {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_memory() {
+        let source = r#"(module
+  (memory 1 3)
+  (data (i32.const 0) "\01\02\03\04")
+  (data (i32.const 65531) "hello")
+  (func (param i32) (result i32)
+    (i32.load (local.get 0)))
+  (func (param i32) (result i32)
+    (i32.load offset=0xffffffff (local.get 0)))
+  (func (param i32) (result i32)
+    (memory.grow (local.get 0))))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        let instance = Instance::new(module).unwrap();
+
+        assert_eq!(
+            instance.call(0, &[Value::I32(0)]),
+            Ok(vec![Value::I32(0x0403_0201)])
+        );
+        assert_eq!(
+            instance.call(0, &[Value::I32(65_532)]),
+            Ok(vec![Value::I32(0x6f6c_6c65)])
+        );
+        assert_eq!(
+            instance.call(0, &[Value::I32(65_533)]),
+            Err(Trap::MemoryOutOfBounds)
+        );
+        assert_eq!(
+            instance.call(1, &[Value::I32(1)]),
+            Err(Trap::MemoryOutOfBounds)
+        );
+        assert_eq!(instance.call(2, &[Value::I32(2)]), Ok(vec![Value::I32(1)]));
+        assert_eq!(instance.call(2, &[Value::I32(1)]), Ok(vec![Value::I32(-1)]));
+
+        let memory = instance.memory().unwrap().borrow();
+        assert_eq!(memory.size(), 3);
+        assert_eq!(memory.max(), Some(3));
+        assert_eq!(&memory.data()[PAGE_SIZE - 5..PAGE_SIZE], b"hello");
+
+        let mut memory = Memory::new(&ResizableLimit {
+            initial: 0,
+            max: None,
+        });
+        assert_eq!(memory.grow(65_536), Some(0));
+        assert_eq!(memory.grow(1), None);
+        memory.write(0x1_0000, &[1, 2]).unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(
+            memory.read(0xffff_fffe, &mut buf),
+            Err(Trap::MemoryOutOfBounds)
+        );
+        memory.read(0xffff, &mut buf).unwrap();
+        assert_eq!(buf, [0, 1, 2, 0]);
+
+        let source = r#"(module
+  (memory 1)
+  (data (i32.const 0) "a")
+  (data (i32.const 65536) "b"))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        match Instance::new(module) {
+            Err(WasmError::Trap(trap)) => assert_eq!(trap, Trap::MemoryOutOfBounds),
+            result => panic!("[test_memory] {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn test_linker() {
+        let memory = Rc::new(RefCell::new(Memory::new(&ResizableLimit {
+            initial: 1,
+            max: Some(2),
+        })));
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut linker = Linker::new();
+        let logged = Rc::clone(&log);
+        linker
+            .func(
+                "env",
+                "log",
+                FuncType::new(vec![Type::I32], vec![]),
+                move |args| {
+                    logged.borrow_mut().push(args[0].clone());
+                    Ok(vec![])
+                },
+            )
+            .func(
+                "env",
+                "fail",
+                FuncType::new(vec![], vec![Type::I32]),
+                |_| Err(Trap::Host("failed".to_string())),
+            )
+            .define("env", "memory", Extern::Memory(Rc::clone(&memory)))
+            .define(
+                "env",
+                "base",
+                Extern::Global(Rc::new(RefCell::new(Global::new(
+                    Value::I32(100),
+                    Mutability::Const,
+                )))),
+            );
+
+        let source = r#"(module
+  (import "env" "log" (func $log (param i32)))
+  (import "env" "memory" (memory 1))
+  (import "env" "base" (global $base i32))
+  (func (export "store") (param i32)
+    (i32.store (i32.const 0) (i32.add (global.get $base) (local.get 0)))
+    (call $log (i32.load (i32.const 0)))))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        let first = linker.instantiate(module).unwrap();
+        assert_eq!(first.call(1, &[Value::I32(5)]), Ok(vec![]));
+        assert_eq!(*log.borrow(), vec![Value::I32(105)]);
+        assert_eq!(memory.borrow().data()[0], 105);
+
+        // the exports of an instance can be imported by others
+        linker.instance("first", &first);
+        let source = r#"(module
+  (import "first" "store" (func $store (param i32)))
+  (import "env" "fail" (func $fail (result i32)))
+  (func (export "twice") (param i32)
+    (call $store (local.get 0))
+    (call $store (i32.mul (local.get 0) (i32.const 2))))
+  (func (export "fail") (result i32)
+    (call $fail)))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        let second = linker.instantiate(module).unwrap();
+        assert_eq!(second.call(2, &[Value::I32(1)]), Ok(vec![]));
+        assert_eq!(
+            *log.borrow(),
+            vec![Value::I32(105), Value::I32(101), Value::I32(102)]
+        );
+        assert_eq!(second.call(3, &[]), Err(Trap::Host("failed".to_string())));
+
+        let source = r#"(module
+  (import "env" "log" (func (param i64)))
+  (import "env" "missing" (func))
+  (import "other" "missing" (global i32)))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        match linker.instantiate(module) {
+            Err(WasmError::UnresolvedImports {
+                unknown,
+                incompatible,
+            }) => {
+                assert_eq!(unknown.len(), 2);
+                assert_eq!(incompatible, vec![("env".to_string(), "log".to_string())]);
+            }
+            result => panic!("[test_linker] {:?}", result.err()),
+        }
+
+        let source = r#"(module
+  (import "env" "missing" (func))
+  (import "env" "memory" (memory 1 2))
+  (import "other" "missing" (global i32)))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        let err = linker.instantiate(module).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "[wasm]: unknown import env.missing, other.missing"
+        );
+
+        // an imported memory may grow no further than the import allows
+        let source = r#"(module (import "env" "memory" (memory 1 1)))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        let err = linker.instantiate(module).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "[wasm]: incompatible import type env.memory"
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let source = r#"(module
+  (type $binary (func (param i32 i32) (result i32)))
+  (table (export "table") 4 6 funcref)
+  (elem (i32.const 1) $add $sub $nullary)
+  (func $add (type $binary) (i32.add (local.get 0) (local.get 1)))
+  (func $sub (type $binary) (i32.sub (local.get 0) (local.get 1)))
+  (func $nullary (result i32) (i32.const 7))
+  (func (export "apply") (param i32 i32 i32) (result i32)
+    (call_indirect (type $binary) (local.get 1) (local.get 2) (local.get 0))))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        let instance = Instance::new(module).unwrap();
+        let apply =
+            |index: i32| instance.call(3, &[Value::I32(index), Value::I32(7), Value::I32(2)]);
+
+        assert_eq!(apply(1), Ok(vec![Value::I32(9)]));
+        assert_eq!(apply(2), Ok(vec![Value::I32(5)]));
+        assert_eq!(apply(0), Err(Trap::UninitializedElement));
+        assert_eq!(apply(3), Err(Trap::IndirectCallTypeMismatch));
+        assert_eq!(apply(4), Err(Trap::UndefinedElement));
+        assert_eq!(apply(-1), Err(Trap::UndefinedElement));
+
+        // elements may be set and grown by the embedder, host functions included
+        let table = Rc::clone(instance.table().unwrap());
+        let ty = FuncType::new(vec![Type::I32, Type::I32], vec![Type::I32]);
+        let mul = Func::new(ty, |args| match args {
+            [Value::I32(a), Value::I32(b)] => Ok(vec![Value::I32(a * b)]),
+            _ => unreachable!(),
+        });
+        assert_eq!(table.borrow_mut().grow(2), Some(4));
+        assert_eq!(table.borrow_mut().grow(1), None);
+        table.borrow_mut().set(5, Some(mul)).unwrap();
+        assert_eq!(apply(5), Ok(vec![Value::I32(14)]));
+        assert_eq!(table.borrow_mut().set(6, None), Err(Trap::UndefinedElement));
+
+        // segments are written in order up to the first that is out of bounds
+        let mut linker = Linker::new();
+        let memory = Rc::new(RefCell::new(Memory::new(&ResizableLimit {
+            initial: 1,
+            max: None,
+        })));
+        linker
+            .define("env", "table", Extern::Table(Rc::clone(&table)))
+            .define("env", "memory", Extern::Memory(Rc::clone(&memory)));
+        let source = r#"(module
+  (import "env" "table" (table 6 funcref))
+  (import "env" "memory" (memory 1))
+  (elem (i32.const 0) $f)
+  (elem (i32.const 5) $f $f)
+  (data (i32.const 0) "a")
+  (func $f))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        match linker.instantiate(module) {
+            Err(WasmError::Trap(trap)) => assert_eq!(trap, Trap::TableOutOfBounds),
+            result => panic!("[test_table] {:?}", result.err()),
+        }
+        assert!(table.borrow().get(0).unwrap().is_some());
+        assert!(table.borrow().get(5).unwrap().is_some());
+        assert_eq!(memory.borrow().data()[0], 0);
+        let source = r#"(module
+  (import "env" "memory" (memory 1))
+  (data (i32.const 0) "a")
+  (data (i32.const 65535) "bc"))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        match linker.instantiate(module) {
+            Err(WasmError::Trap(trap)) => assert_eq!(trap, Trap::MemoryOutOfBounds),
+            result => panic!("[test_table] {:?}", result.err()),
+        }
+        assert_eq!(memory.borrow().data()[0], b'a');
+        assert_eq!(memory.borrow().data()[65535], 0);
+    }
+
+    #[test]
+    fn test_host_table() {
+        let mut table = Table::new(&ResizableLimit {
+            initial: 1,
+            max: None,
+        });
+        assert_eq!(table.size(), 1);
+        assert_eq!(table.max(), None);
+        // without a maximum, tables grow up to an implementation limit
+        assert_eq!(table.grow(std::u32::MAX - 1), None);
+        assert_eq!(table.grow(1), Some(1));
+
+        // tables only hold references
+        let limit = ResizableLimit {
+            initial: 1,
+            max: None,
+        };
+        let mut table = Table::with_type(Type::AnyRef, &limit).unwrap();
+        assert_eq!(table.grow(1), Some(1));
+        assert_eq!(table.get_ref(1), Ok(Value::ExternRef(None)));
+        let err = Table::with_type(Type::I32, &limit).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "[wasm]: type mismatch: expected a reference, found i32"
+        );
+    }
+
+    #[test]
+    fn test_globals() {
+        let offset = Rc::new(RefCell::new(Global::new(Value::I32(2), Mutability::Const)));
+        let counter = Rc::new(RefCell::new(Global::new(Value::I64(0), Mutability::Var)));
+        let mut linker = Linker::new();
+        linker
+            .define("env", "offset", Extern::Global(Rc::clone(&offset)))
+            .define("env", "counter", Extern::Global(Rc::clone(&counter)));
+
+        let source = r#"(module
+  (import "env" "offset" (global $offset i32))
+  (import "env" "counter" (global $counter (mut i64)))
+  (global $copy i32 (global.get $offset))
+  (global $half f64 (f64.const 0.5))
+  (memory 1)
+  (data (global.get $offset) "\2a")
+  (func (export "count") (result i64)
+    (global.set $counter (i64.add (global.get $counter) (i64.const 1)))
+    (global.get $counter))
+  (func (export "copy") (result i32)
+    (i32.add (global.get $copy) (i32.load8_u (i32.const 2)))))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        let instance = linker.instantiate(module).unwrap();
+
+        assert_eq!(instance.call(0, &[]), Ok(vec![Value::I64(1)]));
+        assert_eq!(counter.borrow().get(), Value::I64(1));
+        counter.borrow_mut().set(Value::I64(41)).unwrap();
+        assert_eq!(instance.call(0, &[]), Ok(vec![Value::I64(42)]));
+        assert_eq!(instance.call(1, &[]), Ok(vec![Value::I32(44)]));
+
+        let half = instance.global(3).unwrap().borrow();
+        assert_eq!(half.get(), Value::F64(0.5));
+        assert_eq!(half.mutability(), Mutability::Const);
+
+        let result = offset.borrow_mut().set(Value::I32(3));
+        match result {
+            Err(WasmError::SetConstGlobal) => {}
+            result => panic!("[test_globals] {:?}", result),
+        }
+        let result = counter.borrow_mut().set(Value::I32(3));
+        match result {
+            Err(WasmError::TypeMismatch { expected, actual }) => {
+                assert_eq!((expected, actual), (Some(Type::I64), Some(Type::I32)))
+            }
+            result => panic!("[test_globals] {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_start() {
+        let instance = Instance::new(read_module("start.3.wasm")).unwrap();
+        // the start function runs after the data segments are written
+        assert_eq!(instance.call(1, &[]), Ok(vec![Value::I32(i32::from(b'D'))]));
+
+        let printed = Rc::new(RefCell::new(vec![]));
+        let mut linker = Linker::new();
+        let print_i32 = Rc::clone(&printed);
+        let print = Rc::clone(&printed);
+        linker
+            .func(
+                "spectest",
+                "print_i32",
+                FuncType::new(vec![Type::I32], vec![]),
+                move |args| {
+                    print_i32.borrow_mut().push(args.to_vec());
+                    Ok(vec![])
+                },
+            )
+            .func(
+                "spectest",
+                "print",
+                FuncType::new(vec![], vec![]),
+                move |_| {
+                    print.borrow_mut().push(vec![]);
+                    Ok(vec![])
+                },
+            );
+        for name in &["start.5.wasm", "start.6.wasm", "start.7.wasm"] {
+            linker.instantiate(read_module(name)).unwrap();
+        }
+        assert_eq!(
+            *printed.borrow(),
+            vec![vec![Value::I32(1)], vec![Value::I32(2)], vec![]]
+        );
+
+        match Instance::new(read_module("start.8.wasm")) {
+            Err(WasmError::Trap(Trap::Unreachable)) => {}
+            result => panic!("[test_start] {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn test_invoke() {
+        let source = r#"(module
+  (memory (export "memory") 1)
+  (func (export "mix") (param i32 i64) (result f64)
+    (f64.add (f64.convert_i32_s (local.get 0)) (f64.convert_i64_u (local.get 1))))
+  (func (export "answer") (result i32)
+    (i32.const 42))
+  (func (export "nop")))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        let instance = Instance::new(module).unwrap();
+
+        assert_eq!(
+            instance.invoke("mix", &[Value::I32(-1), Value::I64(3)]),
+            Ok(vec![Value::F64(2.0)])
+        );
+        assert_eq!(instance.invoke("nop", &[]), Ok(vec![]));
+        assert_eq!(
+            instance.invoke("mix", &[Value::I64(3), Value::I32(-1)]),
+            Err(Trap::ArgumentTypeMismatch)
+        );
+        assert_eq!(
+            instance.invoke("mix", &[Value::I32(1)]),
+            Err(Trap::ArgumentTypeMismatch)
+        );
+        assert_eq!(
+            instance.invoke("missing", &[]),
+            Err(Trap::UnknownExport("missing".to_string()))
+        );
+        assert_eq!(
+            instance.invoke("memory", &[]),
+            Err(Trap::UnknownExport("memory".to_string()))
+        );
+
+        let mix = instance.get_typed_func::<(i32, i64), f64>("mix").unwrap();
+        assert_eq!(mix.call((2, -1)), Ok(18_446_744_073_709_551_617.0));
+        let answer = instance.get_typed_func::<(), i32>("answer").unwrap();
+        assert_eq!(answer.call(()), Ok(42));
+        let nop = instance.get_typed_func::<(), ()>("nop").unwrap();
+        assert_eq!(nop.call(()), Ok(()));
+
+        assert_eq!(
+            instance.get_typed_func::<i32, f64>("mix").err(),
+            Some(Trap::ArgumentTypeMismatch)
+        );
+        assert_eq!(
+            instance.get_typed_func::<(), (i32, i32)>("answer").err(),
+            Some(Trap::ResultTypeMismatch)
+        );
+
+        // exported functions keep their instance alive, also once registered
+        let instance = || Instance::new(Module::from_sections(text::parse(source).unwrap()));
+        let answer = instance().unwrap().get_typed_func::<(), i32>("answer");
+        assert_eq!(answer.unwrap().call(()), Ok(42));
+        let mut linker = Linker::new();
+        linker.instance("env", &instance().unwrap());
+        match linker.get("env", "answer") {
+            Some(Extern::Func(func)) => assert_eq!(func.call(&[]), Ok(vec![Value::I32(42)])),
+            item => panic!("[test_invoke] {:?}", item),
+        }
+
+        // and so do functions in their tables
+        let source = r#"(module
+  (table (export "table") funcref (elem $f))
+  (func $f (result i32) i32.const 42))"#;
+        let module = Module::from_sections(text::parse(source).unwrap());
+        let table = match Instance::new(module).unwrap().export("table") {
+            Some(Extern::Table(table)) => table,
+            item => panic!("[test_invoke] {:?}", item),
+        };
+        let func = table.borrow().get(0).unwrap().unwrap();
+        assert_eq!(func.call(&[]), Ok(vec![Value::I32(42)]));
+    }
+
+    fn read_sections(data: &[u8]) -> Vec<Section> {
+        let mut r = Parser::new(data);
+        let mut sections = vec![];
+        loop {
+            match r.read() {
+                Frame::End => break,
+                Frame::Section { section, .. } => sections.push(section),
+                Frame::ParserError { err } => panic!("[read_sections] err {:?}", err),
+                _ => continue,
+            }
+        }
+        sections
+    }
+
+    fn read_module(name: &str) -> Module<'static> {
+        let data = read_file_data(&PathBuf::from("tests/wasm").join(name));
+        Module::parse(&data).unwrap().into_owned()
+    }
+
     fn read_file_data(path: &PathBuf) -> Vec<u8> {
         let mut data = Vec::new();
         let mut f = File::open(path).ok().unwrap();
         f.read_to_end(&mut data).unwrap();
         data
     }
+
+    #[test]
+    fn test_wast() {
+        let script = r#"
+(module $math
+  (global (export "g") i32 (i32.const 7))
+  (func (export "add") (param i32 i32) (result i32)
+    (i32.add (local.get 0) (local.get 1)))
+  (func (export "div") (param i32 i32) (result i32)
+    (i32.div_s (local.get 0) (local.get 1)))
+  (func (export "nan") (result f32)
+    (f32.div (f32.const 0) (f32.const 0)))
+  (func (export "neg_zero") (result f64)
+    (f64.neg (f64.const 0)))
+  (func $loop (export "loop") (call $loop)))
+(register "math" $math)
+(module
+  (import "math" "add" (func $add (param i32 i32) (result i32)))
+  (import "spectest" "global_i32" (global i32))
+  (import "spectest" "print_i32" (func (param i32)))
+  (func (export "twice") (param i32) (result i32)
+    (call $add (local.get 0) (local.get 0))))
+(invoke "twice" (i32.const 1))
+(assert_return (invoke "twice" (i32.const 21)) (i32.const 42))
+(assert_return (invoke $math "add" (i32.const -1) (i32.const 1)) (i32.const 0))
+(assert_return (get $math "g") (i32.const 7))
+(assert_return (invoke $math "nan") (f32.const nan:canonical))
+(assert_return (invoke $math "nan") (f32.const nan:arithmetic))
+(assert_return (invoke $math "neg_zero") (f64.const -0))
+(assert_trap (invoke $math "div" (i32.const 1) (i32.const 0)) "integer divide by zero")
+(assert_exhaustion (invoke $math "loop") "call stack exhausted")
+(assert_trap (module (func $start unreachable) (start $start)) "unreachable")
+(assert_invalid (module (func (result i32) (i64.const 0))) "type mismatch")
+(assert_malformed (module binary "\00asm\02\00\00\00") "unknown binary version")
+(assert_malformed (module quote "(func (i32.const))") "unexpected token")
+(assert_unlinkable (module (import "math" "missing" (func))) "unknown import")
+(assert_return (invoke $math "neg_zero") (f64.const 0))
+(assert_trap (invoke $math "add" (i32.const 1) (i32.const 2)) "unreachable")
+"#;
+        let outcomes = WastRunner::new().run(script).unwrap();
+        let failed = outcomes.iter().filter(|outcome| !outcome.passed());
+        let failed = failed.map(|outcome| outcome.line).collect::<Vec<_>>();
+        assert_eq!(outcomes.len(), 19);
+        assert_eq!(failed, vec![34, 35]);
+        assert_eq!(outcomes[0].directive, "module");
+        assert_eq!(
+            outcomes[18].to_string(),
+            "line 35: assert_trap: failed: expected trap \"unreachable\""
+        );
+
+        assert!(WastRunner::new().run("(frobnicate)").is_err());
+    }
+
+    #[test]
+    fn test_sign_extension() {
+        let script = r#"
+(module
+  (func (export "i32.extend8_s") (param i32) (result i32) (i32.extend8_s (local.get 0)))
+  (func (export "i32.extend16_s") (param i32) (result i32) (i32.extend16_s (local.get 0)))
+  (func (export "i64.extend8_s") (param i64) (result i64) (i64.extend8_s (local.get 0)))
+  (func (export "i64.extend16_s") (param i64) (result i64) (i64.extend16_s (local.get 0)))
+  (func (export "i64.extend32_s") (param i64) (result i64) (i64.extend32_s (local.get 0))))
+(assert_return (invoke "i32.extend8_s" (i32.const 0x7f)) (i32.const 127))
+(assert_return (invoke "i32.extend8_s" (i32.const 0x180)) (i32.const -128))
+(assert_return (invoke "i32.extend16_s" (i32.const 0x18000)) (i32.const -32768))
+(assert_return (invoke "i64.extend8_s" (i64.const 0xff)) (i64.const -1))
+(assert_return (invoke "i64.extend16_s" (i64.const 0x7fff)) (i64.const 32767))
+(assert_return (invoke "i64.extend32_s" (i64.const 0x80000000)) (i64.const -2147483648))
+(assert_invalid (module (func (result i64) (i64.extend8_s (i32.const 0)))) "type mismatch")
+(module binary
+  "\00asm\01\00\00\00"
+  "\01\06\01\60\01\7f\01\7f"
+  "\03\02\01\00"
+  "\07\07\01\03ext\00\00"
+  "\0a\07\01\05\00\20\00\c0\0b")
+(assert_return (invoke "ext" (i32.const 0x80)) (i32.const -128))
+"#;
+        let outcomes = WastRunner::new().run(script).unwrap();
+        for outcome in &outcomes {
+            assert!(outcome.passed(), "{}", outcome);
+        }
+        assert_eq!(outcomes.len(), 10);
+    }
+
+    #[test]
+    fn test_saturating_truncation() {
+        let script = r#"
+(module
+  (func (export "i32.trunc_sat_f32_s") (param f32) (result i32) (i32.trunc_sat_f32_s (local.get 0)))
+  (func (export "i32.trunc_sat_f64_u") (param f64) (result i32) (i32.trunc_sat_f64_u (local.get 0)))
+  (func (export "i64.trunc_sat_f64_s") (param f64) (result i64) (i64.trunc_sat_f64_s (local.get 0)))
+  (func (export "i64.trunc_sat_f32_u") (param f32) (result i64) (i64.trunc_sat_f32_u (local.get 0))))
+(assert_return (invoke "i32.trunc_sat_f32_s" (f32.const -1.9)) (i32.const -1))
+(assert_return (invoke "i32.trunc_sat_f32_s" (f32.const 3e9)) (i32.const 2147483647))
+(assert_return (invoke "i32.trunc_sat_f32_s" (f32.const -inf)) (i32.const -2147483648))
+(assert_return (invoke "i32.trunc_sat_f32_s" (f32.const nan)) (i32.const 0))
+(assert_return (invoke "i32.trunc_sat_f64_u" (f64.const -5)) (i32.const 0))
+(assert_return (invoke "i32.trunc_sat_f64_u" (f64.const 1e10)) (i32.const -1))
+(assert_return (invoke "i64.trunc_sat_f64_s" (f64.const 1e19)) (i64.const 9223372036854775807))
+(assert_return (invoke "i64.trunc_sat_f64_s" (f64.const -1e19)) (i64.const -9223372036854775808))
+(assert_return (invoke "i64.trunc_sat_f32_u" (f32.const inf)) (i64.const -1))
+(assert_return (invoke "i64.trunc_sat_f32_u" (f32.const 4294967296)) (i64.const 4294967296))
+(module binary
+  "\00asm\01\00\00\00"
+  "\01\06\01\60\01\7d\01\7f"
+  "\03\02\01\00"
+  "\07\07\01\03sat\00\00"
+  "\0a\08\01\06\00\20\00\fc\00\0b")
+(assert_return (invoke "sat" (f32.const 1e10)) (i32.const 2147483647))
+(assert_malformed (module binary
+  "\00asm\01\00\00\00"
+  "\01\04\01\60\00\00"
+  "\03\02\01\00"
+  "\0a\07\01\05\00\fc\08\0b\0b") "invalid operator code 252 8")
+"#;
+        let outcomes = WastRunner::new().run(script).unwrap();
+        for outcome in &outcomes {
+            assert!(outcome.passed(), "{}", outcome);
+        }
+        assert_eq!(outcomes.len(), 14);
+    }
+
+    #[test]
+    fn test_bulk_memory() {
+        let source = r#"
+(module
+  (memory (export "mem") 1)
+  (table 4 funcref)
+  (func $zero (result i32) (i32.const 0))
+  (func $one (result i32) (i32.const 1))
+  (data $hello "hello")
+  (data (i32.const 16) "abc")
+  (elem $fs func $zero $one)
+  (func (export "init") (param i32 i32 i32)
+    (memory.init $hello (local.get 0) (local.get 1) (local.get 2)))
+  (func (export "drop") (data.drop $hello))
+  (func (export "copy") (param i32 i32 i32)
+    (memory.copy (local.get 0) (local.get 1) (local.get 2)))
+  (func (export "fill") (param i32 i32 i32)
+    (memory.fill (local.get 0) (local.get 1) (local.get 2)))
+  (func (export "load8") (param i32) (result i32) (i32.load8_u (local.get 0)))
+  (func (export "table.init") (param i32 i32 i32)
+    (table.init $fs (local.get 0) (local.get 1) (local.get 2)))
+  (func (export "table.copy") (param i32 i32 i32)
+    (table.copy (local.get 0) (local.get 1) (local.get 2)))
+  (func (export "elem.drop") (elem.drop $fs))
+  (func (export "call") (param i32) (result i32) (call_indirect (result i32) (local.get 0))))
+"#;
+        let script = format!(
+            "{}{}",
+            source,
+            r#"
+(invoke "init" (i32.const 0) (i32.const 1) (i32.const 4))
+(assert_return (invoke "load8" (i32.const 0)) (i32.const 101))
+(assert_return (invoke "load8" (i32.const 3)) (i32.const 111))
+(assert_trap (invoke "init" (i32.const 0) (i32.const 2) (i32.const 4)) "out of bounds memory access")
+(invoke "copy" (i32.const 17) (i32.const 16) (i32.const 3))
+(assert_return (invoke "load8" (i32.const 18)) (i32.const 98))
+(invoke "fill" (i32.const 100) (i32.const 42) (i32.const 3))
+(assert_return (invoke "load8" (i32.const 102)) (i32.const 42))
+(assert_trap (invoke "fill" (i32.const 65535) (i32.const 0) (i32.const 2)) "out of bounds memory access")
+(invoke "drop")
+(assert_trap (invoke "init" (i32.const 0) (i32.const 0) (i32.const 1)) "out of bounds memory access")
+(invoke "init" (i32.const 0) (i32.const 0) (i32.const 0))
+(assert_trap (invoke "call" (i32.const 2)) "uninitialized element")
+(invoke "table.init" (i32.const 1) (i32.const 0) (i32.const 2))
+(assert_return (invoke "call" (i32.const 2)) (i32.const 1))
+(invoke "table.copy" (i32.const 0) (i32.const 1) (i32.const 2))
+(assert_return (invoke "call" (i32.const 0)) (i32.const 0))
+(assert_trap (invoke "table.copy" (i32.const 3) (i32.const 0) (i32.const 2)) "out of bounds table access")
+(invoke "elem.drop")
+(assert_trap (invoke "table.init" (i32.const 0) (i32.const 0) (i32.const 1)) "out of bounds table access")
+(assert_invalid (module (func (data.drop 0))) "unknown data segment 0")
+(assert_invalid (module (table 1 funcref) (func (elem.drop 0))) "unknown elem segment 0")
+"#
+        );
+        let outcomes = WastRunner::new().run(&script).unwrap();
+        for outcome in &outcomes {
+            assert!(outcome.passed(), "{}", outcome);
+        }
+        assert_eq!(outcomes.len(), 23);
+
+        let data = Module::from_sections(text::parse(source).unwrap()).encode();
+        let module = Module::parse_strict(&data).unwrap();
+        assert_eq!(module.data_count, Some(2));
+        assert_eq!(module.data[0].mode, SegmentMode::Passive);
+        assert_eq!(module.data[1].mode, SegmentMode::Active);
+        assert_eq!(module.elements[0].mode, SegmentMode::Passive);
+        assert_eq!(module.encode(), data);
+        let text = print(&module);
+        assert!(text.contains("(data (;0;) \"hello\")"));
+        assert!(text.contains("(elem (;0;) func "));
+        assert!(text.contains("memory.init 0"));
+
+        // the data count section comes before the code section
+        let mut data = b"\0asm\x01\0\0\0".to_vec();
+        data.extend_from_slice(&[0x0C, 0x01, 0x01]);
+        let err = Module::parse_strict(&data).unwrap_err();
+        assert_eq!(
+            err.inner().to_string(),
+            "[wasm]: data count and data section have inconsistent lengths, 1 != 0"
+        );
+        assert!(Module::parse(&data).unwrap().validate().is_err());
+        data.extend_from_slice(&[0x0A, 0x01, 0x00, 0x0C, 0x01, 0x01]);
+        let err = Module::parse_strict(&data).unwrap_err();
+        assert_eq!(err.inner().to_string(), "[wasm]: section 12 out of order");
+    }
+
+    #[test]
+    fn test_reference_types() {
+        let source = r#"
+(module
+  (table $xs 2 externref)
+  (table $fs 2 funcref)
+  (global $g funcref (ref.func $one))
+  (func $zero (result i32) (i32.const 0))
+  (func $one (result i32) (i32.const 1))
+  (elem (table $fs) (i32.const 0) funcref (ref.func $zero) (ref.null func))
+  (elem declare func $zero)
+  (func (export "get") (param i32) (result externref) (table.get $xs (local.get 0)))
+  (func (export "set") (param i32 externref) (table.set $xs (local.get 0) (local.get 1)))
+  (func (export "size") (result i32) (table.size $xs))
+  (func (export "grow") (param externref i32) (result i32)
+    (table.grow $xs (local.get 0) (local.get 1)))
+  (func (export "fill") (param i32 externref i32)
+    (table.fill $xs (local.get 0) (local.get 1) (local.get 2)))
+  (func (export "is_null") (param externref) (result i32) (ref.is_null (local.get 0)))
+  (func (export "select") (param i32) (result externref)
+    (select (result externref) (ref.null extern) (table.get $xs (i32.const 0)) (local.get 0)))
+  (func (export "call") (param i32) (result i32) (call_indirect $fs (result i32) (local.get 0)))
+  (func (export "set_one") (table.set $fs (i32.const 1) (global.get $g)))
+  (func (export "funcref") (result funcref) (ref.func $zero)))
+"#;
+        let script = format!(
+            "{}{}",
+            source,
+            r#"
+(assert_return (invoke "size") (i32.const 2))
+(assert_return (invoke "get" (i32.const 0)) (ref.null extern))
+(invoke "set" (i32.const 1) (ref.extern 7))
+(assert_return (invoke "get" (i32.const 1)) (ref.extern 7))
+(assert_trap (invoke "get" (i32.const 2)) "out of bounds table access")
+(assert_return (invoke "grow" (ref.extern 3) (i32.const 2)) (i32.const 2))
+(assert_return (invoke "get" (i32.const 3)) (ref.extern 3))
+(invoke "fill" (i32.const 0) (ref.extern 5) (i32.const 2))
+(assert_return (invoke "select" (i32.const 0)) (ref.extern 5))
+(assert_return (invoke "select" (i32.const 1)) (ref.null extern))
+(assert_return (invoke "is_null" (ref.null extern)) (i32.const 1))
+(assert_return (invoke "is_null" (ref.extern 1)) (i32.const 0))
+(assert_return (invoke "call" (i32.const 0)) (i32.const 0))
+(assert_trap (invoke "call" (i32.const 1)) "uninitialized element")
+(invoke "set_one")
+(assert_return (invoke "call" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "funcref") (ref.func))
+(assert_invalid (module (func $f (drop (ref.func $f)))) "undeclared function reference 0")
+(assert_invalid
+  (module (type (func)) (table 1 externref) (func (call_indirect (type 0) (i32.const 0))))
+  "type mismatch")
+(assert_invalid
+  (module (func (param externref) (drop (select (local.get 0) (local.get 0) (i32.const 1)))))
+  "select of externref needs a type")
+(module
+  (table 0 funcref)
+  (func (export "grow") (param i32) (result i32) (table.grow (ref.null func) (local.get 0))))
+(assert_return (invoke "grow" (i32.const 0xFFFFFFF0)) (i32.const -1))
+(assert_return (invoke "grow" (i32.const 1)) (i32.const 0))
+"#
+        );
+        let outcomes = WastRunner::new().run(&script).unwrap();
+        for outcome in &outcomes {
+            assert!(outcome.passed(), "{}", outcome);
+        }
+        assert_eq!(outcomes.len(), 24);
+
+        let data = Module::from_sections(text::parse(source).unwrap()).encode();
+        let module = Module::parse_strict(&data).unwrap();
+        assert_eq!(module.tables[0].elem_type, Type::AnyRef);
+        assert_eq!(module.tables[1].elem_type, Type::AnyFunc);
+        assert_eq!(module.elements[0].table_index, 1);
+        match &module.elements[0].elems {
+            ElemItems::Expressions(exprs) => assert_eq!(exprs.len(), 2),
+            elems => panic!("unexpected {:?}", elems),
+        }
+        assert_eq!(module.elements[1].mode, SegmentMode::Declared);
+        assert_eq!(module.encode(), data);
+        let text = print(&module);
+        assert!(text.contains("(table (;0;) 2 externref)"));
+        assert!(text.contains("(elem (;1;) declare func "));
+        assert!(text.contains("call_indirect 1 (type "));
+        assert!(text.contains("select (result externref)"));
+        let module = Module::from_sections(text::parse(&text).unwrap());
+        assert_eq!(module.encode(), data);
+
+        let instance = Instance::new(module).unwrap();
+        assert_eq!(instance.tables().len(), 2);
+        let table = instance.tables()[1].borrow();
+        assert_eq!(table.elem_type(), Type::AnyFunc);
+        assert!(table.get(0).unwrap().is_some());
+        assert_eq!(table.get_ref(1), Ok(Value::FuncRef(None)));
+    }
 }